@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::{OutputStream, OutputStreamHandle, Source};
+
+use crate::hardware::peripheral::speaker::SAMPLE_RATE;
+
+// Maximo de muestras en cola (~0.25 s) para que el audio no se retrase
+const MAX_QUEUED: usize = SAMPLE_RATE as usize / 4;
+
+// Manda al dispositivo de audio las muestras del altavoz
+pub struct AudioOutput {
+    _stream: OutputStream,
+    _handle: OutputStreamHandle,
+    queue: Arc<Mutex<VecDeque<i16>>>,
+}
+
+impl AudioOutput {
+    // None si no hay dispositivo de audio
+    pub fn open() -> Option<Self> {
+        let (stream, handle) = OutputStream::try_default().ok()?;
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        handle.play_raw(SpeakerSource { queue: queue.clone() }.convert_samples()).ok()?;
+
+        Some(AudioOutput {
+            _stream: stream,
+            _handle: handle,
+            queue,
+        })
+    }
+
+    pub fn push(&self, samples: &[i16]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+
+        let len = queue.len();
+        if len > MAX_QUEUED {
+            queue.drain(..len - MAX_QUEUED);
+        }
+    }
+}
+
+struct SpeakerSource {
+    queue: Arc<Mutex<VecDeque<i16>>>,
+}
+
+impl Iterator for SpeakerSource {
+    type Item = i16;
+
+    // Si no hay muestras suena silencio
+    fn next(&mut self) -> Option<i16> {
+        Some(self.queue.lock().unwrap().pop_front().unwrap_or(0))
+    }
+}
+
+impl Source for SpeakerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,40 @@
+use ibm_5150::hardware::cpu_8088::dissasemble::listing;
+
+// Desensambla una imagen de ROM entera a un fichero de texto
+// listing imagen.bin [--address FE000] [--output imagen.lst]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let option = |name: &str| args.windows(2).find(|w| w[0] == name).map(|w| w[1].clone());
+
+    let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: listing IMAGE [--address HEX] [--output FILE]");
+        std::process::exit(1);
+    };
+
+    // Direccion lineal donde se carga la imagen, por defecto la de la BIOS
+    let address = match option("--address").map(|address| u32::from_str_radix(&address, 16)) {
+        Some(Ok(address)) if address <= 0xFFFFF => address,
+        Some(_) => {
+            eprintln!("invalid address");
+            std::process::exit(1);
+        },
+        None => 0xFE000,
+    };
+    let output = option("--output").unwrap_or_else(|| format!("{}.lst", path));
+
+    let image = match std::fs::read(path) {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        },
+    };
+
+    // Segmento alineado a 64K para que los offsets sean los que usa el codigo de la ROM
+    let cs = ((address >> 4) & 0xF000) as u16;
+    let ip = (address & 0xFFFF) as u16;
+    if let Err(err) = std::fs::write(&output, listing(&image, cs, ip) + "\n") {
+        eprintln!("{}: {}", output, err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,36 @@
+use ibm_5150::*;
+use ibm_5150::util::monitor::Monitor;
+
+// Depurador en el terminal, sin ventana ni audio
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let (config, floppies) = match MachineConfig::from_args(&args) {
+        Ok(machine) => machine,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+
+    let mut sys = System::with_config(config);
+    if let Err(err) = sys.load_roms() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    for (drive, path) in floppies.iter().enumerate() {
+        if let Err(err) = sys.mount_floppy(drive, path) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let mut monitor = Monitor::new(sys);
+    if let Err(err) = monitor.run() {
+        eprintln!("{}", err);
+    }
+
+    if let Err(err) = monitor.sys.flush_floppies() {
+        eprintln!("{}", err);
+    }
+}
@@ -0,0 +1,63 @@
+use ibm_5150::*;
+
+// Repite sin ventana una grabacion hecha con --record y ensena como queda la pantalla
+// replay grabacion [--floppy imagen.img] [--cycles N] [--snapshot estado]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: replay RECORDING [--floppy IMAGE] [--cycles N] [--snapshot FILE]");
+        std::process::exit(1);
+    };
+
+    let log = match InputLog::load_file(path) {
+        Ok(log) => log,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        },
+    };
+
+    // Por defecto hasta un segundo despues de la ultima tecla
+    let cycles = match args.windows(2).find(|w| w[0] == "--cycles").map(|w| w[1].parse::<u64>()) {
+        Some(Ok(cycles)) => cycles,
+        Some(Err(err)) => {
+            eprintln!("--cycles: {}", err);
+            std::process::exit(1);
+        },
+        None => log.end() + CPU_CLOCK,
+    };
+
+    let events = log.events.len();
+    let mut pc = match Headless::with_config(log.config.clone()) {
+        Ok(pc) => pc,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+    // Los disquetes tienen que ser los mismos que al grabar, en el mismo orden
+    for (drive, image) in args.windows(2).filter(|w| w[0] == "--floppy").map(|w| &w[1]).enumerate() {
+        if let Err(err) = pc.sys.mount_floppy(drive, image) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+    if let Err(err) = pc.sys.start_replay(log) {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    }
+
+    while pc.sys.total_cycles < cycles {
+        pc.run_cycles((cycles - pc.sys.total_cycles).min(FRAME_CYCLES as u64) as u32);
+    }
+
+    println!("{}", pc.screen_text());
+    println!("{} keys replayed, {} cycles", events, pc.sys.total_cycles);
+
+    if let Some(snapshot) = args.windows(2).find(|w| w[0] == "--snapshot").map(|w| &w[1]) {
+        if let Err(err) = pc.sys.save_snapshot(snapshot) {
+            eprintln!("{}: {}", snapshot, err);
+            std::process::exit(1);
+        }
+    }
+}
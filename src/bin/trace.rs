@@ -0,0 +1,38 @@
+use std::io::{self, BufWriter, Write};
+
+use ibm_5150::util::trace::export_text;
+
+// Pasa una traza binaria a texto, una instruccion por linea
+// trace fichero.trace [salida.txt]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(path) = args.get(1) else {
+        eprintln!("usage: trace TRACE [OUTPUT]");
+        std::process::exit(1);
+    };
+
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        },
+    };
+
+    let mut out: Box<dyn Write> = match args.get(2) {
+        Some(output) => match std::fs::File::create(output) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(err) => {
+                eprintln!("{}: {}", output, err);
+                std::process::exit(1);
+            },
+        },
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let result = export_text(&data, &mut out).and_then(|_| Ok(out.flush()?));
+    if let Err(err) = result {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    }
+}
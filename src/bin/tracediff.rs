@@ -0,0 +1,52 @@
+use ibm_5150::*;
+use ibm_5150::util::trace_diff::{Comparator, load_reference};
+
+// Arranca la maquina y la compara paso a paso con una traza de referencia
+// tracediff referencia [--bios-1981] [--cga] [--fpu] [--accurate-timing] [--floppy imagen.img] [--no-bus]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: tracediff REFERENCE [--bios-1981] [--cga] [--fpu] [--accurate-timing] [--floppy IMAGE] [--no-bus]");
+        std::process::exit(1);
+    };
+
+    let (config, floppies) = match MachineConfig::from_args(&args) {
+        Ok(machine) => machine,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+
+    let reference = match std::fs::read(path).map_err(TraceError::from).and_then(|data| load_reference(&data)) {
+        Ok(reference) => reference,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            std::process::exit(1);
+        },
+    };
+
+    let mut sys = System::with_config(config);
+    if let Err(err) = sys.load_roms() {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    for (drive, path) in floppies.iter().enumerate() {
+        if let Err(err) = sys.mount_floppy(drive, path) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    // Con --no-bus solo se comparan los registros
+    let mut comparator = Comparator::new();
+    comparator.accesses = !args.iter().any(|arg| arg == "--no-bus");
+
+    match comparator.run(&mut sys, &reference) {
+        Ok(steps) => println!("{} steps match", steps),
+        Err(divergence) => {
+            println!("{}", divergence);
+            std::process::exit(1);
+        },
+    }
+}
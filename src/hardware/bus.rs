@@ -1,14 +1,24 @@
 use crate::hardware::cpu_8088::cpu_utils::*;
 use crate::hardware::cpu_8088::instr_utils::Length;
 use crate::hardware::cpu_8088::CPU;
+use crate::hardware::cpu_8088::biu::{BusActivity, BUS_CYCLE};
+
+use std::cell::Cell;
 
 use super::cpu_8088::instr_utils::Segment;
 use super::display::ibm_mda::IbmMDA;
+use super::display::ibm_cga::IbmCGA;
 use super::peripheral::Peripheral;
-use super::peripheral::dma_8237::DMA8237;
+use super::peripheral::dma_8237::{DMA8237, MemoryRefresh};
+use super::peripheral::fdc_765::FDC765;
 use super::peripheral::pic_8259::PIC8259;
 use super::peripheral::ppi_8255::PPI8255;
+use super::peripheral::speaker::Speaker;
 use super::peripheral::timer_8253::TIM8253;
+use super::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::util::debugger::Watchpoints;
+use crate::util::trace::{AccessLog, BusAccess};
+use crate::util::rewind::Journal;
 
 #[derive(Clone)]
 pub struct Bus {
@@ -19,6 +29,21 @@ pub struct Bus {
     pub dma: DMA8237,
     pub ppi: PPI8255,
     pub mda: IbmMDA,
+    pub cga: IbmCGA,
+    pub fdc: FDC765,
+    pub speaker: Speaker,
+
+    // Actividad del bus para la temporizacion exacta
+    mem_accesses: Cell<u32>,
+    io_accesses: u32,
+    dma_cycles: u32,
+
+    // Watchpoints del depurador
+    pub watchpoints: Watchpoints,
+    // Accesos de la instruccion que se esta grabando en la traza
+    pub trace: AccessLog,
+    // Lo que hace falta para rebobinar
+    pub journal: Journal,
 }
 
 impl Bus {
@@ -32,58 +57,117 @@ impl Bus {
             dma: DMA8237::new(),
             ppi: PPI8255::new(),
             mda: IbmMDA::new(),
+            cga: IbmCGA::new(),
+            fdc: FDC765::new(),
+            speaker: Speaker::new(),
+
+            mem_accesses: Cell::new(0),
+            io_accesses: 0,
+            dma_cycles: 0,
+
+            watchpoints: Watchpoints::default(),
+            trace: AccessLog::default(),
+            journal: Journal::default(),
         }
     }
 
     pub fn update_peripherals(&mut self, cycles: u32) {
         self.update_timer();
         self.update_ppi(cycles);        
+        self.update_fdc(cycles);
+        self.cga.update(cycles);
     }
     
     fn update_timer(&mut self) {
-        self.pit.update(&mut self.pic, &mut self.ppi);
+        self.pit.update(&mut self.pic, &mut self.ppi, &mut self.speaker);
+
+        // La salida del canal 1 del PIT pide el refresco de memoria por el canal 0 del DMA
+        for _ in 0..self.pit.take_refresh_requests() {
+            self.dma.set_dreq(0, true);
+            let transferred = self.dma.service(0, &mut MemoryRefresh, &mut self.memory);
+            self.dma_cycles += transferred * BUS_CYCLE;
+        }
     }
 
     fn update_ppi(&mut self, cycles: u32) {
         self.ppi.update(&mut self.pic, cycles);
     }
 
+    fn update_fdc(&mut self, cycles: u32) {
+        self.fdc.update(cycles, &mut self.pic, &mut self.dma);
+        self.dma.journal = self.journal.enabled;
+        let transferred = self.dma.service(2, &mut self.fdc, &mut self.memory);
+        self.dma_cycles += transferred * BUS_CYCLE;
+        for (address, old) in std::mem::take(&mut self.dma.overwritten) {
+            self.journal.write(address, old);
+        }
+    }
+
+    // Devuelve y reinicia lo que ha usado el bus desde la ultima llamada
+    pub fn take_activity(&mut self) -> BusActivity {
+        BusActivity {
+            memory: self.mem_accesses.take(),
+            io: std::mem::take(&mut self.io_accesses),
+            dma: std::mem::take(&mut self.dma_cycles),
+        }
+    }
+
+    // Accesos a memoria desde fuera de la CPU (el stub de gdb), que no cuentan como ciclos de bus
+    pub fn untimed<T>(&mut self, access: impl FnOnce(&mut Bus) -> T) -> T {
+        let mem_accesses = self.mem_accesses.get();
+        let val = access(self);
+        self.mem_accesses.set(mem_accesses);
+        val
+    }
+
     pub fn port_in(&mut self, port: u16) -> u16 {
-        match port {
+        self.watchpoints.port(port, false);
+        self.io_accesses += 1;
+        let val = match port {
             0x00..=0x0F => self.dma.port_in(port),
             0x20..=0x21 => self.pic.port_in(port),
             0x40..=0x43 => self.pit.port_in(port),
             0x60..=0x63 => self.ppi.port_in(port),
-            0x80..=0x83 => {/* TODO Reg pagina DMA */ 0},
+            0x80..=0x83 => self.dma.page_in(port),
             0xA0..=0xAF => 0,
 
             0x3B0..=0x3BF => self.mda.port_in(port),
+            0x3D0..=0x3DF => self.cga.port_in(port),
+            0x3F0..=0x3F7 => self.fdc.port_in(port),
             _ => {0},
-        }
+        };
+        let val = self.journal.port_in(port, val);
+        self.trace.push(BusAccess::IoRead(port, val));
+        val
     }
 
     pub fn port_out(&mut self, cpu: &mut CPU, val: u16, port: u16) {
+        self.io_accesses += 1;
+        self.watchpoints.port(port, true);
+        self.trace.push(BusAccess::IoWrite(port, val));
         match port {
             0x00..=0x0F => self.dma.port_out(val, port),
             0x20..=0x21 => self.pic.port_out(val, port),
             0x40..=0x43 => self.pit.port_out(val, port),
             0x60..=0x63 => self.ppi.port_out(val, port),
-            0x80..=0x83 => {/* TODO Reg pagina DMA */ },
+            0x80..=0x83 => self.dma.page_out(val, port),
             0xA0..=0xAF => cpu.nmi_out(val),
 
             0x3B0..=0x3BF => self.mda.port_out(val, port),
+            0x3D0..=0x3DF => self.cga.port_out(val, port),
+            0x3F0..=0x3F7 => self.fdc.port_out(val, port),
             _ => {},
         };
     }
 
     pub fn read_8(&self, segment: u16, offset: u16) -> u8 {
         let ea = ((segment as usize) << 4) + offset as usize;
+        self.mem_accesses.set(self.mem_accesses.get() + 1);
+        self.watchpoints.memory((ea % 0x100000) as u32, false);
 
-        if ea == 0xFAC9B {
-            let _a = 0;
-        }
-
-        self.memory[ea % 0x100000]
+        let val = self.memory[ea % 0x100000];
+        self.trace.push(BusAccess::MemRead((ea % 0x100000) as u32, val));
+        val
     }
 
     pub fn read_16(&self, segment: u16, offset: u16) -> u16 {
@@ -93,20 +177,16 @@ impl Bus {
 
     pub fn write_8(&mut self, segment: u16, offset: u16, val: u8) {
         let ea = ((segment as usize) << 4) + offset as usize;
-
-        if val == 'T' as u8 {
-            let _a = 0;
-        }
-
-        if ea >= 0xB0000 && ea < 0xB1000 {
-            let _a = 0;
-        }
+        self.mem_accesses.set(self.mem_accesses.get() + 1);
+        self.watchpoints.memory((ea % 0x100000) as u32, true);
+        self.trace.push(BusAccess::MemWrite((ea % 0x100000) as u32, val));
 
         // NO ESCRIBIR EN ROM
         if ea >= 0xC0000 {
             return;
         }
 
+        self.journal.write((ea % 0x100000) as u32, self.memory[ea % 0x100000]);
         self.memory[ea % 0x100000] = val;
     }
 
@@ -139,3 +219,49 @@ impl Bus {
         }
     }
 }
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    // Los perifericos sin la RAM ni los disquetes
+    pub fn save_devices(&self, w: &mut StateWriter) {
+        self.pic.save_state(w);
+        self.pit.save_state(w);
+        self.dma.save_state(w);
+        self.ppi.save_state(w);
+        self.mda.save_state(w);
+        self.cga.save_state(w);
+        self.fdc.save_controller(w);
+        w.write_u32(self.dma_cycles);
+    }
+
+    pub fn load_devices(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pic.load_state(r)?;
+        self.pit.load_state(r)?;
+        self.dma.load_state(r)?;
+        self.ppi.load_state(r)?;
+        self.mda.load_state(r)?;
+        self.cga.load_state(r)?;
+        self.fdc.load_controller(r)?;
+        self.dma_cycles = r.read_u32()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.memory);
+        self.save_devices(w);
+        self.fdc.save_media(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_into(&mut self.memory, "memory")?;
+        self.load_devices(r)?;
+        self.fdc.load_media(r)
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use super::peripheral::ppi_8255::{TYPEMATIC_DELAY_MS, TYPEMATIC_RATE};
+use super::peripheral::floppy_disk::DiskError;
+
+// Disqueteras que maneja el FDC
+pub const MAX_FLOPPY_DRIVES: u8 = 4;
+
+// Imagen ROM que se copia a memoria en una direccion fija
+#[derive(Clone)]
+pub struct RomImage {
+    pub path: PathBuf,
+    pub address: usize,
+}
+
+impl RomImage {
+    pub fn new<P: AsRef<Path>>(path: P, address: usize) -> Self {
+        RomImage {
+            path: path.as_ref().to_path_buf(),
+            address,
+        }
+    }
+}
+
+// Tarjeta de video instalada y modo inicial que indica SW1
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DisplayType {
+    Mda,
+    Cga40,
+    Cga80,
+}
+
+#[derive(Clone)]
+pub struct MachineConfig {
+    pub bios: RomImage,
+    pub basic: Vec<RomImage>,
+    pub option_roms: Vec<RomImage>,
+    // ROM de caracteres de la tarjeta de video
+    pub font: PathBuf,
+    // Disqueteras instaladas (0-4), se indican a la BIOS con SW1
+    pub floppy_drives: u8,
+    pub display: DisplayType,
+    // Repeticion automatica del teclado: retardo en ms y repeticiones por segundo
+    pub typematic_delay: u32,
+    pub typematic_rate: u32,
+    // Coprocesador 8087 en el zocalo
+    pub fpu: bool,
+    // Temporizacion exacta del bus del 8088 con la cola de prefetch
+    pub accurate_timing: bool,
+}
+
+impl MachineConfig {
+    // BIOS del 27/10/82 con BASIC C1.10
+    pub fn ibm_5150_1982() -> Self {
+        MachineConfig {
+            bios: RomImage::new("roms/BIOS_IBM5150_27OCT82_1501476_U33.BIN", 0xFE000),
+            basic: vec![
+                RomImage::new("roms/basic_1.10/IBM_5150-C1.10-U29-5000019.bin", 0xF6000),
+                RomImage::new("roms/basic_1.10/IBM_5150-C1.10-U30-5000021.bin", 0xF8000),
+                RomImage::new("roms/basic_1.10/IBM_5150-C1.10-U31-5000022.bin", 0xFA000),
+                RomImage::new("roms/basic_1.10/IBM_5150-C1.10-U32-5000023.bin", 0xFC000),
+            ],
+            option_roms: Vec::new(),
+            font: PathBuf::from("roms/IBM_5788005_AM9264_1981_CGA_MDA_CARD.BIN"),
+            floppy_drives: 0,
+            display: DisplayType::Mda,
+            typematic_delay: TYPEMATIC_DELAY_MS,
+            typematic_rate: TYPEMATIC_RATE,
+            fpu: false,
+            accurate_timing: false,
+        }
+    }
+
+    // BIOS del 24/04/81 con BASIC C1.00
+    pub fn ibm_5150_1981() -> Self {
+        MachineConfig {
+            bios: RomImage::new("roms/bios.BIN", 0xFE000),
+            basic: vec![
+                RomImage::new("roms/basic_1.00/IBM_5150-C1.00-U29-5700019.bin", 0xF6000),
+                RomImage::new("roms/basic_1.00/IBM_5150-C1.00-U30-5700027.bin", 0xF8000),
+                RomImage::new("roms/basic_1.00/IBM_5150-C1.00-U31-5700035.bin", 0xFA000),
+                RomImage::new("roms/basic_1.00/IBM_5150-C1.00-U32-5700043.bin", 0xFC000),
+            ],
+            option_roms: Vec::new(),
+            font: PathBuf::from("roms/IBM_5788005_AM9264_1981_CGA_MDA_CARD.BIN"),
+            floppy_drives: 0,
+            display: DisplayType::Mda,
+            typematic_delay: TYPEMATIC_DELAY_MS,
+            typematic_rate: TYPEMATIC_RATE,
+            fpu: false,
+            accurate_timing: false,
+        }
+    }
+
+    // La maquina que piden los argumentos de los ejecutables, y las imagenes de disquete:
+    // --bios-1981, --cga, --fpu, --accurate-timing y --floppy imagen.img una vez por cada unidad, hasta 4
+    pub fn from_args(args: &[String]) -> Result<(MachineConfig, Vec<String>), DiskError> {
+        let mut config = if args.iter().any(|arg| arg == "--bios-1981") {
+            MachineConfig::ibm_5150_1981()
+        } else {
+            MachineConfig::ibm_5150_1982()
+        };
+
+        if args.iter().any(|arg| arg == "--cga") {
+            config.display = DisplayType::Cga80;
+        }
+
+        if args.iter().any(|arg| arg == "--fpu") {
+            config.fpu = true;
+        }
+
+        if args.iter().any(|arg| arg == "--accurate-timing") {
+            config.accurate_timing = true;
+        }
+
+        let floppies: Vec<String> = args.windows(2).filter(|w| w[0] == "--floppy").map(|w| w[1].clone()).collect();
+        if floppies.len() > MAX_FLOPPY_DRIVES as usize {
+            return Err(DiskError::NoDrive(MAX_FLOPPY_DRIVES as usize));
+        }
+        config.floppy_drives = floppies.len() as u8;
+
+        Ok((config, floppies))
+    }
+
+    pub fn roms(&self) -> impl Iterator<Item = &RomImage> {
+        self.basic.iter().chain(self.option_roms.iter()).chain(std::iter::once(&self.bios))
+    }
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        MachineConfig::ibm_5150_1982()
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(PathBuf, std::io::Error),
+    OutOfRange(PathBuf, usize, usize),
+    FontSize(PathBuf, usize),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::Io(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            RomError::OutOfRange(path, address, size) => write!(f, "{} ({} bytes) does not fit at {:05X}", path.display(), size, address),
+            RomError::FontSize(path, size) => write!(f, "{} is {} bytes, expected at least {}", path.display(), size, FONT_ROM_SIZE),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+pub const FONT_ROM_SIZE: usize = 0x2000;
+
+pub fn read_rom(rom: &RomImage) -> Result<Vec<u8>, RomError> {
+    let data = std::fs::read(&rom.path).map_err(|e| RomError::Io(rom.path.clone(), e))?;
+
+    if rom.address + data.len() > 0x100000 {
+        return Err(RomError::OutOfRange(rom.path.clone(), rom.address, data.len()));
+    }
+
+    Ok(data)
+}
+
+pub fn read_font(path: &Path) -> Result<Vec<u8>, RomError> {
+    let data = std::fs::read(path).map_err(|e| RomError::Io(path.to_path_buf(), e))?;
+
+    if data.len() < FONT_ROM_SIZE {
+        return Err(RomError::FontSize(path.to_path_buf(), data.len()));
+    }
+
+    Ok(data)
+}
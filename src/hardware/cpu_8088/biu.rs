@@ -0,0 +1,124 @@
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+// Cola de prefetch del 8088
+pub const QUEUE_SIZE: u32 = 4;
+// Cada ciclo de bus son 4 ciclos de reloj (T1-T4) y mueve un byte
+pub const BUS_CYCLE: u32 = 4;
+// La placa del PC mete un estado de espera en los accesos a E/S
+pub const IO_WAIT: u32 = 1;
+
+// Actividad del bus durante una instruccion
+#[derive(Clone, Copy, Default)]
+pub struct BusActivity {
+    // Bytes leidos o escritos en memoria
+    pub memory: u32,
+    // Accesos a puertos
+    pub io: u32,
+    // Ciclos en los que el DMA ha tenido el bus
+    pub dma: u32,
+}
+
+// Unidad de interfaz con el bus: temporizacion exacta con la cola de prefetch
+#[derive(Clone)]
+pub struct BIU {
+    // Bytes en la cola
+    pub queue: u32,
+    // Ciclos que lleva el fetch en curso
+    progress: u32,
+    // Direccion lineal del primer byte que no ha consumido la EU
+    next: u32,
+    // Bytes de instruccion consumidos por la EU en la instruccion actual
+    pub consumed: u32,
+
+    // Ciclos de bus totales y ciclos que la EU ha esperado a la cola
+    pub bus_cycles: u64,
+    pub stall_cycles: u64,
+}
+
+impl BIU {
+    pub fn new() -> Self {
+        BIU {
+            queue: 0,
+            progress: 0,
+            next: 0xFFFF0,
+            consumed: 0,
+
+            bus_cycles: 0,
+            stall_cycles: 0,
+        }
+    }
+
+    // Ciclos reales de una instruccion que empieza en start y deja la IP en end.
+    // eu son los ciclos de las tablas, con la cola llena y sin esperas
+    pub fn instruction(&mut self, start: u32, end: u32, eu: u32, activity: BusActivity) -> u32 {
+        // Si algo ha cambiado CS:IP entre instrucciones (una interrupcion) la cola no sirve
+        if start != self.next {
+            self.queue = 0;
+            self.progress = 0;
+        }
+
+        // Los bytes que no estaban en la cola hay que esperarlos
+        let consumed = std::mem::take(&mut self.consumed);
+        let mut stall = 0;
+        if consumed > self.queue {
+            let missing = consumed - self.queue;
+            stall = missing * BUS_CYCLE - self.progress;
+            self.bus_cycles += missing as u64;
+            self.queue = 0;
+            self.progress = 0;
+        } else {
+            self.queue -= consumed;
+        }
+
+        // Los accesos de la EU y el DMA tienen prioridad. Si no caben la instruccion se alarga
+        let busy = activity.memory * BUS_CYCLE + activity.io * (BUS_CYCLE + IO_WAIT) + activity.dma;
+        let eu = eu.max(busy);
+        self.bus_cycles += (activity.memory + activity.io) as u64;
+
+        // En el tiempo libre se llena la cola
+        let mut free = self.progress + eu - busy;
+        while self.queue < QUEUE_SIZE && free >= BUS_CYCLE {
+            self.queue += 1;
+            self.bus_cycles += 1;
+            free -= BUS_CYCLE;
+        }
+        self.progress = if self.queue < QUEUE_SIZE {free} else {0};
+
+        // Los saltos vacian la cola
+        if end != (start + consumed) & 0xFFFFF {
+            self.queue = 0;
+            self.progress = 0;
+        }
+
+        self.next = end;
+        self.stall_cycles += stall as u64;
+        stall + eu
+    }
+}
+
+impl Default for BIU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for BIU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.queue);
+        w.write_u32(self.progress);
+        w.write_u32(self.next);
+        w.write_u32(self.consumed);
+        w.write_u64(self.bus_cycles);
+        w.write_u64(self.stall_cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.queue = r.read_u32()?;
+        self.progress = r.read_u32()?;
+        self.next = r.read_u32()?;
+        self.consumed = r.read_u32()?;
+        self.bus_cycles = r.read_u64()?;
+        self.stall_cycles = r.read_u64()?;
+        Ok(())
+    }
+}
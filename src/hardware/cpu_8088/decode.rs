@@ -96,14 +96,14 @@ impl CPU {
                     _ => unreachable!(),
                 }
             },
-            // Prefijo _MISC r/m16
-            0xFF => {
+            // Prefijo _MISC r/m8 y r/m16. Con FE todo se hace en byte
+            0xFE | 0xFF => {
                 let operand = self.fetch(bus);
 
                 match operand & 0b00111000 {
                     0x0 => {
                         self.instr.opcode = Opcode::INC;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
 
                         self.cycles += match self.instr.operand1 {
@@ -114,7 +114,7 @@ impl CPU {
                     },
                     0x8 => {
                         self.instr.opcode = Opcode::DEC;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
 
                         self.cycles += match self.instr.operand1 {
@@ -125,7 +125,7 @@ impl CPU {
                     },
                     0x10 => {
                         self.instr.opcode = Opcode::CALL;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
 
                         self.instr.jump_type = JumpType::IndWithinSegment;
@@ -138,19 +138,21 @@ impl CPU {
                     },
                     0x18 => {
                         self.instr.opcode = Opcode::CALL;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
 
                         self.instr.jump_type = JumpType::IndIntersegment;
 
+                        // Con un registro usa la ultima direccion calculada
                         self.cycles += match self.instr.operand1 {
+                            OperandType::Register(_) => 53,
                             OperandType::Memory(_) => 53 + self.instr.ea_cycles,
                             _ => unreachable!(),
                         }
                     },
                     0x20 => {
                         self.instr.opcode = Opcode::JMP;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
 
                         self.instr.jump_type = JumpType::IndWithinSegment;
@@ -163,19 +165,21 @@ impl CPU {
                     },
                     0x28 => {
                         self.instr.opcode = Opcode::JMP;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
 
                         self.instr.jump_type = JumpType::IndIntersegment;
 
                         self.cycles += match self.instr.operand1 {
+                            OperandType::Register(_) => 24,
                             OperandType::Memory(_) => 24 + self.instr.ea_cycles,
                             _ => unreachable!(),
                         }
                     },
-                    0x30 => {
+                    // /7 es un alias de PUSH sin documentar
+                    0x30 | 0x38 => {
                         self.instr.opcode = Opcode::PUSH;
-                        self.instr.data_length = Length::Word;
+                        self.instr.data_length = Length::new(op, 0);
                         decode_mod_n_rm(self, bus, operand);
                         
                         self.cycles += match self.instr.operand1 {
@@ -435,25 +439,6 @@ impl CPU {
 
                 self.cycles += 4;
             },
-            // Prefijo _MISC r/m8
-            0xFE => {
-                let operand = self.fetch(bus);
-
-                self.instr.data_length = Length::Byte;
-                decode_mod_n_rm(self, bus, operand);
-
-                self.cycles += match self.instr.operand1 {
-                    OperandType::Register(_) => 3,
-                    OperandType::Memory(_) => 23 + self.instr.ea_cycles,
-                    _ => unreachable!(),
-                };
-
-                match operand & 0b00111000 {
-                    0x00 => self.instr.opcode = Opcode::INC,
-                    0x08 => self.instr.opcode = Opcode::DEC,
-                    _ => unreachable!(),
-                }
-            },
             0x40..=0x47 => {
                 self.instr.opcode = Opcode::INC;
                 self.instr.data_length = Length::Word;
@@ -684,7 +669,7 @@ impl CPU {
                     0x18 => self.instr.opcode = Opcode::RCR,
                     0x20 => self.instr.opcode = Opcode::SALSHL,
                     0x28 => self.instr.opcode = Opcode::SHR,
-                    0x30 => self.instr.opcode = Opcode::SALSHL,
+                    0x30 => self.instr.opcode = Opcode::SETMO,
                     0x38 => self.instr.opcode = Opcode::SAR,
                     _ => unreachable!(),
                 }
@@ -791,7 +776,7 @@ impl CPU {
                 };
             },
             0xA8 | 0xA9 => {
-                self.instr.opcode = Opcode::AND;
+                self.instr.opcode = Opcode::TEST;
                 self.instr.data_length = Length::new(op, 0);
                 self.instr.operand1 = match self.instr.data_length {
                     Length::Byte => OperandType::Register(Operand::AL),
@@ -811,6 +796,7 @@ impl CPU {
                     0x3E => Segment::DS,
                     _ => unreachable!(),
                 };
+                self.instr.resume_ip = self.ip.wrapping_sub(1);
                 
                 self.cycles += 2;
 
@@ -818,12 +804,22 @@ impl CPU {
                 self.decode(bus, new_op);
             },
 
+            // LOCK (F1 es un alias). En un PC sin otros maestros del bus no hace nada
+            0xF0 | 0xF1 => {
+                self.instr.resume_ip = self.ip.wrapping_sub(1);
+                self.cycles += 2;
+
+                let new_op = self.fetch(bus);
+                self.decode(bus, new_op);
+            },
+
             0xF2 | 0xF3 => {
                 self.instr.repetition_prefix = if op & 0x01 == 1 {
                     RepetitionPrefix::REPEZ
                 } else {
                     RepetitionPrefix::REPNEZ
                 };
+                self.instr.resume_ip = self.ip.wrapping_sub(1);
 
                 let new_op = self.fetch(bus);
                 self.decode(bus, new_op);
@@ -1102,12 +1098,39 @@ impl CPU {
                 self.cycles += 2;
             }
 
-            _ => {
-                // writeln!(&mut self.file, "Instrucción sin hacer: {:02X}", op).unwrap();
+            0xD8..=0xDF => {
+                self.instr.opcode = Opcode::ESC;
+                self.instr.data_length = Length::Word;
+
+                // El 8088 solo calcula la direccion, el 8087 hace el resto
+                let address = get_address(self).wrapping_sub(1) as u32;
+                if let Some(fpu) = &mut self.fpu {
+                    fpu.fetched(address);
+                }
+                let operand = self.fetch(bus);
+                decode_mod_n_rm(self, bus, operand);
+                self.instr.operand2 = OperandType::Immediate(((op as u16 & 0x07) << 8) | operand as u16);
 
-                self.cycles += 3
-            }
-            // _ => unreachable!(),
+                self.cycles += match self.instr.operand1 {
+                    OperandType::Register(_) => 2,
+                    OperandType::Memory(_) => 8 + self.instr.ea_cycles,
+                    _ => unreachable!(),
+                }
+            },
+            0x9B => {
+                self.instr.opcode = Opcode::WAIT;
+                self.cycles += 3;
+            },
+
+            0xD6 => {
+                self.instr.opcode = Opcode::SALC;
+                self.cycles += 4;
+            },
+
+            // Alias sin documentar de los saltos condicionales
+            0x60..=0x6F => self.decode(bus, op | 0x10),
+            // Alias sin documentar de RET
+            0xC0 | 0xC1 | 0xC8 | 0xC9 => self.decode(bus, op | 0x02),
         }
     }
 }
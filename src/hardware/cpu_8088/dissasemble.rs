@@ -1,20 +1,450 @@
+use std::fmt::Display;
+
 use crate::hardware::bus::Bus;
 
-use super::CPU;
-use super::instr_utils::Instruction;
+// Desensamblador del 8088 sin efectos: solo lee bytes, no toca la CPU ni el bus
+
+// Los prefijos no tienen limite en el 8088, pero mas de esto no aparece en codigo real
+pub const MAX_LENGTH: usize = 16;
+
+const REG8: [&str; 8] = ["AL", "CL", "DL", "BL", "AH", "CH", "DH", "BH"];
+const REG16: [&str; 8] = ["AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI"];
+const SEGMENTS: [&str; 4] = ["ES", "CS", "SS", "DS"];
+const EA: [&str; 8] = ["BX+SI", "BX+DI", "BP+SI", "BP+DI", "SI", "DI", "BP", "BX"];
+
+const ALU: [&str; 8] = ["ADD", "OR", "ADC", "SBB", "AND", "SUB", "XOR", "CMP"];
+const SHIFTS: [&str; 8] = ["ROL", "ROR", "RCL", "RCR", "SHL", "SHR", "SETMO", "SAR"];
+const GROUP3: [&str; 8] = ["TEST", "TEST", "NOT", "NEG", "MUL", "IMUL", "DIV", "IDIV"];
+const JUMPS: [&str; 16] = [
+    "JO", "JNO", "JB", "JNB", "JZ", "JNZ", "JBE", "JA",
+    "JS", "JNS", "JPE", "JPO", "JL", "JGE", "JLE", "JG",
+];
+
+// Destino de un salto o CALL directo
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Near(u16),
+    Far(u16, u16),
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Near(offset) => write!(f, "{:04X}", offset),
+            Target::Far(segment, offset) => write!(f, "{:04X}:{:04X}", segment, offset),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Dissasembly {
+    // Offset del primer byte (prefijos incluidos)
+    pub ip: u16,
+    pub bytes: Vec<u8>,
+    // Sintaxis Intel, numeros en hexadecimal
+    pub text: String,
+    pub target: Option<Target>,
+}
+
+impl Display for Dissasembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(f, "{:04X}  {:<14}  {}", self.ip, bytes.join(""), self.text)
+    }
+}
+
+fn reg(reg: u8, word: bool) -> &'static str {
+    if word {REG16[reg as usize & 7]} else {REG8[reg as usize & 7]}
+}
+
+fn imm8(val: u8) -> String {
+    format!("{:02X}", val)
+}
+
+fn imm16(val: u16) -> String {
+    format!("{:04X}", val)
+}
+
+fn text(mnemonic: &str, operands: &[String]) -> String {
+    if operands.is_empty() {
+        mnemonic.to_string()
+    } else {
+        format!("{} {}", mnemonic, operands.join(", "))
+    }
+}
+
+struct Decoder<'a> {
+    code: &'a [u8],
+    pos: usize,
+    ip: u16,
+    segment: Option<&'static str>,
+    // Si algun operando ha usado el prefijo de segmento
+    segment_used: bool,
+}
+
+impl Decoder<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.code.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn word(&mut self) -> Option<u16> {
+        let low = self.byte()?;
+        let high = self.byte()?;
+        Some(u16::from_le_bytes([low, high]))
+    }
+
+    fn imm(&mut self, word: bool) -> Option<String> {
+        Some(if word {imm16(self.word()?)} else {imm8(self.byte()?)})
+    }
+
+    // IP de la siguiente instruccion, para los saltos relativos
+    fn next_ip(&self) -> u16 {
+        self.ip.wrapping_add(self.pos as u16)
+    }
+
+    fn memory(&mut self, address: String) -> String {
+        self.segment_used = true;
+        match self.segment {
+            Some(segment) => format!("{}:[{}]", segment, address),
+            None => format!("[{}]", address),
+        }
+    }
+
+    // Operando r/m del byte modrm. Con ptr la memoria lleva el tamaño delante
+    fn rm(&mut self, modrm: u8, word: bool, ptr: bool) -> Option<String> {
+        let rm = (modrm & 0x07) as usize;
+        let address = match modrm >> 6 {
+            0b00 if rm == 0b110 => imm16(self.word()?),
+            0b00 => EA[rm].to_string(),
+            0b01 => {
+                let disp = self.byte()? as i8;
+                format!("{}{}{:02X}", EA[rm], if disp < 0 {'-'} else {'+'}, disp.unsigned_abs())
+            },
+            0b10 => format!("{}+{:04X}", EA[rm], self.word()?),
+            _ => return Some(reg(modrm, word).to_string()),
+        };
+
+        let memory = self.memory(address);
+        Some(if ptr {format!("{} PTR {}", if word {"WORD"} else {"BYTE"}, memory)} else {memory})
+    }
+
+    fn relative8(&mut self) -> Option<Target> {
+        let disp = self.byte()? as i8;
+        Some(Target::Near(self.next_ip().wrapping_add(disp as u16)))
+    }
+
+    fn relative16(&mut self) -> Option<Target> {
+        let disp = self.word()?;
+        Some(Target::Near(self.next_ip().wrapping_add(disp)))
+    }
+
+    fn far(&mut self) -> Option<Target> {
+        let offset = self.word()?;
+        let segment = self.word()?;
+        Some(Target::Far(segment, offset))
+    }
 
-#[allow(dead_code)]
-pub fn dissasemble_one(bus_: &Bus, cpu_: &CPU) -> Instruction {
-    let mut cpu = CPU::new();
-    cpu.ip = cpu_.ip;
-    cpu.cs = cpu_.cs;
+    // Prefijos y despues la instruccion. None si los bytes se acaban antes
+    fn decode(&mut self) -> Option<(String, Option<Target>)> {
+        let mut lock = false;
+        let mut rep = None;
+        let op = loop {
+            match self.byte()? {
+                0x26 => self.segment = Some("ES"),
+                0x2E => self.segment = Some("CS"),
+                0x36 => self.segment = Some("SS"),
+                0x3E => self.segment = Some("DS"),
+                0xF0 | 0xF1 => lock = true,
+                0xF2 => rep = Some(false),
+                0xF3 => rep = Some(true),
+                op => break op,
+            }
+        };
 
-    let mut bus = bus_.clone();
+        let (mut text, target) = self.instruction(op)?;
+
+        // El prefijo de segmento que no ha usado ningun operando se queda delante (MOVSB, XLAT...)
+        if let Some(segment) = self.segment.filter(|_| !self.segment_used) {
+            text = format!("{}: {}", segment, text);
+        }
+        match rep {
+            Some(true) if matches!(op, 0xA6 | 0xA7 | 0xAE | 0xAF) => text = format!("REPZ {}", text),
+            Some(true) => text = format!("REP {}", text),
+            Some(false) => text = format!("REPNZ {}", text),
+            None => {},
+        }
+        if lock {
+            text = format!("LOCK {}", text);
+        }
+        Some((text, target))
+    }
+
+    fn instruction(&mut self, op: u8) -> Option<(String, Option<Target>)> {
+        let word = op & 0x01 != 0;
+
+        // Alias sin documentar de los saltos condicionales y de RET
+        let op = match op {
+            0x60..=0x6F => op | 0x10,
+            0xC0 | 0xC1 | 0xC8 | 0xC9 => op | 0x02,
+            _ => op,
+        };
+
+        let instr = match op {
+            // ALU reg/mem
+            0x00..=0x3F if op & 0x07 < 4 => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, word, false)?;
+                let reg = reg(modrm >> 3, word).to_string();
+                let operands = if op & 0x02 != 0 {[reg, rm]} else {[rm, reg]};
+                text(ALU[(op >> 3) as usize], &operands)
+            },
+            // ALU AL/AX, imm
+            0x00..=0x3F if op & 0x07 < 6 => {
+                let imm = self.imm(word)?;
+                text(ALU[(op >> 3) as usize], &[reg(0, word).to_string(), imm])
+            },
+            0x06 | 0x0E | 0x16 | 0x1E => text("PUSH", &[SEGMENTS[(op >> 3) as usize].to_string()]),
+            0x07 | 0x0F | 0x17 | 0x1F => text("POP", &[SEGMENTS[(op >> 3) as usize].to_string()]),
+            0x27 => text("DAA", &[]),
+            0x2F => text("DAS", &[]),
+            0x37 => text("AAA", &[]),
+            0x3F => text("AAS", &[]),
+            0x40..=0x47 => text("INC", &[reg(op, true).to_string()]),
+            0x48..=0x4F => text("DEC", &[reg(op, true).to_string()]),
+            0x50..=0x57 => text("PUSH", &[reg(op, true).to_string()]),
+            0x58..=0x5F => text("POP", &[reg(op, true).to_string()]),
+            0x70..=0x7F => {
+                let target = self.relative8()?;
+                return Some((format!("{} {}", JUMPS[(op & 0x0F) as usize], target), Some(target)));
+            },
+            0x80..=0x83 => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, word, true)?;
+                let imm = match op {
+                    0x83 => imm16(self.byte()? as i8 as u16),
+                    _ => self.imm(word)?,
+                };
+                text(ALU[((modrm >> 3) & 0x07) as usize], &[rm, imm])
+            },
+            0x84..=0x87 => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, word, false)?;
+                text(if op < 0x86 {"TEST"} else {"XCHG"}, &[rm, reg(modrm >> 3, word).to_string()])
+            },
+            0x88..=0x8B => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, word, false)?;
+                let reg = reg(modrm >> 3, word).to_string();
+                text("MOV", &if op & 0x02 != 0 {[reg, rm]} else {[rm, reg]})
+            },
+            // Los segmentos 4-7 son alias de 0-3
+            0x8C | 0x8E => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, true, false)?;
+                let segment = SEGMENTS[((modrm >> 3) & 0x03) as usize].to_string();
+                text("MOV", &if op == 0x8E {[segment, rm]} else {[rm, segment]})
+            },
+            0x8D | 0xC4 | 0xC5 => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, true, false)?;
+                let mnemonic = match op {
+                    0x8D => "LEA",
+                    0xC4 => "LES",
+                    _ => "LDS",
+                };
+                text(mnemonic, &[reg(modrm >> 3, true).to_string(), rm])
+            },
+            0x8F => {
+                let modrm = self.byte()?;
+                text("POP", &[self.rm(modrm, true, true)?])
+            },
+            0x90 => text("NOP", &[]),
+            0x91..=0x97 => text("XCHG", &["AX".to_string(), reg(op, true).to_string()]),
+            0x98 => text("CBW", &[]),
+            0x99 => text("CWD", &[]),
+            0x9A => {
+                let target = self.far()?;
+                return Some((format!("CALL {}", target), Some(target)));
+            },
+            0x9B => text("WAIT", &[]),
+            0x9C => text("PUSHF", &[]),
+            0x9D => text("POPF", &[]),
+            0x9E => text("SAHF", &[]),
+            0x9F => text("LAHF", &[]),
+            0xA0..=0xA3 => {
+                let address = imm16(self.word()?);
+                let memory = self.memory(address);
+                let reg = reg(0, word).to_string();
+                text("MOV", &if op & 0x02 != 0 {[memory, reg]} else {[reg, memory]})
+            },
+            0xA4 | 0xA5 => text(if word {"MOVSW"} else {"MOVSB"}, &[]),
+            0xA6 | 0xA7 => text(if word {"CMPSW"} else {"CMPSB"}, &[]),
+            0xA8 | 0xA9 => {
+                let imm = self.imm(word)?;
+                text("TEST", &[reg(0, word).to_string(), imm])
+            },
+            0xAA | 0xAB => text(if word {"STOSW"} else {"STOSB"}, &[]),
+            0xAC | 0xAD => text(if word {"LODSW"} else {"LODSB"}, &[]),
+            0xAE | 0xAF => text(if word {"SCASW"} else {"SCASB"}, &[]),
+            0xB0..=0xBF => {
+                let word = op & 0x08 != 0;
+                let imm = self.imm(word)?;
+                text("MOV", &[reg(op, word).to_string(), imm])
+            },
+            0xC2 => text("RET", &[imm16(self.word()?)]),
+            0xC3 => text("RET", &[]),
+            0xC6 | 0xC7 => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, word, true)?;
+                let imm = self.imm(word)?;
+                text("MOV", &[rm, imm])
+            },
+            0xCA => text("RETF", &[imm16(self.word()?)]),
+            0xCB => text("RETF", &[]),
+            0xCC => text("INT", &["3".to_string()]),
+            0xCD => text("INT", &[imm8(self.byte()?)]),
+            0xCE => text("INTO", &[]),
+            0xCF => text("IRET", &[]),
+            0xD0..=0xD3 => {
+                let modrm = self.byte()?;
+                let rm = self.rm(modrm, word, true)?;
+                let count = if op & 0x02 != 0 {"CL"} else {"1"};
+                text(SHIFTS[((modrm >> 3) & 0x07) as usize], &[rm, count.to_string()])
+            },
+            0xD4 | 0xD5 => {
+                let base = self.byte()?;
+                let mnemonic = if op == 0xD4 {"AAM"} else {"AAD"};
+                if base == 0x0A {text(mnemonic, &[])} else {text(mnemonic, &[imm8(base)])}
+            },
+            0xD6 => text("SALC", &[]),
+            0xD7 => text("XLAT", &[]),
+            // El 8088 solo calcula la direccion: el numero de ESC junta el opcode y el campo reg
+            0xD8..=0xDF => {
+                let modrm = self.byte()?;
+                let number = ((op & 0x07) << 3) | ((modrm >> 3) & 0x07);
+                let rm = self.rm(modrm, true, false)?;
+                text("ESC", &[imm8(number), rm])
+            },
+            0xE0..=0xE3 | 0xEB => {
+                let target = self.relative8()?;
+                let mnemonic = match op {
+                    0xE0 => "LOOPNZ",
+                    0xE1 => "LOOPZ",
+                    0xE2 => "LOOP",
+                    0xE3 => "JCXZ",
+                    _ => "JMP SHORT",
+                };
+                return Some((format!("{} {}", mnemonic, target), Some(target)));
+            },
+            0xE4 | 0xE5 => {
+                let port = imm8(self.byte()?);
+                text("IN", &[reg(0, word).to_string(), port])
+            },
+            0xE6 | 0xE7 => {
+                let port = imm8(self.byte()?);
+                text("OUT", &[port, reg(0, word).to_string()])
+            },
+            0xE8 | 0xE9 => {
+                let target = self.relative16()?;
+                return Some((format!("{} {}", if op == 0xE8 {"CALL"} else {"JMP"}, target), Some(target)));
+            },
+            0xEA => {
+                let target = self.far()?;
+                return Some((format!("JMP {}", target), Some(target)));
+            },
+            0xEC | 0xED => text("IN", &[reg(0, word).to_string(), "DX".to_string()]),
+            0xEE | 0xEF => text("OUT", &["DX".to_string(), reg(0, word).to_string()]),
+            0xF4 => text("HLT", &[]),
+            0xF5 => text("CMC", &[]),
+            0xF6 | 0xF7 => {
+                let modrm = self.byte()?;
+                let group = ((modrm >> 3) & 0x07) as usize;
+                let rm = self.rm(modrm, word, true)?;
+                if group < 2 {
+                    let imm = self.imm(word)?;
+                    text(GROUP3[group], &[rm, imm])
+                } else {
+                    text(GROUP3[group], &[rm])
+                }
+            },
+            0xF8 => text("CLC", &[]),
+            0xF9 => text("STC", &[]),
+            0xFA => text("CLI", &[]),
+            0xFB => text("STI", &[]),
+            0xFC => text("CLD", &[]),
+            0xFD => text("STD", &[]),
+            // FE con /2-/7 hace lo mismo que FF pero con un byte, /7 es un alias de PUSH
+            0xFE | 0xFF => {
+                let modrm = self.byte()?;
+                match (modrm >> 3) & 0x07 {
+                    0 => text("INC", &[self.rm(modrm, word, true)?]),
+                    1 => text("DEC", &[self.rm(modrm, word, true)?]),
+                    2 => text("CALL", &[self.rm(modrm, word, true)?]),
+                    4 => text("JMP", &[self.rm(modrm, word, true)?]),
+                    group @ (3 | 5) => {
+                        let rm = self.rm(modrm, word, false)?;
+                        let mnemonic = if group == 3 {"CALL"} else {"JMP"};
+                        if modrm >> 6 == 0b11 {
+                            text(mnemonic, &[format!("FAR {}", rm)])
+                        } else {
+                            text(mnemonic, &[format!("DWORD PTR {}", rm)])
+                        }
+                    },
+                    _ => text("PUSH", &[self.rm(modrm, word, true)?]),
+                }
+            },
+            // Los prefijos ya los ha leido decode y los alias ya estan convertidos
+            _ => unreachable!(),
+        };
+        Some((instr, None))
+    }
+}
+
+// Desensambla la instruccion al principio de code, que empieza en el offset ip.
+// Si los bytes no llegan para una instruccion entera sale un DB con el primero
+pub fn dissasemble(code: &[u8], ip: u16) -> Dissasembly {
+    let mut decoder = Decoder {
+        code,
+        pos: 0,
+        ip,
+        segment: None,
+        segment_used: false,
+    };
+
+    match decoder.decode() {
+        Some((text, target)) => Dissasembly {
+            ip,
+            bytes: code[..decoder.pos].to_vec(),
+            text,
+            target,
+        },
+        None => Dissasembly {
+            ip,
+            bytes: code.iter().take(1).copied().collect(),
+            text: code.first().map(|byte| format!("DB {:02X}", byte)).unwrap_or_default(),
+            target: None,
+        },
+    }
+}
+
+// La instruccion en CS:IP de la memoria del bus. El offset da la vuelta dentro del segmento
+pub fn dissasemble_bus(bus: &Bus, cs: u16, ip: u16) -> Dissasembly {
+    let code: Vec<u8> = (0..MAX_LENGTH as u16)
+        .map(|i| bus.read_dir(((cs as usize) << 4) + ip.wrapping_add(i) as usize))
+        .collect();
+    dissasemble(&code, ip)
+}
 
-    // let op = fetch_disassembly(&mut bus, &mut ip);
-    // decode_dissasembly(&mut cpu, &mut bus, op, &mut ip);
-    // cpu.instr
-    let op = cpu.fetch(&mut bus);
-    cpu.decode(&mut bus, op);
-    cpu.instr
+// Listado de todo code de seguido, una instruccion por linea con CS:IP y los bytes
+pub fn listing(code: &[u8], cs: u16, ip: u16) -> String {
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    while pos < code.len() {
+        let instr = dissasemble(&code[pos..], ip.wrapping_add(pos as u16));
+        pos += instr.bytes.len();
+        lines.push(format!("{:04X}:{}", cs, instr));
+    }
+    lines.join("\n")
 }
@@ -12,6 +12,7 @@ impl CPU {
             Opcode::MOV => {
                 let val = self.get_val(bus, self.instr.operand2);
                 self.set_val(bus, self.instr.operand1, val);
+                self.check_ss_shadow();
             },
             Opcode::PUSH => {
                 let val = self.get_val(bus, self.instr.operand1);
@@ -20,6 +21,7 @@ impl CPU {
             Opcode::POP => {
                 let val = self.pop_stack_16(bus);
                 self.set_val(bus, self.instr.operand1, val);
+                self.check_ss_shadow();
             },
             Opcode::XCHG => {
                 let val1 = self.get_val(bus, self.instr.operand1);
@@ -45,13 +47,11 @@ impl CPU {
                     self.instr.segment
                 };
 
-                let val = bus.read_8(self.get_segment(segment), self.get_reg(Operand::BX) + self.get_reg(Operand::AL));
+                let val = bus.read_8(self.get_segment(segment), self.get_reg(Operand::BX).wrapping_add(self.get_reg(Operand::AL)));
                 self.set_reg8(Operand::AL, val);
             },
             Opcode::LEA => {
-                let val = bus.read_length(self, self.instr.segment, self.instr.offset, self.instr.data_length);
-                self.set_val(bus, self.instr.operand1, val);
-                // self.set_val(bus, self.instr.operand1, self.instr.offset);
+                self.set_val(bus, self.instr.operand1, self.instr.offset);
             }
             Opcode::LDS => {
                 let val = bus.read_length(self, self.instr.segment, self.instr.offset, self.instr.data_length);
@@ -250,16 +250,26 @@ impl CPU {
             Opcode::AAM => {
                 let temp_al = self.ax.low;
                 let val = self.get_val(bus, self.instr.operand1);
+                if val == 0 {
+                    self.sw_int = true;
+                    self.sw_int_type = 0;
+                    return;
+                }
                 self.ax.high = temp_al / val as u8;
                 self.ax.low = temp_al % val as u8;
 
                 self.flags.set_aam_flags(self.ax.low);
             },
             Opcode::DIV => {
-                let val2 = self.get_val(bus, self.instr.operand1);
+                let val2 = self.get_val(bus, self.instr.operand1) as u32;
+                let (val1, max) = match self.instr.data_length {
+                    Length::Byte => (self.ax.get_x() as u32, 0xFF),
+                    Length::Word => (to_u32(self.ax.get_x(), self.dx.get_x()), 0xFFFF),
+                    _ => unreachable!(),
+                };
 
-                if val2 == 0 {
-                    // self.interrupt(bus, 0);
+                // Division por cero o cociente que no cabe: INT 0 volviendo a la siguiente instruccion
+                if val2 == 0 || val1 / val2 > max {
                     self.sw_int = true;
                     self.sw_int_type = 0;
                     return;
@@ -267,52 +277,47 @@ impl CPU {
 
                 match self.instr.data_length {
                     Length::Byte => {
-                        let val1 = self.ax.low as u16;
-                        self.set_reg(self.instr.data_length, Operand::AL, val1.wrapping_div(val2));
-                        self.set_reg(self.instr.data_length, Operand::AH, val1.wrapping_rem(val2));
+                        self.ax.low = (val1 / val2) as u8;
+                        self.ax.high = (val1 % val2) as u8;
                     },
                     Length::Word => {
-                        let val1 = self.ax.get_x();
-                        self.set_reg(self.instr.data_length, Operand::AX, val1.wrapping_div(val2));
-                        self.set_reg(self.instr.data_length, Operand::DX, val1.wrapping_rem(val2));
+                        self.ax.set_x((val1 / val2) as u16);
+                        self.dx.set_x((val1 % val2) as u16);
                     },
                     _ => unreachable!(),
                 };
             },
             Opcode::IDIV => {
-                let val2 = self.get_val(bus, self.instr.operand1) as i16;
+                let val2 = self.get_val(bus, self.instr.operand1);
+                let (val1, val2, max) = match self.instr.data_length {
+                    Length::Byte => (self.ax.get_x() as i16 as i32, val2 as u8 as i8 as i32, 0x7F),
+                    Length::Word => (to_u32(self.ax.get_x(), self.dx.get_x()) as i32, val2 as i16 as i32, 0x7FFF),
+                    _ => unreachable!(),
+                };
 
                 if val2 == 0 {
-                    // self.interrupt(bus, 0);
                     self.sw_int = true;
                     self.sw_int_type = 0;
                     return;
                 }
 
+                // En el 8088 el cociente mas negativo (-80h o -8000h) tambien da error
+                let res = val1.wrapping_div(val2);
+                if res > max || res < -max {
+                    self.sw_int = true;
+                    self.sw_int_type = 0;
+                    return;
+                }
+
+                let rem = val1.wrapping_rem(val2);
                 match self.instr.data_length {
                     Length::Byte => {
-                        let val1 = self.ax.low as i8 as i16;
-                        let res = val1.wrapping_div(val2);
-                        if res > 0x7F || -res > 0x80 {
-                            // self.interrupt(bus, 0);
-                            self.sw_int = true;
-                            self.sw_int_type = 0;
-                        } else {
-                            self.set_reg(self.instr.data_length, Operand::AL, res as u16);
-                            self.set_reg(self.instr.data_length, Operand::AH, val1.wrapping_rem(val2) as u16);
-                        }
+                        self.ax.low = res as u8;
+                        self.ax.high = rem as u8;
                     },
                     Length::Word => {
-                        let val1 = to_u32(self.ax.get_x(), self.dx.get_x()) as i32;
-                        let res = val1.wrapping_div(val2 as i32);
-                        if res > 0x7FFF || -res > 0x8000 {
-                            // self.interrupt(bus, 0);
-                            self.sw_int = true;
-                            self.sw_int_type = 0;
-                        } else {
-                            self.set_reg(self.instr.data_length, Operand::AX, res as u16);
-                            self.set_reg(self.instr.data_length, Operand::DX, val1.wrapping_rem(val2 as i32) as u16);
-                        }
+                        self.ax.set_x(res as u16);
+                        self.dx.set_x(rem as u16);
                     },
                     _ => unreachable!(),
                 };
@@ -322,7 +327,7 @@ impl CPU {
                 let temp_ah = self.ax.high;
                 self.ax.high = 0;
                 let val = self.get_val(bus, self.instr.operand1);
-                self.ax.low = temp_al + (temp_ah.wrapping_add(val as u8));
+                self.ax.low = temp_al.wrapping_add(temp_ah.wrapping_mul(val as u8));
 
                 self.flags.set_aam_flags(self.ax.low);
             },
@@ -359,6 +364,21 @@ impl CPU {
 
                 self.flags.set_shr_flags(self.instr.data_length, self.instr.operand2, count, val, res);
             },
+            Opcode::SETMO => {
+                // Sin documentar: deja el operando a unos. Con CL a 0 no hace nada
+                let count = self.get_val(bus, self.instr.operand2);
+                if count == 0 {
+                    return;
+                }
+                let res = match self.instr.data_length {
+                    Length::Byte => 0xFF,
+                    Length::Word => 0xFFFF,
+                    _ => unreachable!(),
+                };
+                self.set_val(bus, self.instr.operand1, res);
+                self.flags.set_logic_flags(self.instr.data_length, res);
+                self.flags.a = false;
+            },
             Opcode::SAR => {
                 let val = self.get_val(bus, self.instr.operand1);
                 let count = self.get_val(bus, self.instr.operand2) as u32;
@@ -495,7 +515,6 @@ impl CPU {
                     JumpType::DirIntersegment(offset, segment) => {
                         self.cs = segment; 
                         self.ip = offset;
-                        let _a = 0;
                     },
                     _ => unreachable!(),
                 }
@@ -601,12 +620,36 @@ impl CPU {
             },
             Opcode::STI => {
                 self.flags.i = true;
+                self.int_shadow = true;
             },
 
             Opcode::HLT => {
                 self.halted = true;
             },
 
+            Opcode::ESC => {
+                let OperandType::Immediate(esc) = self.instr.operand2 else {unreachable!()};
+                let address = match self.instr.operand1 {
+                    OperandType::Memory(_) => Some((self.get_segment(self.instr.segment), self.instr.offset)),
+                    _ => None,
+                };
+
+                // Sin coprocesador el ESC no hace nada
+                if let Some(fpu) = &mut self.fpu {
+                    fpu.execute(bus, esc, address);
+                }
+            },
+            Opcode::SALC => {
+                self.ax.low = if self.flags.c {0xFF} else {0x00};
+            },
+            Opcode::WAIT => {
+                // Espera a que el 8087 quite BUSY
+                if let Some(fpu) = &mut self.fpu {
+                    self.cycles += fpu.busy;
+                    fpu.busy = 0;
+                }
+            },
+
             _ => {}
             // _ => unreachable!(),
         }
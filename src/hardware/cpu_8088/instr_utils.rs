@@ -33,6 +33,8 @@ pub struct Instruction {
     pub ret_type: RetType,
 
     pub repetition_prefix: RepetitionPrefix,
+    // Direccion del ultimo prefijo, o del opcode si no hay. Una interrupcion en mitad de un REP vuelve aqui
+    pub resume_ip: u16,
 }
 
 impl Default for Instruction {
@@ -59,6 +61,7 @@ impl Default for Instruction {
             ret_type: RetType::None,
 
             repetition_prefix: RepetitionPrefix::None,
+            resume_ip: 0,
         }
     }
 }
@@ -197,8 +200,31 @@ pub enum Opcode {
     STI,
     HLT,
     NOP,
+    ESC,
+    WAIT,
+    SALC,
+    SETMO,
 }
 
+// Todos los opcodes en el mismo orden que el enum, para poder convertir desde u8
+pub const OPCODES: [Opcode; 96] = [
+    Opcode::None, Opcode::MOV, Opcode::PUSH, Opcode::POP, Opcode::XCHG, Opcode::IN, Opcode::OUT,
+    Opcode::XLAT, Opcode::LEA, Opcode::LDS, Opcode::LES, Opcode::LAHF, Opcode::SAHF, Opcode::PUSHF,
+    Opcode::POPF, Opcode::ADD, Opcode::ADC, Opcode::INC, Opcode::AAA, Opcode::DAA, Opcode::SUB,
+    Opcode::SBB, Opcode::DEC, Opcode::NEG, Opcode::CMP, Opcode::AAS, Opcode::DAS, Opcode::MUL,
+    Opcode::IMUL, Opcode::AAM, Opcode::DIV, Opcode::IDIV, Opcode::AAD, Opcode::CBW, Opcode::CWD,
+    Opcode::NOT, Opcode::SALSHL, Opcode::SHR, Opcode::SAR, Opcode::ROL, Opcode::ROR, Opcode::RCL,
+    Opcode::RCR, Opcode::AND, Opcode::TEST, Opcode::OR, Opcode::XOR, Opcode::MOVSB, Opcode::MOVSW,
+    Opcode::CMPSB, Opcode::CMPSW, Opcode::SCASB, Opcode::SCASW, Opcode::LODSB, Opcode::LODSW,
+    Opcode::STOSB, Opcode::STOSW, Opcode::CALL, Opcode::JMP, Opcode::RET, Opcode::JEJZ,
+    Opcode::JLJNGE, Opcode::JLEJNG, Opcode::JBJNAE, Opcode::JBEJNA, Opcode::JPJPE, Opcode::JO,
+    Opcode::JS, Opcode::JNEJNZ, Opcode::JNLJGE, Opcode::JNLEJG, Opcode::JNBJAE, Opcode::JNBEJA,
+    Opcode::JNPJPO, Opcode::JNO, Opcode::JNS, Opcode::LOOP, Opcode::LOOPZE, Opcode::LOOPNZNE,
+    Opcode::JCXZ, Opcode::INT, Opcode::INTO, Opcode::IRET, Opcode::CLC, Opcode::CMC, Opcode::STC,
+    Opcode::CLD, Opcode::STD, Opcode::CLI, Opcode::STI, Opcode::HLT, Opcode::NOP,
+    Opcode::ESC, Opcode::WAIT, Opcode::SALC, Opcode::SETMO,
+];
+
 impl Display for Opcode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let val = match self {
@@ -294,6 +320,10 @@ impl Display for Opcode {
             Opcode::STI => "STI",
             Opcode::HLT => "HLT",
             Opcode::NOP => "NOP",
+            Opcode::ESC => "ESC",
+            Opcode::WAIT => "WAIT",
+            Opcode::SALC => "SALC",
+            Opcode::SETMO => "SETMO",
         };
         write!(f, "{}", val)
     }
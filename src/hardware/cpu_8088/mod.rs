@@ -1,8 +1,10 @@
 pub mod instr_utils;
 pub mod cpu_utils;
 pub mod regs;
+pub mod biu;
 mod decode;
 mod execute;
+mod state;
 
 pub mod dissasemble;
 
@@ -10,6 +12,8 @@ pub mod dissasemble;
 use std::collections::HashMap;
 
 use super::bus::Bus;
+use super::fpu_8087::FPU8087;
+use biu::BIU;
 use instr_utils::*;
 use regs::{GPReg, Flags};
 use cpu_utils::*;
@@ -47,12 +51,25 @@ pub struct CPU {
 
     pub nmi: bool,
     pub nmi_enabled: bool,
+    // Nivel de la linea INT del 8087 la ultima vez que se miro
+    pub fpu_int: bool,
     // Controla de que tipo es la SW INT si existe
     pub sw_int: bool,
     pub sw_int_type: u8,
+    // TF al empezar la instruccion: al acabar toca INT 1
+    pub trap: bool,
+    // Despues de MOV SS, POP SS o STI no se atienden interrupciones hasta la siguiente instruccion
+    pub int_shadow: bool,
+    // Vectores atendidos en el ultimo paso, para el depurador
+    pub interrupts_taken: Vec<u8>,
 
     pub halted: bool,
 
+    // Coprocesador 8087 opcional
+    pub fpu: Option<FPU8087>,
+    // Temporizacion exacta del bus y la cola de prefetch, opcional
+    pub biu: Option<BIU>,
+
     // Usado en instrucciones de Strings cuando tengan que repetirse
     pub to_decode: bool,
 
@@ -88,11 +105,18 @@ impl CPU {
 
             nmi: false,
             nmi_enabled: false,
+            fpu_int: false,
             sw_int: false,
             sw_int_type: 0,
+            trap: false,
+            int_shadow: false,
+            interrupts_taken: Vec::new(),
 
             halted: false,
 
+            fpu: None,
+            biu: None,
+
             to_decode: true,
 
             #[cfg(debug_assertions)]
@@ -101,10 +125,19 @@ impl CPU {
     }
 }
 
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CPU {
     pub fn fetch(&mut self, bus: &mut Bus) -> u8 {
         let dir = get_address(self);
         self.ip = (self.ip as u32 + 1) as u16;
+        if let Some(biu) = &mut self.biu {
+            biu.consumed += 1;
+        }
         bus.read_dir(dir)
     }
 
@@ -112,22 +145,49 @@ impl CPU {
     pub fn fetch_decode_execute(&mut self, bus: &mut Bus) -> (u32, u16) {
         self.cycles = 0;
         let ip = self.ip;
+        let start = get_address(self) as u32;
+        self.trap = self.flags.t;
+        self.interrupts_taken.clear();
 
         if self.to_decode {
             self.instr = Instruction::default();
+            self.instr.resume_ip = self.ip;
             let op = self.fetch(bus);
             self.decode(bus, op);
         }
 
         self.execute(bus);
+
+        // Lo que haya usado el bus desde la instruccion anterior (interrupciones, DMA) tambien cuenta
+        let activity = bus.take_activity();
+        let end = get_address(self) as u32;
+        if let Some(biu) = &mut self.biu {
+            self.cycles = biu.instruction(start, end, self.cycles, activity);
+        }
+        if let Some(fpu) = &mut self.fpu {
+            fpu.elapse(self.cycles);
+        }
         bus.pit.cycles += self.cycles;
         (self.cycles, ip)
     }
 
     pub fn handle_interrupts(&mut self, bus: &mut Bus) {
-        if self.flags.i && self.sw_int {
+        let trap = std::mem::take(&mut self.trap);
+
+        // La NMI se pide al subir la linea INT del 8087 y queda pendiente mientras este enmascarada
+        let fpu_int = self.fpu.as_ref().is_some_and(|fpu| fpu.interrupt());
+        if fpu_int && !self.fpu_int {
+            self.nmi = true;
+        }
+        self.fpu_int = fpu_int;
+
+        if self.sw_int {
+            // Las internas (division, INT n, INTO) no dependen de IF
             self.interrupt(bus, self.sw_int_type as u16 * 0x04);
             self.sw_int = false;
+        } else if std::mem::take(&mut self.int_shadow) {
+            // Ni NMI, ni INTR, ni paso a paso
+            return;
         } else if self.nmi && self.nmi_enabled {
             // Si hay una NON-MASKABLE INTERRUPT
             self.interrupt(bus, 0x0008);
@@ -135,18 +195,26 @@ impl CPU {
         } else if self.flags.i && bus.pic.has_int() {
             let interruption = bus.pic.get_next();
             self.interrupt(bus, (interruption * 0x04) as u16);
-        } else {
-            // TODO ESTO IGUAL ESTA MAL
-            self.nmi = false;
-            self.sw_int = false;
+        }
+
+        // El paso a paso es lo ultimo: su rutina se ejecuta antes que la de cualquier otra
+        if trap {
+            self.interrupt(bus, 0x0004);
         }
     }
 
     pub fn interrupt(&mut self, bus: &mut Bus, ip_location: u16) {
+        // A mitad de un REP se vuelve al ultimo prefijo. Como el 8088, los anteriores se pierden
+        if !self.to_decode {
+            self.ip = self.instr.resume_ip;
+            self.to_decode = true;
+        }
+
         self.push_stack_16(bus, self.flags.get_flags());
         self.push_stack_16(bus, self.cs);
         self.push_stack_16(bus, self.ip);
 
+        self.interrupts_taken.push((ip_location / 4) as u8);
         self.ip = bus.read_16(0, ip_location);
         self.cs = bus.read_16(0, ip_location + 2);
         
@@ -167,6 +235,12 @@ impl CPU {
 
 // Utilidades para el set de instrucciones
 impl CPU {
+    pub fn check_ss_shadow(&mut self) {
+        if let OperandType::SegmentRegister(Segment::SS) = self.instr.operand1 {
+            self.int_shadow = true;
+        }
+    }
+
     fn set_reg8(&mut self, reg: Operand, val: u8) {
         match reg {
             Operand::AL => self.ax.low = val,
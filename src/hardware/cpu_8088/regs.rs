@@ -22,6 +22,12 @@ impl GPReg {
     }
 }
 
+impl Default for GPReg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Flags {
     pub o: bool,
@@ -61,11 +67,17 @@ impl Flags {
         let z = ((self.z as u16) <<  6) & 0b0000000001000000;
         let a = ((self.a as u16) <<  4) & 0b0000000000010000;
         let p = ((self.p as u16) <<  2) & 0b0000000000000100;
-        let c = ((self.c as u16) <<  0) & 0b0000000000000001;
+        let c = (self.c as u16) & 0b0000000000000001;
         o + d + i + t + s + z + a + p + c
     }
 }
 
+impl Default for Flags {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn check_o_add_8(val1: u8, val2: u8, res: u8) -> bool {
     let sign_1 = val1 >> 7;
     let sign_2 = val2 >> 7;
@@ -147,7 +159,7 @@ fn check_c_sub_8(val1: u8, val2: u8) -> bool {
 }
 
 fn check_p(val: u16) -> bool {
-    val.count_ones() % 2 == 0
+    val.count_ones().is_multiple_of(2)
 }
 
 impl Flags {
@@ -0,0 +1,362 @@
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+use crate::hardware::fpu_8087::FPU8087;
+
+use super::CPU;
+use super::biu::BIU;
+use super::instr_utils::*;
+
+fn write_segment(w: &mut StateWriter, segment: Segment) {
+    w.write_u8(match segment {
+        Segment::None => 0,
+        Segment::CS => 1,
+        Segment::DS => 2,
+        Segment::ES => 3,
+        Segment::SS => 4,
+    });
+}
+
+fn read_segment(r: &mut StateReader) -> Result<Segment, StateError> {
+    match r.read_u8()? {
+        0 => Ok(Segment::None),
+        1 => Ok(Segment::CS),
+        2 => Ok(Segment::DS),
+        3 => Ok(Segment::ES),
+        4 => Ok(Segment::SS),
+        _ => Err(StateError::Invalid("segment")),
+    }
+}
+
+fn write_operand(w: &mut StateWriter, operand: Operand) {
+    let (tag, disp) = match operand {
+        Operand::AL => (0, 0),
+        Operand::BL => (1, 0),
+        Operand::CL => (2, 0),
+        Operand::DL => (3, 0),
+        Operand::AH => (4, 0),
+        Operand::BH => (5, 0),
+        Operand::CH => (6, 0),
+        Operand::DH => (7, 0),
+        Operand::AX => (8, 0),
+        Operand::BX => (9, 0),
+        Operand::CX => (10, 0),
+        Operand::DX => (11, 0),
+        Operand::SI => (12, 0),
+        Operand::DI => (13, 0),
+        Operand::BP => (14, 0),
+        Operand::SP => (15, 0),
+        Operand::BXSI => (16, 0),
+        Operand::BXDI => (17, 0),
+        Operand::BPSI => (18, 0),
+        Operand::BPDI => (19, 0),
+        Operand::DispBXSI(d) => (20, d),
+        Operand::DispBXDI(d) => (21, d),
+        Operand::DispBPSI(d) => (22, d),
+        Operand::DispBPDI(d) => (23, d),
+        Operand::DispSI(d) => (24, d),
+        Operand::DispDI(d) => (25, d),
+        Operand::DispBP(d) => (26, d),
+        Operand::DispBX(d) => (27, d),
+        Operand::Disp(d) => (28, d),
+    };
+    w.write_u8(tag);
+    w.write_u16(disp);
+}
+
+fn read_operand(r: &mut StateReader) -> Result<Operand, StateError> {
+    let tag = r.read_u8()?;
+    let d = r.read_u16()?;
+    Ok(match tag {
+        0 => Operand::AL,
+        1 => Operand::BL,
+        2 => Operand::CL,
+        3 => Operand::DL,
+        4 => Operand::AH,
+        5 => Operand::BH,
+        6 => Operand::CH,
+        7 => Operand::DH,
+        8 => Operand::AX,
+        9 => Operand::BX,
+        10 => Operand::CX,
+        11 => Operand::DX,
+        12 => Operand::SI,
+        13 => Operand::DI,
+        14 => Operand::BP,
+        15 => Operand::SP,
+        16 => Operand::BXSI,
+        17 => Operand::BXDI,
+        18 => Operand::BPSI,
+        19 => Operand::BPDI,
+        20 => Operand::DispBXSI(d),
+        21 => Operand::DispBXDI(d),
+        22 => Operand::DispBPSI(d),
+        23 => Operand::DispBPDI(d),
+        24 => Operand::DispSI(d),
+        25 => Operand::DispDI(d),
+        26 => Operand::DispBP(d),
+        27 => Operand::DispBX(d),
+        28 => Operand::Disp(d),
+        _ => return Err(StateError::Invalid("operand")),
+    })
+}
+
+fn write_operand_type(w: &mut StateWriter, operand: OperandType) {
+    match operand {
+        OperandType::Register(op) => {
+            w.write_u8(0);
+            write_operand(w, op);
+        },
+        OperandType::SegmentRegister(seg) => {
+            w.write_u8(1);
+            write_segment(w, seg);
+        },
+        OperandType::Memory(op) => {
+            w.write_u8(2);
+            write_operand(w, op);
+        },
+        OperandType::Immediate(imm) => {
+            w.write_u8(3);
+            w.write_u16(imm);
+        },
+        OperandType::None => w.write_u8(4),
+    }
+}
+
+fn read_operand_type(r: &mut StateReader) -> Result<OperandType, StateError> {
+    Ok(match r.read_u8()? {
+        0 => OperandType::Register(read_operand(r)?),
+        1 => OperandType::SegmentRegister(read_segment(r)?),
+        2 => OperandType::Memory(read_operand(r)?),
+        3 => OperandType::Immediate(r.read_u16()?),
+        4 => OperandType::None,
+        _ => return Err(StateError::Invalid("operand type")),
+    })
+}
+
+fn write_jump_type(w: &mut StateWriter, jump_type: JumpType) {
+    let (tag, a, b) = match jump_type {
+        JumpType::DirIntersegment(offset, segment) => (0, offset, segment),
+        JumpType::DirWithinSegment(disp) => (1, disp, 0),
+        JumpType::DirWithinSegmentShort(disp) => (2, disp as u16, 0),
+        JumpType::IndIntersegment => (3, 0, 0),
+        JumpType::IndWithinSegment => (4, 0, 0),
+        JumpType::None => (5, 0, 0),
+    };
+    w.write_u8(tag);
+    w.write_u16(a);
+    w.write_u16(b);
+}
+
+fn read_jump_type(r: &mut StateReader) -> Result<JumpType, StateError> {
+    let tag = r.read_u8()?;
+    let a = r.read_u16()?;
+    let b = r.read_u16()?;
+    Ok(match tag {
+        0 => JumpType::DirIntersegment(a, b),
+        1 => JumpType::DirWithinSegment(a),
+        2 => JumpType::DirWithinSegmentShort(a as u8),
+        3 => JumpType::IndIntersegment,
+        4 => JumpType::IndWithinSegment,
+        5 => JumpType::None,
+        _ => return Err(StateError::Invalid("jump type")),
+    })
+}
+
+fn write_ret_type(w: &mut StateWriter, ret_type: RetType) {
+    let (tag, val) = match ret_type {
+        RetType::NearAdd(val) => (0, val),
+        RetType::Near => (1, 0),
+        RetType::Far => (2, 0),
+        RetType::FarAdd(val) => (3, val),
+        RetType::None => (4, 0),
+    };
+    w.write_u8(tag);
+    w.write_u16(val);
+}
+
+fn read_ret_type(r: &mut StateReader) -> Result<RetType, StateError> {
+    let tag = r.read_u8()?;
+    let val = r.read_u16()?;
+    Ok(match tag {
+        0 => RetType::NearAdd(val),
+        1 => RetType::Near,
+        2 => RetType::Far,
+        3 => RetType::FarAdd(val),
+        4 => RetType::None,
+        _ => return Err(StateError::Invalid("ret type")),
+    })
+}
+
+impl Snapshot for Instruction {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.opcode as u8);
+        write_operand_type(w, self.operand1);
+        write_operand_type(w, self.operand2);
+
+        w.write_u8(match self.direction {
+            Direction::ToReg => 0,
+            Direction::FromReg => 1,
+            Direction::None => 2,
+        });
+        w.write_u8(match self.data_length {
+            Length::Byte => 0,
+            Length::Word => 1,
+            Length::None => 2,
+        });
+        w.write_u8(match self.addr_mode {
+            AddrMode::Mode0 => 0,
+            AddrMode::Mode1 => 1,
+            AddrMode::Mode2 => 2,
+            AddrMode::Mode3 => 3,
+            AddrMode::None => 4,
+        });
+
+        write_segment(w, self.segment);
+        w.write_u16(self.offset);
+        w.write_u32(self.ea_cycles);
+        w.write_u16(self.port);
+        write_jump_type(w, self.jump_type);
+        write_ret_type(w, self.ret_type);
+
+        w.write_u8(match self.repetition_prefix {
+            RepetitionPrefix::None => 0,
+            RepetitionPrefix::REPNEZ => 1,
+            RepetitionPrefix::REPEZ => 2,
+        });
+        w.write_u16(self.resume_ip);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.opcode = *OPCODES.get(r.read_u8()? as usize).ok_or(StateError::Invalid("opcode"))?;
+        self.operand1 = read_operand_type(r)?;
+        self.operand2 = read_operand_type(r)?;
+
+        self.direction = match r.read_u8()? {
+            0 => Direction::ToReg,
+            1 => Direction::FromReg,
+            2 => Direction::None,
+            _ => return Err(StateError::Invalid("direction")),
+        };
+        self.data_length = match r.read_u8()? {
+            0 => Length::Byte,
+            1 => Length::Word,
+            2 => Length::None,
+            _ => return Err(StateError::Invalid("data length")),
+        };
+        self.addr_mode = match r.read_u8()? {
+            0 => AddrMode::Mode0,
+            1 => AddrMode::Mode1,
+            2 => AddrMode::Mode2,
+            3 => AddrMode::Mode3,
+            4 => AddrMode::None,
+            _ => return Err(StateError::Invalid("addressing mode")),
+        };
+
+        self.segment = read_segment(r)?;
+        self.offset = r.read_u16()?;
+        self.ea_cycles = r.read_u32()?;
+        self.port = r.read_u16()?;
+        self.jump_type = read_jump_type(r)?;
+        self.ret_type = read_ret_type(r)?;
+
+        self.repetition_prefix = match r.read_u8()? {
+            0 => RepetitionPrefix::None,
+            1 => RepetitionPrefix::REPNEZ,
+            2 => RepetitionPrefix::REPEZ,
+            _ => return Err(StateError::Invalid("repetition prefix")),
+        };
+        self.resume_ip = r.read_u16()?;
+
+        Ok(())
+    }
+}
+
+impl Snapshot for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        for reg in [self.ax, self.bx, self.cx, self.dx] {
+            w.write_u16(reg.get_x());
+        }
+        for reg in [self.si, self.di, self.bp, self.sp] {
+            w.write_u16(reg);
+        }
+
+        w.write_u16(self.flags.get_flags());
+
+        for seg in [self.cs, self.ds, self.es, self.ss] {
+            w.write_u16(seg);
+        }
+        w.write_u16(self.ip);
+
+        self.instr.save_state(w);
+        w.write_u32(self.cycles);
+
+        w.write_bool(self.nmi);
+        w.write_bool(self.nmi_enabled);
+        w.write_bool(self.sw_int);
+        w.write_u8(self.sw_int_type);
+        w.write_bool(self.trap);
+        w.write_bool(self.int_shadow);
+        w.write_bool(self.halted);
+        w.write_bool(self.to_decode);
+
+        w.write_bool(self.fpu.is_some());
+        if let Some(fpu) = &self.fpu {
+            fpu.save_state(w);
+        }
+
+        w.write_bool(self.biu.is_some());
+        if let Some(biu) = &self.biu {
+            biu.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ax.set_x(r.read_u16()?);
+        self.bx.set_x(r.read_u16()?);
+        self.cx.set_x(r.read_u16()?);
+        self.dx.set_x(r.read_u16()?);
+        self.si = r.read_u16()?;
+        self.di = r.read_u16()?;
+        self.bp = r.read_u16()?;
+        self.sp = r.read_u16()?;
+
+        self.flags.set_flags(r.read_u16()?);
+
+        self.cs = r.read_u16()?;
+        self.ds = r.read_u16()?;
+        self.es = r.read_u16()?;
+        self.ss = r.read_u16()?;
+        self.ip = r.read_u16()?;
+
+        self.instr.load_state(r)?;
+        self.cycles = r.read_u32()?;
+
+        self.nmi = r.read_bool()?;
+        self.nmi_enabled = r.read_bool()?;
+        self.sw_int = r.read_bool()?;
+        self.sw_int_type = r.read_u8()?;
+        self.trap = r.read_bool()?;
+        self.int_shadow = r.read_bool()?;
+        self.halted = r.read_bool()?;
+        self.to_decode = r.read_bool()?;
+
+        self.fpu = if r.read_bool()? {
+            let mut fpu = FPU8087::new();
+            fpu.load_state(r)?;
+            Some(fpu)
+        } else {
+            None
+        };
+        self.fpu_int = self.fpu.as_ref().is_some_and(|fpu| fpu.interrupt());
+
+        self.biu = if r.read_bool()? {
+            let mut biu = BIU::new();
+            biu.load_state(r)?;
+            Some(biu)
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
 #[derive(Default, Clone)]
 pub struct CRTC6845 {
     horizontal_total_reg: u8,               // W
@@ -46,6 +48,24 @@ impl CRTC6845 {
         }
     }
 
+    // Direccion (en caracteres) del primer caracter de la pantalla
+    pub fn start_address(&self) -> usize {
+        ((self.start_addressh_reg as usize) << 8) | self.start_addressl_reg as usize
+    }
+
+    pub fn cursor_address(&self) -> usize {
+        ((self.cursorh_reg as usize) << 8) | self.cursorl_reg as usize
+    }
+
+    // Lineas de inicio y fin del cursor. None si esta oculto
+    pub fn cursor_lines(&self) -> Option<(u8, u8)> {
+        if self.cursor_start_reg & 0x60 == 0x20 {
+            None
+        } else {
+            Some((self.cursor_start_reg & 0x1F, self.cursor_end_reg))
+        }
+    }
+
     pub fn read_reg(&mut self, port: usize) -> u8 {
         match port {
             14 => self.cursorh_reg,
@@ -56,4 +76,54 @@ impl CRTC6845 {
             _ => 0
         }
     }
-}
\ No newline at end of file
+}
+
+impl Snapshot for CRTC6845 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&[
+            self.horizontal_total_reg,
+            self.horizontal_displayed_reg,
+            self.horizontal_sync_pos_reg,
+            self.sync_width_reg,
+            self.vertical_total_reg,
+            self.vertical_total_adjust_reg,
+            self.vertical_displayed_reg,
+            self.vertical_sync_pos_reg,
+            self.interlace_mode_and_skew_reg,
+            self.max_scan_line_address,
+            self.cursor_start_reg,
+            self.cursor_end_reg,
+            self.start_addressh_reg,
+            self.start_addressl_reg,
+            self.cursorh_reg,
+            self.cursorl_reg,
+            self.light_penh_reg,
+            self.light_penl_reg,
+        ]);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        let mut regs = [0; 18];
+        r.read_into(&mut regs, "CRTC registers")?;
+
+        self.horizontal_total_reg = regs[0];
+        self.horizontal_displayed_reg = regs[1];
+        self.horizontal_sync_pos_reg = regs[2];
+        self.sync_width_reg = regs[3];
+        self.vertical_total_reg = regs[4];
+        self.vertical_total_adjust_reg = regs[5];
+        self.vertical_displayed_reg = regs[6];
+        self.vertical_sync_pos_reg = regs[7];
+        self.interlace_mode_and_skew_reg = regs[8];
+        self.max_scan_line_address = regs[9];
+        self.cursor_start_reg = regs[10];
+        self.cursor_end_reg = regs[11];
+        self.start_addressh_reg = regs[12];
+        self.start_addressl_reg = regs[13];
+        self.cursorh_reg = regs[14];
+        self.cursorl_reg = regs[15];
+        self.light_penh_reg = regs[16];
+        self.light_penl_reg = regs[17];
+        Ok(())
+    }
+}
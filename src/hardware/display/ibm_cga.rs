@@ -0,0 +1,294 @@
+use ggez::{graphics::{ImageGeneric, GlBackendSpec, Image, Color}, Context};
+
+use crate::hardware::config::FONT_ROM_SIZE;
+use crate::hardware::peripheral::Peripheral;
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+use super::{DisplayAdapter, Char, crtc6845::CRTC6845};
+
+pub const IMG_WIDTH: usize = 640;
+pub const IMG_HEIGHT: usize = 200;
+const IMG_BUFF_SIZE: usize = IMG_WIDTH * IMG_HEIGHT * 4;
+
+pub const CGA_VRAM_SIZE: usize = 0x4000;
+// Fuente de 8x8 de la CGA dentro de la ROM de caracteres
+const CGA_FONT_OFFSET: usize = 0x1800;
+
+// Bits del registro de modo (0x3D8)
+const MODE_80_COLUMNS: u8 = 0x01;
+const MODE_GRAPHICS: u8 = 0x02;
+const MODE_BW: u8 = 0x04;
+const MODE_ENABLE: u8 = 0x08;
+const MODE_HIRES: u8 = 0x10;
+const MODE_BLINK: u8 = 0x20;
+
+// Temporizacion de la pantalla en ciclos de CPU (NTSC, ~60 Hz)
+const LINE_CYCLES: u32 = 304;
+const DISPLAY_CYCLES: u32 = 160;
+const FRAME_LINES: u32 = 262;
+const DISPLAY_LINES: u32 = 200;
+const VSYNC_START: u32 = 224;
+const VSYNC_END: u32 = 240;
+
+// Colores RGBI
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00), (0x00, 0x00, 0xAA), (0x00, 0xAA, 0x00), (0x00, 0xAA, 0xAA),
+    (0xAA, 0x00, 0x00), (0xAA, 0x00, 0xAA), (0xAA, 0x55, 0x00), (0xAA, 0xAA, 0xAA),
+    (0x55, 0x55, 0x55), (0x55, 0x55, 0xFF), (0x55, 0xFF, 0x55), (0x55, 0xFF, 0xFF),
+    (0xFF, 0x55, 0x55), (0xFF, 0x55, 0xFF), (0xFF, 0xFF, 0x55), (0xFF, 0xFF, 0xFF),
+];
+
+fn color(index: u8) -> Color {
+    let (r, g, b) = PALETTE[index as usize & 0x0F];
+    Color::from_rgb(r, g, b)
+}
+
+#[derive(Clone)]
+pub struct IbmCGA {
+    pub img_buffer: Vec<u8>,
+    pub font: Vec<u8>,
+
+    mode: u8,
+    color_select: u8,
+
+    crtc_adddr_reg: usize,
+    crtc: CRTC6845,
+
+    // Posicion del haz dentro del frame
+    frame_cycles: u32,
+    // Frames dibujados, para el parpadeo
+    frames: u32,
+}
+
+impl IbmCGA {
+    pub fn new() -> IbmCGA {
+        IbmCGA {
+            img_buffer: vec![0x00; IMG_BUFF_SIZE],
+            font: vec![0x00; FONT_ROM_SIZE],
+
+            mode: 0x00,
+            color_select: 0x00,
+
+            crtc_adddr_reg: 0,
+            crtc: CRTC6845::default(),
+
+            frame_cycles: 0,
+            frames: 0,
+        }
+    }
+
+    pub fn update(&mut self, cycles: u32) {
+        self.frame_cycles = (self.frame_cycles + cycles) % (LINE_CYCLES * FRAME_LINES);
+    }
+
+    fn enabled(&self) -> bool {
+        self.mode & MODE_ENABLE > 0
+    }
+
+    fn columns(&self) -> usize {
+        if self.mode & MODE_80_COLUMNS > 0 {80} else {40}
+    }
+
+    fn read_status(&self) -> u8 {
+        let line = self.frame_cycles / LINE_CYCLES;
+        let display = line < DISPLAY_LINES && self.frame_cycles % LINE_CYCLES < DISPLAY_CYCLES;
+
+        let mut status = 0;
+        if !display {
+            status |= 0x01;
+        }
+        if (VSYNC_START..VSYNC_END).contains(&line) {
+            status |= 0x08;
+        }
+        status
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+        let pos = (y * IMG_WIDTH + x) * 4;
+        let (r, g, b, a) = color.to_rgba();
+        self.img_buffer[pos..pos + 4].copy_from_slice(&[r, g, b, a]);
+    }
+
+    // Dibuja la pantalla en img_buffer (640x200)
+    pub fn render(&mut self, vram: &[u8]) {
+        self.frames = self.frames.wrapping_add(1);
+
+        if !self.enabled() {
+            self.img_buffer.fill(0x00);
+        } else if self.mode & MODE_GRAPHICS == 0 {
+            self.render_text(vram);
+        } else if self.mode & MODE_HIRES > 0 {
+            self.render_640(vram);
+        } else {
+            self.render_320(vram);
+        }
+    }
+
+    fn render_text(&mut self, vram: &[u8]) {
+        let columns = self.columns();
+        let start = self.crtc.start_address();
+        let blink_on = self.frames & 0x10 > 0;
+
+        for row in 0..25 {
+            for col in 0..columns {
+                let address = start + row * columns + col;
+                let offset = (address * 2) % CGA_VRAM_SIZE;
+                let attr = vram[offset + 1];
+
+                let mut character = Char::new(vram[offset] as usize);
+                character.foreground_color = color(attr & 0x0F);
+
+                if self.mode & MODE_BLINK > 0 {
+                    character.background_color = color((attr >> 4) & 0x07);
+                    if attr & 0x80 > 0 && !blink_on {
+                        character.foreground_color = character.background_color;
+                    }
+                } else {
+                    character.background_color = color(attr >> 4);
+                }
+
+                // El cursor se dibuja en lugar del subrayado
+                character.underline = address == self.crtc.cursor_address() && self.frames & 0x08 > 0;
+
+                self.render_font(character, col, row);
+            }
+        }
+    }
+
+    // 2 bits por pixel, lineas pares en 0x0000 e impares en 0x2000
+    fn render_320(&mut self, vram: &[u8]) {
+        let background = self.color_select & 0x0F;
+        let intensity = if self.color_select & 0x10 > 0 {8} else {0};
+        let palette = if self.mode & MODE_BW > 0 {
+            [3, 4, 7]
+        } else if self.color_select & 0x20 > 0 {
+            [3, 5, 7]
+        } else {
+            [2, 4, 6]
+        };
+
+        for y in 0..IMG_HEIGHT {
+            let line = (y & 1) * 0x2000 + (y >> 1) * 80;
+
+            for x in 0..320 {
+                let byte = vram[line + x / 4];
+                let index = (byte >> (6 - (x % 4) * 2)) & 0x03;
+
+                let pixel = if index == 0 {
+                    color(background)
+                } else {
+                    color(palette[index as usize - 1] + intensity)
+                };
+
+                self.put_pixel(x * 2, y, pixel);
+                self.put_pixel(x * 2 + 1, y, pixel);
+            }
+        }
+    }
+
+    // 1 bit por pixel, el color de primer plano sale del registro de color
+    fn render_640(&mut self, vram: &[u8]) {
+        let foreground = color(self.color_select & 0x0F);
+
+        for y in 0..IMG_HEIGHT {
+            let line = (y & 1) * 0x2000 + (y >> 1) * 80;
+
+            for x in 0..IMG_WIDTH {
+                let pixel = vram[line + x / 8] & (0x80 >> (x % 8)) > 0;
+                self.put_pixel(x, y, if pixel {foreground} else {Color::BLACK});
+            }
+        }
+    }
+
+    // Devuelve el texto de la pantalla en modo texto, una fila por linea
+    pub fn text_rows(&self, vram: &[u8]) -> Vec<String> {
+        if self.mode & MODE_GRAPHICS > 0 {
+            return Vec::new();
+        }
+
+        let columns = self.columns();
+        let start = self.crtc.start_address();
+
+        (0..25).map(|row| {
+            (0..columns).map(|col| {
+                let v = vram[((start + row * columns + col) * 2) % CGA_VRAM_SIZE];
+                if (0x20..0x7F).contains(&v) {v as char} else {' '}
+            }).collect()
+        }).collect()
+    }
+}
+
+impl Default for IbmCGA {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DisplayAdapter for IbmCGA {
+    fn create_frame(&mut self, ctx: &mut Context, vram: &[u8]) -> ImageGeneric<GlBackendSpec> {
+        self.render(vram);
+        Image::from_rgba8(ctx, IMG_WIDTH as u16, IMG_HEIGHT as u16, &self.img_buffer).unwrap()
+    }
+
+    fn render_font(&mut self, character: Char, width: usize, height: usize) {
+        // En 40 columnas cada pixel se dibuja dos veces
+        let scale = IMG_WIDTH / (self.columns() * 8);
+        let cursor = self.crtc.cursor_lines();
+
+        for i in 0..8 {
+            let char_ = self.font[CGA_FONT_OFFSET + character.index * 8 + i];
+            let in_cursor = character.underline && cursor.is_some_and(|(start, end)| (start as usize..=end as usize).contains(&i));
+
+            for j in 0..8 {
+                let pixel = in_cursor || char_ & (1 << (7 - j)) > 0;
+                let color = if pixel {character.foreground_color} else {character.background_color};
+
+                for k in 0..scale {
+                    self.put_pixel((width * 8 + j) * scale + k, height * 8 + i, color);
+                }
+            }
+        }
+    }
+}
+
+impl Peripheral for IbmCGA {
+    fn port_in(&mut self, port: u16) -> u16 {
+        match port {
+            0x3D0..=0x3D7 if port & 1 == 1 => self.crtc.read_reg(self.crtc_adddr_reg) as u16,
+            0x3DA => self.read_status() as u16,
+            _ => 0,
+        }
+    }
+
+    fn port_out(&mut self, val: u16, port: u16) {
+        match port {
+            // 0x3D4/0x3D5 y sus copias
+            0x3D0..=0x3D7 if port & 1 == 0 => self.crtc_adddr_reg = (val as u8) as usize,
+            0x3D0..=0x3D7 => self.crtc.reg_write(self.crtc_adddr_reg, val as u8),
+            0x3D8 => self.mode = val as u8,
+            0x3D9 => self.color_select = val as u8,
+            _ => {},
+        }
+    }
+}
+
+impl Snapshot for IbmCGA {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.mode);
+        w.write_u8(self.color_select);
+        w.write_u8(self.crtc_adddr_reg as u8);
+        self.crtc.save_state(w);
+        w.write_u32(self.frame_cycles);
+        w.write_u32(self.frames);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.mode = r.read_u8()?;
+        self.color_select = r.read_u8()?;
+        self.crtc_adddr_reg = r.read_u8()? as usize;
+        self.crtc.load_state(r)?;
+        self.frame_cycles = r.read_u32()?;
+        self.frames = r.read_u32()?;
+        Ok(())
+    }
+}
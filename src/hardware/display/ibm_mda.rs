@@ -1,8 +1,8 @@
-use std::io::Read;
-
 use ggez::{graphics::{ImageGeneric, GlBackendSpec, Image}, Context};
 
+use crate::hardware::config::FONT_ROM_SIZE;
 use crate::hardware::peripheral::Peripheral;
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
 
 use super::{DisplayAdapter, Char, crtc6845::CRTC6845};
 
@@ -29,13 +29,10 @@ impl IbmMDA {
     pub fn new() -> IbmMDA {
         // let a: Vec<u8> = (0..IMG_BUFF_SIZE).map(|x| if x % 4 == 3 {0xFF} else {0x00}).collect();
         let a = vec![0x00; IMG_BUFF_SIZE];
-        let mut file = std::fs::File::open("roms/IBM_5788005_AM9264_1981_CGA_MDA_CARD.BIN").unwrap();
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf).unwrap();
 
         IbmMDA {
             img_buffer: a,
-            font: buf,
+            font: vec![0x00; FONT_ROM_SIZE],
 
             crtc_op1: 0b00001001,
             crtc_sp: 0b11111110,
@@ -51,6 +48,19 @@ impl IbmMDA {
     fn enabled(&self) -> bool {
         self.crtc_op1 & 0b00001000 > 0
     }
+
+    // Devuelve el texto de la pantalla, una fila de 80 caracteres por linea
+    pub fn text_rows(&self, vram: &[u8]) -> Vec<String> {
+        vram.chunks(160)
+            .map(|row| row.chunks(2).map(|v| if (0x20..0x7F).contains(&v[0]) {v[0] as char} else {' '}).collect())
+            .collect()
+    }
+}
+
+impl Default for IbmMDA {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DisplayAdapter for IbmMDA {
@@ -134,3 +144,22 @@ impl Peripheral for IbmMDA {
         }
     }
 }
+
+impl Snapshot for IbmMDA {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.crtc_op1);
+        w.write_u8(self.crtc_sp);
+        w.write_u8(self.crtc_adddr_reg as u8);
+        self.crtc.save_state(w);
+        w.write_u8(self.retrace);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.crtc_op1 = r.read_u8()?;
+        self.crtc_sp = r.read_u8()?;
+        self.crtc_adddr_reg = r.read_u8()? as usize;
+        self.crtc.load_state(r)?;
+        self.retrace = r.read_u8()?;
+        Ok(())
+    }
+}
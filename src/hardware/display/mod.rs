@@ -1,6 +1,7 @@
 use ggez::{Context, graphics::{ImageGeneric, GlBackendSpec, Color}};
 
 pub mod ibm_mda;
+pub mod ibm_cga;
 pub mod crtc6845;
 
 pub trait DisplayAdapter {
@@ -0,0 +1,756 @@
+use std::cmp::Ordering;
+
+use super::*;
+
+// Formato de un operando en memoria de las instrucciones aritmeticas (D8, DA, DC, DE)
+#[derive(Clone, Copy)]
+enum MemFormat {
+    Real32,
+    Int32,
+    Real64,
+    Int16,
+}
+
+fn read_bytes<const N: usize>(bus: &Bus, segment: u16, offset: u16) -> [u8; N] {
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = bus.read_8(segment, offset.wrapping_add(i as u16));
+    }
+    bytes
+}
+
+fn write_bytes(bus: &mut Bus, segment: u16, offset: u16, bytes: &[u8]) {
+    for (i, byte) in bytes.iter().enumerate() {
+        bus.write_8(segment, offset.wrapping_add(i as u16), *byte);
+    }
+}
+
+// BCD empaquetado de 18 digitos con el signo en el ultimo byte
+fn from_bcd(bytes: [u8; 10]) -> F80 {
+    let mut val: i64 = 0;
+    for byte in bytes[..9].iter().rev() {
+        val = val * 100 + (byte >> 4) as i64 * 10 + (byte & 0x0F) as i64;
+    }
+
+    let res = F80::from_i64(val);
+    if bytes[9] & 0x80 != 0 {-res} else {res}
+}
+
+fn to_bcd(mut val: u64, sign: bool) -> [u8; 10] {
+    let mut bytes = [0; 10];
+    for byte in bytes[..9].iter_mut() {
+        let low = (val % 10) as u8;
+        val /= 10;
+        let high = (val % 10) as u8;
+        val /= 10;
+        *byte = (high << 4) | low;
+    }
+    bytes[9] = if sign {0x80} else {0x00};
+    bytes
+}
+
+const BCD_INDEFINITE: [u8; 10] = [0, 0, 0, 0, 0, 0, 0, 0xC0, 0xFF, 0xFF];
+const BCD_LIMIT: u128 = 1_000_000_000_000_000_000;
+
+// C3, C2 y C0 de una comparacion
+fn compare_flags(order: Option<Ordering>) -> (bool, bool, bool) {
+    match order {
+        Some(Ordering::Greater) => (false, false, false),
+        Some(Ordering::Less) => (false, false, true),
+        Some(Ordering::Equal) => (true, false, false),
+        None => (true, true, true),
+    }
+}
+
+impl FPU8087 {
+    // Ejecuta una instruccion ESC: esc = (opcode & 7) << 8 | ModR/M.
+    // La linea de interrupcion se mira despues con interrupt
+    pub fn execute(&mut self, bus: &mut Bus, esc: u16, address: Option<(u16, u16)>) {
+        // La instruccion anterior se da por terminada: el programa tendria que haber hecho WAIT
+        self.busy = 0;
+
+        let op = ((esc >> 8) & 0x07) as u8;
+        let modrm = esc as u8;
+        let reg = (modrm >> 3) & 0x07;
+        let rm = (modrm & 0x07) as usize;
+
+        // Las instrucciones de control no cambian los punteros de la ultima instruccion
+        let control = match address {
+            Some(_) => matches!((op, reg), (1, 4..=7) | (5, 4) | (5, 6) | (5, 7)),
+            None => op == 3 && reg == 4,
+        };
+        if !control {
+            self.instr_pointer = self.fetched;
+            self.opcode = esc & 0x07FF;
+            if let Some((segment, offset)) = address {
+                self.operand_pointer = (((segment as u32) << 4) + offset as u32) & 0xFFFFF;
+            }
+        }
+
+        self.busy = match address {
+            Some((segment, offset)) => self.memory_op(bus, op, reg, segment, offset),
+            None => self.register_op(op, reg, rm),
+        };
+    }
+
+    // ST(i), o indefinido con excepcion si esta vacio
+    fn get(&self, i: usize, ex: &mut u8) -> F80 {
+        if self.is_empty(i) {
+            *ex |= INVALID;
+            F80::INDEFINITE
+        } else {
+            self.st(i)
+        }
+    }
+
+    // Sin enmascarar, invalid, division por cero y denormal no escriben resultado
+    fn can_write(&self, ex: u8) -> bool {
+        self.unmasked(ex) & (INVALID | ZERO_DIVIDE | DENORMAL) == 0
+    }
+
+    fn store(&mut self, i: usize, val: F80, ex: u8) -> bool {
+        let write = self.can_write(ex);
+        if write {
+            self.set_st(i, val);
+        }
+        self.raise(ex);
+        write
+    }
+
+    // PUSH con comprobacion de desbordamiento de la pila
+    fn push_checked(&mut self, val: F80, mut ex: u8) {
+        if !self.is_empty(7) {
+            ex |= INVALID;
+        }
+
+        if self.can_write(ex) {
+            self.push(if ex & INVALID != 0 {F80::INDEFINITE} else {val});
+        }
+        self.raise(ex);
+    }
+
+    // Devuelve false si el resultado no se ha escrito por una excepcion
+    fn arith(&mut self, reg: u8, dest: usize, a: F80, b: F80, mut ex: u8) -> bool {
+        let format = self.precision();
+        let rc = self.rounding();
+
+        let res = match reg {
+            0 => add(a, b, format, rc, &mut ex),
+            1 => mul(a, b, format, rc, &mut ex),
+            2 | 3 => {
+                self.compare(a, b, ex);
+                if reg == 3 {
+                    self.pop();
+                }
+                return true;
+            },
+            4 => sub(a, b, format, rc, &mut ex),
+            5 => sub(b, a, format, rc, &mut ex),
+            6 => div(a, b, format, rc, &mut ex),
+            7 => div(b, a, format, rc, &mut ex),
+            _ => unreachable!(),
+        };
+
+        self.store(dest, res, ex)
+    }
+
+    fn compare(&mut self, a: F80, b: F80, mut ex: u8) {
+        if a.is_denormal() || b.is_denormal() {
+            ex |= DENORMAL;
+        }
+
+        let order = compare(a, b);
+        if order.is_none() {
+            ex |= INVALID;
+        }
+
+        let (c3, c2, c0) = compare_flags(order);
+        self.set_condition(c3, c2, false, c0);
+        self.raise(ex);
+    }
+
+    fn load_operand(&self, bus: &Bus, format: MemFormat, segment: u16, offset: u16, ex: &mut u8) -> F80 {
+        match format {
+            MemFormat::Real32 => F80::from_f32_bits(u32::from_le_bytes(read_bytes(bus, segment, offset)), ex),
+            MemFormat::Int32 => F80::from_i64(i32::from_le_bytes(read_bytes(bus, segment, offset)) as i64),
+            MemFormat::Real64 => F80::from_f64_bits(u64::from_le_bytes(read_bytes(bus, segment, offset)), ex),
+            MemFormat::Int16 => F80::from_i64(i16::from_le_bytes(read_bytes(bus, segment, offset)) as i64),
+        }
+    }
+
+    // Valor entero de ST(0) para FIST, None si no cabe en el rango
+    fn integer(&self, val: F80, min: i128, max: i128, ex: &mut u8) -> Option<i128> {
+        if !val.is_finite() {
+            *ex |= INVALID;
+            return None;
+        }
+
+        let (mag, inexact) = val.round_int(self.rounding());
+        let res = if val.sign {-(mag.min(i128::MAX as u128) as i128)} else {mag.min(i128::MAX as u128) as i128};
+        if res < min || res > max {
+            *ex |= INVALID;
+            return None;
+        }
+
+        if inexact {
+            *ex |= PRECISION;
+        }
+        Some(res)
+    }
+
+    // FIST/FISTP de N bytes
+    fn store_integer(&mut self, bus: &mut Bus, segment: u16, offset: u16, bytes: usize, pop: bool) {
+        let mut ex = 0;
+        let val = self.get(0, &mut ex);
+
+        let bits = bytes as u32 * 8;
+        let min = -(1i128 << (bits - 1));
+        let max = (1i128 << (bits - 1)) - 1;
+
+        let res = if ex & INVALID == 0 {self.integer(val, min, max, &mut ex)} else {None};
+        if self.can_write(ex) {
+            // Entero indefinido: el menor negativo
+            let res = res.unwrap_or(min) as i64;
+            write_bytes(bus, segment, offset, &res.to_le_bytes()[..bytes]);
+            if pop {
+                self.pop();
+            }
+        }
+        self.raise(ex);
+    }
+
+    fn store_real(&mut self, bus: &mut Bus, segment: u16, offset: u16, double: bool, pop: bool) {
+        let mut ex = 0;
+        let val = self.get(0, &mut ex);
+        let rc = self.rounding();
+
+        if val.is_denormal() {
+            ex |= DENORMAL;
+        }
+
+        let bytes = if double {
+            val.to_f64_bits(rc, &mut ex).to_le_bytes().to_vec()
+        } else {
+            val.to_f32_bits(rc, &mut ex).to_le_bytes().to_vec()
+        };
+
+        if self.can_write(ex) {
+            write_bytes(bus, segment, offset, &bytes);
+            if pop {
+                self.pop();
+            }
+        }
+        self.raise(ex);
+    }
+
+    fn memory_op(&mut self, bus: &mut Bus, op: u8, reg: u8, segment: u16, offset: u16) -> u32 {
+        let mut ex = 0;
+
+        match (op, reg) {
+            // Aritmetica con el operando en memoria
+            (0 | 2 | 4 | 6, _) => {
+                let format = match op {
+                    0 => MemFormat::Real32,
+                    2 => MemFormat::Int32,
+                    4 => MemFormat::Real64,
+                    6 => MemFormat::Int16,
+                    _ => unreachable!(),
+                };
+                let b = self.load_operand(bus, format, segment, offset, &mut ex);
+                let a = self.get(0, &mut ex);
+                self.arith(reg, 0, a, b, ex);
+
+                match reg {
+                    1 => 130,
+                    2 | 3 => 65,
+                    6 | 7 => 220,
+                    _ => 105,
+                }
+            },
+
+            // FLD
+            (1, 0) | (5, 0) | (3, 5) => {
+                let val = match op {
+                    1 => self.load_operand(bus, MemFormat::Real32, segment, offset, &mut ex),
+                    5 => self.load_operand(bus, MemFormat::Real64, segment, offset, &mut ex),
+                    _ => F80::from_bytes(read_bytes(bus, segment, offset)),
+                };
+                self.push_checked(val, ex);
+                if op == 3 {50} else {45}
+            },
+            // FST/FSTP
+            (1, 2 | 3) => {
+                self.store_real(bus, segment, offset, false, reg == 3);
+                85
+            },
+            (5, 2 | 3) => {
+                self.store_real(bus, segment, offset, true, reg == 3);
+                100
+            },
+            (3, 7) => {
+                let val = self.get(0, &mut ex);
+                if self.can_write(ex) {
+                    write_bytes(bus, segment, offset, &val.to_bytes());
+                    self.pop();
+                }
+                self.raise(ex);
+                55
+            },
+
+            // FILD
+            (3, 0) | (7, 0) | (7, 5) => {
+                let val = match (op, reg) {
+                    (3, 0) => self.load_operand(bus, MemFormat::Int32, segment, offset, &mut ex),
+                    (7, 0) => self.load_operand(bus, MemFormat::Int16, segment, offset, &mut ex),
+                    _ => F80::from_i64(i64::from_le_bytes(read_bytes(bus, segment, offset))),
+                };
+                self.push_checked(val, ex);
+                55
+            },
+            // FIST/FISTP
+            (3, 2 | 3) => {
+                self.store_integer(bus, segment, offset, 4, reg == 3);
+                90
+            },
+            (7, 2 | 3) => {
+                self.store_integer(bus, segment, offset, 2, reg == 3);
+                85
+            },
+            (7, 7) => {
+                self.store_integer(bus, segment, offset, 8, true);
+                100
+            },
+
+            // FBLD/FBSTP
+            (7, 4) => {
+                let val = from_bcd(read_bytes(bus, segment, offset));
+                self.push_checked(val, ex);
+                300
+            },
+            (7, 6) => {
+                let val = self.get(0, &mut ex);
+
+                let bytes = if ex & INVALID != 0 || !val.is_finite() {
+                    ex |= INVALID;
+                    BCD_INDEFINITE
+                } else {
+                    let (mag, inexact) = val.round_int(self.rounding());
+                    if mag >= BCD_LIMIT {
+                        ex |= INVALID;
+                        BCD_INDEFINITE
+                    } else {
+                        if inexact {
+                            ex |= PRECISION;
+                        }
+                        to_bcd(mag as u64, val.sign)
+                    }
+                };
+
+                if self.can_write(ex) {
+                    write_bytes(bus, segment, offset, &bytes);
+                    self.pop();
+                }
+                self.raise(ex);
+                530
+            },
+
+            // Entorno y estado
+            (1, 4) => {
+                self.read_env(bus, segment, offset);
+                45
+            },
+            (1, 5) => {
+                self.control = bus.read_16(segment, offset);
+                self.check_pending();
+                10
+            },
+            (1, 6) => {
+                self.write_env(bus, segment, offset);
+                self.control |= 0x003F;
+                45
+            },
+            (1, 7) => {
+                bus.write_16(segment, offset, self.control);
+                15
+            },
+            (5, 4) => {
+                self.read_env(bus, segment, offset);
+                for i in 0..8 {
+                    let reg_offset = offset.wrapping_add(ENV_SIZE + i as u16 * 10);
+                    let phys = self.physical(i);
+                    self.regs[phys] = F80::from_bytes(read_bytes(bus, segment, reg_offset));
+                }
+                205
+            },
+            (5, 6) => {
+                self.write_env(bus, segment, offset);
+                for i in 0..8 {
+                    let reg_offset = offset.wrapping_add(ENV_SIZE + i as u16 * 10);
+                    write_bytes(bus, segment, reg_offset, &self.st(i).to_bytes());
+                }
+                self.init();
+                205
+            },
+            (5, 7) => {
+                bus.write_16(segment, offset, self.status());
+                15
+            },
+
+            // Reservados
+            _ => 0,
+        }
+    }
+
+    fn register_op(&mut self, op: u8, reg: u8, i: usize) -> u32 {
+        let mut ex = 0;
+
+        match (op, reg) {
+            // Aritmetica con ST(0) como destino
+            (0, _) => {
+                let a = self.get(0, &mut ex);
+                let b = self.get(i, &mut ex);
+                self.arith(reg, 0, a, b, ex);
+                match reg {
+                    1 => 130,
+                    2 | 3 => 45,
+                    6 | 7 => 195,
+                    _ => 85,
+                }
+            },
+            // Aritmetica con ST(i) como destino. SUB/SUBR y DIV/DIVR estan invertidos
+            (4 | 6, _) if !(op == 6 && reg == 3) => {
+                let a = self.get(i, &mut ex);
+                let b = self.get(0, &mut ex);
+                let reg = match reg {
+                    4 => 5,
+                    5 => 4,
+                    6 => 7,
+                    7 => 6,
+                    _ => reg,
+                };
+
+                if reg == 2 || reg == 3 {
+                    // Alias de FCOM/FCOMP
+                    self.arith(reg, 0, b, a, ex);
+                } else {
+                    if self.arith(reg, i, a, b, ex) && op == 6 {
+                        self.pop();
+                    }
+                }
+                match reg {
+                    1 => 130,
+                    2 | 3 => 45,
+                    6 | 7 => 195,
+                    _ => 85,
+                }
+            },
+            // FCOMPP
+            (6, 3) => {
+                if i != 1 {
+                    return 0;
+                }
+                let a = self.get(0, &mut ex);
+                let b = self.get(1, &mut ex);
+                self.compare(a, b, ex);
+                self.pop();
+                self.pop();
+                50
+            },
+
+            // FLD ST(i)
+            (1, 0) => {
+                let val = self.get(i, &mut ex);
+                self.push_checked(val, ex);
+                20
+            },
+            // FXCH
+            (1, 1) | (5, 1) | (7, 1) => {
+                let a = self.get(0, &mut ex);
+                let b = self.get(i, &mut ex);
+                if self.can_write(ex) {
+                    self.set_st(0, b);
+                    self.set_st(i, a);
+                }
+                self.raise(ex);
+                12
+            },
+            // FNOP
+            (1, 2) if i == 0 => 13,
+            // FST/FSTP ST(i)
+            (5, 2 | 3) | (1, 3) | (7, 2 | 3) => {
+                let val = self.get(0, &mut ex);
+                if self.can_write(ex) {
+                    self.set_st(i, val);
+                    if reg == 3 || op != 5 {
+                        self.pop();
+                    }
+                }
+                self.raise(ex);
+                18
+            },
+            // FFREE, y FFREEP sin documentar
+            (5, 0) | (7, 0) => {
+                self.free(i);
+                if op == 7 {
+                    self.pop();
+                }
+                11
+            },
+
+            (1, 4) => self.sign_op(i),
+            (1, 5) => self.load_constant(i),
+            (1, 6) | (1, 7) => self.math_op(reg, i),
+
+            (3, 4) => {
+                match i {
+                    // FENI/FDISI
+                    0 => self.control &= !CONTROL_IEM,
+                    1 => self.control |= CONTROL_IEM,
+                    // FCLEX
+                    2 => self.status &= 0x7F00,
+                    // FINIT
+                    3 => self.init(),
+                    _ => {},
+                }
+                5
+            },
+
+            // Reservados
+            _ => 0,
+        }
+    }
+
+    // FCHS, FABS, FTST y FXAM
+    fn sign_op(&mut self, i: usize) -> u32 {
+        let mut ex = 0;
+
+        match i {
+            0 | 1 => {
+                let val = self.get(0, &mut ex);
+                let res = if i == 0 {-val} else {val.abs()};
+                self.store(0, res, ex);
+                15
+            },
+            4 => {
+                let val = self.get(0, &mut ex);
+                self.compare(val, F80::ZERO, ex);
+                42
+            },
+            5 => {
+                let val = self.st(0);
+                let (c3, c2, c0) = if self.is_empty(0) {
+                    (true, false, true)
+                } else if val.is_nan() {
+                    (false, false, true)
+                } else if val.is_infinite() {
+                    (false, true, true)
+                } else if val.is_zero() {
+                    (true, false, false)
+                } else if val.is_denormal() {
+                    (true, true, false)
+                } else if val.is_unnormal() {
+                    (false, false, false)
+                } else {
+                    (false, true, false)
+                };
+                self.set_condition(c3, c2, val.sign, c0);
+                17
+            },
+            _ => 0,
+        }
+    }
+
+    // FLD1, FLDL2T, FLDL2E, FLDPI, FLDLG2, FLDLN2 y FLDZ
+    fn load_constant(&mut self, i: usize) -> u32 {
+        let val = match i {
+            0 => F80::ONE,
+            1 => F80::L2T,
+            2 => F80::L2E,
+            3 => F80::PI,
+            4 => F80::LG2,
+            5 => F80::LN2,
+            6 => F80::ZERO,
+            _ => return 0,
+        };
+
+        self.push_checked(val, 0);
+        20
+    }
+
+    // Transcendentes y operaciones de D9 F0-FF
+    fn math_op(&mut self, reg: u8, i: usize) -> u32 {
+        let mut ex = 0;
+        let rc = self.rounding();
+
+        match (reg, i) {
+            // F2XM1
+            (6, 0) => {
+                let x = self.get(0, &mut ex);
+                let res = self.transcendental(x, &mut ex, |x| (x * std::f64::consts::LN_2).exp_m1());
+                self.store(0, res, ex);
+                500
+            },
+            // FYL2X
+            (6, 1) => {
+                let x = self.get(0, &mut ex);
+                let y = self.get(1, &mut ex);
+                let res = if x.is_zero() && y.is_finite() && !y.is_zero() {
+                    ex |= ZERO_DIVIDE;
+                    F80::infinity(!y.sign)
+                } else if x.sign && !x.is_zero() && !x.is_nan() {
+                    ex |= INVALID;
+                    F80::INDEFINITE
+                } else {
+                    let y = y.to_f64();
+                    self.transcendental(x, &mut ex, |x| y * x.log2())
+                };
+                self.store(1, res, ex);
+                if self.can_write(ex) {
+                    self.pop();
+                }
+                950
+            },
+            // FPTAN: ST(0) = Y y se mete X, con Y/X = tan(ST(0))
+            (6, 2) => {
+                let x = self.get(0, &mut ex);
+                let res = self.transcendental(x, &mut ex, f64::tan);
+                self.store(0, res, ex);
+                if self.can_write(ex) {
+                    self.push_checked(F80::ONE, 0);
+                }
+                450
+            },
+            // FPATAN
+            (6, 3) => {
+                let x = self.get(0, &mut ex);
+                let y = self.get(1, &mut ex);
+                let yf = y.to_f64();
+                let res = if y.is_nan() {y} else {self.transcendental(x, &mut ex, |x| yf.atan2(x))};
+                self.store(1, res, ex);
+                if self.can_write(ex) {
+                    self.pop();
+                }
+                650
+            },
+            // FXTRACT
+            (6, 4) => {
+                let x = self.get(0, &mut ex);
+                if !self.is_empty(7) {
+                    ex |= INVALID;
+                }
+
+                let (exponent, significand) = if x.is_nan() {
+                    (x, x)
+                } else if x.is_infinite() {
+                    (F80::infinity(false), x)
+                } else if x.is_zero() {
+                    ex |= ZERO_DIVIDE;
+                    (F80::infinity(true), x)
+                } else {
+                    (F80::from_i64(x.exponent() as i64), x.significand())
+                };
+
+                if self.can_write(ex) {
+                    self.set_st(0, exponent);
+                    self.push(significand);
+                }
+                self.raise(ex);
+                50
+            },
+            // FDECSTP/FINCSTP
+            (6, 6) => {
+                self.set_top(self.top() + 7);
+                9
+            },
+            (6, 7) => {
+                self.set_top(self.top() + 1);
+                9
+            },
+
+            // FPREM
+            (7, 0) => {
+                let a = self.get(0, &mut ex);
+                let b = self.get(1, &mut ex);
+
+                if a.is_nan() || b.is_nan() {
+                    let res = if a.is_nan() {a} else {b};
+                    self.store(0, res, ex);
+                } else if !a.is_finite() || b.is_zero() {
+                    self.store(0, F80::INDEFINITE, ex | INVALID);
+                } else if b.is_infinite() || a.is_zero() {
+                    self.set_condition(false, false, false, false);
+                    self.raise(ex);
+                } else {
+                    let (res, quotient, complete) = partial_remainder(a, b);
+                    self.set_condition(quotient & 0x02 != 0, !complete, quotient & 0x01 != 0, quotient & 0x04 != 0);
+                    self.store(0, res, ex);
+                }
+                125
+            },
+            // FYL2XP1
+            (7, 1) => {
+                let x = self.get(0, &mut ex);
+                let y = self.get(1, &mut ex).to_f64();
+                let res = self.transcendental(x, &mut ex, |x| y * x.ln_1p() / std::f64::consts::LN_2);
+                self.store(1, res, ex);
+                if self.can_write(ex) {
+                    self.pop();
+                }
+                850
+            },
+            // FSQRT
+            (7, 2) => {
+                let x = self.get(0, &mut ex);
+                let res = sqrt(x, self.precision(), rc, &mut ex);
+                self.store(0, res, ex);
+                183
+            },
+            // FRNDINT
+            (7, 4) => {
+                let x = self.get(0, &mut ex);
+                let res = if x.is_nan() {x} else {x.round_to_integer(rc, &mut ex)};
+                self.store(0, res, ex);
+                45
+            },
+            // FSCALE
+            (7, 5) => {
+                let x = self.get(0, &mut ex);
+                let n = self.get(1, &mut ex);
+
+                let res = if x.is_nan() || n.is_nan() {
+                    if x.is_nan() {x} else {n}
+                } else if !n.is_finite() {
+                    ex |= INVALID;
+                    F80::INDEFINITE
+                } else {
+                    let (mag, _) = n.round_int(Rounding::Zero);
+                    let mag = mag.min(0x10000) as i32;
+                    x.scale(if n.sign {-mag} else {mag}, rc, &mut ex)
+                };
+                self.store(0, res, ex);
+                35
+            },
+
+            _ => 0,
+        }
+    }
+
+    // Las trascendentes se calculan en doble precision y siempre son inexactas
+    fn transcendental(&self, x: F80, ex: &mut u8, f: impl Fn(f64) -> f64) -> F80 {
+        if x.is_nan() {
+            return x;
+        }
+        if x.is_denormal() {
+            *ex |= DENORMAL;
+        }
+
+        let res = f(x.to_f64());
+        if res.is_nan() {
+            *ex |= INVALID;
+            return F80::INDEFINITE;
+        }
+
+        *ex |= PRECISION;
+        F80::from_f64(res)
+    }
+}
@@ -0,0 +1,733 @@
+use std::cmp::Ordering;
+use std::ops::Neg;
+
+// Excepciones, en los mismos bits que la palabra de estado
+pub const INVALID: u8 = 0x01;
+pub const DENORMAL: u8 = 0x02;
+pub const ZERO_DIVIDE: u8 = 0x04;
+pub const OVERFLOW: u8 = 0x08;
+pub const UNDERFLOW: u8 = 0x10;
+pub const PRECISION: u8 = 0x20;
+
+pub const BIAS: i32 = 16383;
+const EXP_SPECIAL: u16 = 0x7FFF;
+const INTEGER_BIT: u64 = 1 << 63;
+const QUIET_BIT: u64 = 1 << 62;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rounding {
+    Nearest,
+    Down,
+    Up,
+    Zero,
+}
+
+impl Rounding {
+    // Campo RC de la palabra de control
+    pub fn from_control(control: u16) -> Self {
+        match (control >> 10) & 0x03 {
+            0b00 => Rounding::Nearest,
+            0b01 => Rounding::Down,
+            0b10 => Rounding::Up,
+            0b11 => Rounding::Zero,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Formato de destino de un redondeo: bits de mantisa y rango del exponente
+#[derive(Clone, Copy)]
+pub struct Format {
+    pub bits: u32,
+    pub emin: i32,
+    pub emax: i32,
+}
+
+pub const SINGLE: Format = Format { bits: 24, emin: -126, emax: 127 };
+pub const DOUBLE: Format = Format { bits: 53, emin: -1022, emax: 1023 };
+pub const EXTENDED: Format = Format { bits: 64, emin: 1 - BIAS, emax: BIAS };
+
+impl Format {
+    // Registro con el control de precision (PC) de la palabra de control
+    pub fn from_control(control: u16) -> Self {
+        let bits = match (control >> 8) & 0x03 {
+            0b00 => 24,
+            0b10 => 53,
+            _ => 64,
+        };
+        Format { bits, ..EXTENDED }
+    }
+}
+
+// Real temporal de 80 bits: signo, exponente de 15 bits y mantisa con el bit entero explicito
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct F80 {
+    pub sign: bool,
+    pub exp: u16,
+    pub mant: u64,
+}
+
+// Valor finito distinto de cero: mant / 2^63 * 2^exp, con el bit 63 siempre a 1
+#[derive(Clone, Copy)]
+struct Unpacked {
+    sign: bool,
+    exp: i32,
+    mant: u64,
+}
+
+impl F80 {
+    pub const ZERO: F80 = F80 { sign: false, exp: 0, mant: 0 };
+    pub const ONE: F80 = F80 { sign: false, exp: 0x3FFF, mant: INTEGER_BIT };
+    // NaN que se devuelve cuando la excepcion de operacion invalida esta enmascarada
+    pub const INDEFINITE: F80 = F80 { sign: true, exp: EXP_SPECIAL, mant: 0xC000_0000_0000_0000 };
+
+    // Constantes de FLDL2T, FLDL2E, FLDPI, FLDLG2 y FLDLN2
+    pub const L2T: F80 = F80 { sign: false, exp: 0x4000, mant: 0xD49A_784B_CD1B_8AFE };
+    pub const L2E: F80 = F80 { sign: false, exp: 0x3FFF, mant: 0xB8AA_3B29_5C17_F0BC };
+    pub const PI: F80 = F80 { sign: false, exp: 0x4000, mant: 0xC90F_DAA2_2168_C235 };
+    pub const LG2: F80 = F80 { sign: false, exp: 0x3FFD, mant: 0x9A20_9A84_FBCF_F799 };
+    pub const LN2: F80 = F80 { sign: false, exp: 0x3FFE, mant: 0xB172_17F7_D1CF_79AC };
+
+    pub fn zero(sign: bool) -> Self {
+        F80 { sign, ..F80::ZERO }
+    }
+
+    pub fn infinity(sign: bool) -> Self {
+        F80 { sign, exp: EXP_SPECIAL, mant: INTEGER_BIT }
+    }
+
+    pub fn from_bytes(bytes: [u8; 10]) -> Self {
+        let mut mant = [0; 8];
+        mant.copy_from_slice(&bytes[..8]);
+        let se = u16::from_le_bytes([bytes[8], bytes[9]]);
+
+        F80 {
+            sign: se & 0x8000 != 0,
+            exp: se & 0x7FFF,
+            mant: u64::from_le_bytes(mant),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 10] {
+        let mut bytes = [0; 10];
+        bytes[..8].copy_from_slice(&self.mant.to_le_bytes());
+        let se = self.exp | if self.sign {0x8000} else {0};
+        bytes[8..].copy_from_slice(&se.to_le_bytes());
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.exp == 0 && self.mant == 0
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.exp == EXP_SPECIAL && self.mant << 1 != 0
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        self.exp == EXP_SPECIAL && self.mant << 1 == 0
+    }
+
+    pub fn is_denormal(&self) -> bool {
+        self.exp == 0 && self.mant != 0
+    }
+
+    // Exponente distinto de cero sin el bit entero
+    pub fn is_unnormal(&self) -> bool {
+        self.exp != 0 && self.exp != EXP_SPECIAL && self.mant & INTEGER_BIT == 0
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.exp != EXP_SPECIAL
+    }
+
+    pub fn abs(self) -> Self {
+        F80 { sign: false, ..self }
+    }
+
+    fn unpack(self) -> Unpacked {
+        let mut exp = if self.exp == 0 {1 - BIAS} else {self.exp as i32 - BIAS};
+        let lz = self.mant.leading_zeros();
+        exp -= lz as i32;
+
+        Unpacked { sign: self.sign, exp, mant: self.mant << lz }
+    }
+
+    fn quiet(self) -> Self {
+        F80 { mant: self.mant | QUIET_BIT | INTEGER_BIT, ..self }
+    }
+
+    fn signaling(&self) -> bool {
+        self.is_nan() && self.mant & QUIET_BIT == 0
+    }
+
+    // Exponente sin sesgo de un numero finito distinto de cero
+    pub fn exponent(&self) -> i32 {
+        self.unpack().exp
+    }
+
+    // Mismo numero con el exponente cambiado, el valor queda en [1, 2)
+    pub fn significand(self) -> Self {
+        let u = self.unpack();
+        F80 { sign: u.sign, exp: BIAS as u16, mant: u.mant }
+    }
+
+    pub fn from_i64(val: i64) -> Self {
+        if val == 0 {
+            return F80::ZERO;
+        }
+
+        let mag = val.unsigned_abs();
+        let lz = mag.leading_zeros();
+        F80 {
+            sign: val < 0,
+            exp: (BIAS + 63 - lz as i32) as u16,
+            mant: mag << lz,
+        }
+    }
+
+    pub fn from_f32_bits(bits: u32, ex: &mut u8) -> Self {
+        let sign = bits & 0x8000_0000 != 0;
+        let exp = ((bits >> 23) & 0xFF) as i32;
+        let frac = (bits & 0x7F_FFFF) as u64;
+
+        match exp {
+            0xFF if frac == 0 => F80::infinity(sign),
+            0xFF => F80 { sign, exp: EXP_SPECIAL, mant: INTEGER_BIT | (frac << 40) },
+            0 if frac == 0 => F80::zero(sign),
+            0 => {
+                *ex |= DENORMAL;
+                F80::normalized(sign, -126 - 23 + 63, frac)
+            },
+            _ => F80 { sign, exp: (exp - 127 + BIAS) as u16, mant: INTEGER_BIT | (frac << 40) },
+        }
+    }
+
+    pub fn from_f64_bits(bits: u64, ex: &mut u8) -> Self {
+        let sign = bits & 0x8000_0000_0000_0000 != 0;
+        let exp = ((bits >> 52) & 0x7FF) as i32;
+        let frac = bits & 0xF_FFFF_FFFF_FFFF;
+
+        match exp {
+            0x7FF if frac == 0 => F80::infinity(sign),
+            0x7FF => F80 { sign, exp: EXP_SPECIAL, mant: INTEGER_BIT | (frac << 11) },
+            0 if frac == 0 => F80::zero(sign),
+            0 => {
+                *ex |= DENORMAL;
+                F80::normalized(sign, -1022 - 52 + 63, frac)
+            },
+            _ => F80 { sign, exp: (exp - 1023 + BIAS) as u16, mant: INTEGER_BIT | (frac << 11) },
+        }
+    }
+
+    pub fn from_f64(val: f64) -> Self {
+        F80::from_f64_bits(val.to_bits(), &mut 0)
+    }
+
+    // Valor exacto mant / 2^63 * 2^exp
+    fn normalized(sign: bool, exp: i32, mant: u64) -> Self {
+        let lz = mant.leading_zeros();
+        let biased = exp - lz as i32 + BIAS;
+
+        if biased <= 0 {
+            // Demasiado pequeno, queda como denormal
+            let shift = (1 - biased) as u32;
+            let mant = if shift >= 64 {0} else {(mant << lz) >> shift};
+            return F80 { sign, exp: 0, mant };
+        }
+        F80 { sign, exp: biased as u16, mant: mant << lz }
+    }
+
+    pub fn to_f32_bits(self, rc: Rounding, ex: &mut u8) -> u32 {
+        let sign = if self.sign {0x8000_0000} else {0};
+
+        if self.is_nan() {
+            return sign | 0x7FC0_0000 | ((self.mant >> 40) as u32 & 0x7F_FFFF);
+        }
+        if self.is_infinite() {
+            return sign | 0x7F80_0000;
+        }
+        if self.is_zero() {
+            return sign;
+        }
+
+        let r = self.round_to(SINGLE, rc, ex);
+        if r.is_infinite() {
+            return sign | 0x7F80_0000;
+        }
+        if r.is_zero() {
+            return sign;
+        }
+
+        let e = r.exp as i32 - BIAS;
+        if e >= SINGLE.emin {
+            sign | (((e + 127) as u32) << 23) | ((r.mant >> 40) as u32 & 0x7F_FFFF)
+        } else {
+            sign | (r.mant >> (40 + SINGLE.emin - e)) as u32
+        }
+    }
+
+    pub fn to_f64_bits(self, rc: Rounding, ex: &mut u8) -> u64 {
+        let sign = if self.sign {0x8000_0000_0000_0000} else {0};
+
+        if self.is_nan() {
+            return sign | 0x7FF8_0000_0000_0000 | ((self.mant >> 11) & 0xF_FFFF_FFFF_FFFF);
+        }
+        if self.is_infinite() {
+            return sign | 0x7FF0_0000_0000_0000;
+        }
+        if self.is_zero() {
+            return sign;
+        }
+
+        let r = self.round_to(DOUBLE, rc, ex);
+        if r.is_infinite() {
+            return sign | 0x7FF0_0000_0000_0000;
+        }
+        if r.is_zero() {
+            return sign;
+        }
+
+        let e = r.exp as i32 - BIAS;
+        if e >= DOUBLE.emin {
+            sign | (((e + 1023) as u64) << 52) | ((r.mant >> 11) & 0xF_FFFF_FFFF_FFFF)
+        } else {
+            sign | (r.mant >> (11 + DOUBLE.emin - e))
+        }
+    }
+
+    // Para las funciones trascendentes, que se calculan en doble precision
+    pub fn to_f64(self) -> f64 {
+        f64::from_bits(self.to_f64_bits(Rounding::Nearest, &mut 0))
+    }
+
+    // Redondea un valor finito al formato indicado
+    pub fn round_to(self, format: Format, rc: Rounding, ex: &mut u8) -> Self {
+        if !self.is_finite() || self.is_zero() {
+            return self;
+        }
+
+        let u = self.unpack();
+        round(u.sign, u.exp, (u.mant as u128) << 64, false, format, rc, ex)
+    }
+
+    // Redondea a entero. Devuelve la magnitud y si se ha perdido precision
+    pub fn round_int(self, rc: Rounding) -> (u128, bool) {
+        if self.is_zero() {
+            return (0, false);
+        }
+
+        let u = self.unpack();
+        if u.exp >= 63 {
+            // Ya es entero. Los que no caben en 64 bits se saturan
+            return if u.exp < 127 {((u.mant as u128) << (u.exp - 63), false)} else {(u128::MAX, false)};
+        }
+
+        let (kept, order, inexact) = if u.exp < -1 {
+            (0, Ordering::Less, true)
+        } else if u.exp == -1 {
+            (0, if u.mant == INTEGER_BIT {Ordering::Equal} else {Ordering::Greater}, true)
+        } else {
+            let shift = (63 - u.exp) as u32;
+            let rest = u.mant & ((1 << shift) - 1);
+            (u.mant >> shift, rest.cmp(&(1 << (shift - 1))), rest != 0)
+        };
+
+        let up = match rc {
+            Rounding::Nearest => order == Ordering::Greater || (order == Ordering::Equal && kept & 1 == 1),
+            Rounding::Up => inexact && !u.sign,
+            Rounding::Down => inexact && u.sign,
+            Rounding::Zero => false,
+        };
+
+        (kept as u128 + up as u128, inexact)
+    }
+
+    // FRNDINT
+    pub fn round_to_integer(self, rc: Rounding, ex: &mut u8) -> Self {
+        if !self.is_finite() || self.is_zero() || self.exponent() >= 63 {
+            return self;
+        }
+
+        let (mag, inexact) = self.round_int(rc);
+        if inexact {
+            *ex |= PRECISION;
+        }
+
+        if mag == 0 {
+            return F80::zero(self.sign);
+        }
+        let lz = (mag as u64).leading_zeros();
+        if mag >> 64 != 0 {
+            return F80 { sign: self.sign, exp: (BIAS + 64) as u16, mant: INTEGER_BIT };
+        }
+        F80 { sign: self.sign, exp: (BIAS + 63 - lz as i32) as u16, mant: (mag as u64) << lz }
+    }
+
+    // Multiplica por 2^n (FSCALE)
+    pub fn scale(self, n: i32, rc: Rounding, ex: &mut u8) -> Self {
+        if !self.is_finite() || self.is_zero() {
+            return self;
+        }
+
+        let u = self.unpack();
+        round(u.sign, u.exp.saturating_add(n), (u.mant as u128) << 64, false, EXTENDED, rc, ex)
+    }
+}
+
+// Redondea mant * 2^(exp - 127) al formato. Si sticky hay bits a 1 por debajo de mant
+fn round(sign: bool, exp: i32, mant: u128, sticky: bool, format: Format, rc: Rounding, ex: &mut u8) -> F80 {
+    if mant == 0 {
+        if sticky {
+            // Solo quedan bits perdidos: es menor que cualquier denormal
+            *ex |= UNDERFLOW | PRECISION;
+            return match rc {
+                Rounding::Up if !sign => tiny(format, sign),
+                Rounding::Down if sign => tiny(format, sign),
+                _ => F80::zero(sign),
+            };
+        }
+        return F80::zero(sign);
+    }
+
+    let lz = mant.leading_zeros();
+    let mut mant = mant << lz;
+    let mut exp = exp - lz as i32;
+    let mut sticky = sticky;
+
+    // Denormal: se desplaza hasta el exponente minimo
+    let is_tiny = exp < format.emin;
+    if is_tiny {
+        let shift = (format.emin - exp) as u32;
+        if shift >= 128 {
+            sticky |= mant != 0;
+            mant = 0;
+        } else {
+            sticky |= mant & ((1 << shift) - 1) != 0;
+            mant >>= shift;
+        }
+        exp = format.emin;
+    }
+
+    let shift = 128 - format.bits;
+    let rest = mant & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let mut kept = mant >> shift;
+    let inexact = rest != 0 || sticky;
+
+    let up = match rc {
+        Rounding::Nearest => rest > half || (rest == half && (sticky || kept & 1 == 1)),
+        Rounding::Up => inexact && !sign,
+        Rounding::Down => inexact && sign,
+        Rounding::Zero => false,
+    };
+
+    if up {
+        kept += 1;
+        if kept >> format.bits != 0 {
+            kept >>= 1;
+            exp += 1;
+        }
+    }
+
+    if inexact {
+        *ex |= PRECISION;
+        if is_tiny {
+            *ex |= UNDERFLOW;
+        }
+    }
+
+    if exp > format.emax {
+        *ex |= OVERFLOW | PRECISION;
+        let to_infinity = match rc {
+            Rounding::Nearest => true,
+            Rounding::Up => !sign,
+            Rounding::Down => sign,
+            Rounding::Zero => false,
+        };
+
+        return if to_infinity {
+            F80::infinity(sign)
+        } else {
+            let mant = (((1u128 << format.bits) - 1) << (64 - format.bits)) as u64;
+            F80 { sign, exp: (format.emax + BIAS) as u16, mant }
+        };
+    }
+
+    if kept == 0 {
+        return F80::zero(sign);
+    }
+
+    let mant = (kept << (64 - format.bits)) as u64;
+    if mant & INTEGER_BIT == 0 && format.emin == EXTENDED.emin {
+        // Denormal del propio formato de 80 bits
+        return F80 { sign, exp: 0, mant };
+    }
+
+    F80::normalized(sign, exp, mant)
+}
+
+// El menor denormal del formato
+fn tiny(format: Format, sign: bool) -> F80 {
+    let mant = 1u64 << (64 - format.bits);
+    if format.emin == EXTENDED.emin {
+        F80 { sign, exp: 0, mant }
+    } else {
+        F80::normalized(sign, format.emin, mant)
+    }
+}
+
+// NaN resultado de una operacion con algun NaN. Los NaN signaling provocan excepcion
+fn propagate_nan(a: F80, b: F80, ex: &mut u8) -> F80 {
+    if a.signaling() || b.signaling() {
+        *ex |= INVALID;
+    }
+
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => if a.mant >= b.mant {a.quiet()} else {b.quiet()},
+        (true, false) => a.quiet(),
+        _ => b.quiet(),
+    }
+}
+
+fn check_denormal(a: F80, b: F80, ex: &mut u8) {
+    if a.is_denormal() || b.is_denormal() || a.is_unnormal() || b.is_unnormal() {
+        *ex |= DENORMAL;
+    }
+}
+
+pub fn add(a: F80, b: F80, format: Format, rc: Rounding, ex: &mut u8) -> F80 {
+    if a.is_nan() || b.is_nan() {
+        return propagate_nan(a, b, ex);
+    }
+    if a.is_infinite() || b.is_infinite() {
+        if a.is_infinite() && b.is_infinite() && a.sign != b.sign {
+            *ex |= INVALID;
+            return F80::INDEFINITE;
+        }
+        return if a.is_infinite() {a} else {b};
+    }
+
+    check_denormal(a, b, ex);
+
+    if a.is_zero() && b.is_zero() {
+        let sign = if a.sign == b.sign {a.sign} else {rc == Rounding::Down};
+        return F80::zero(sign);
+    }
+    if a.is_zero() {
+        return b.round_to(format, rc, ex);
+    }
+    if b.is_zero() {
+        return a.round_to(format, rc, ex);
+    }
+
+    let (mut x, mut y) = (a.unpack(), b.unpack());
+    if (x.exp, x.mant) < (y.exp, y.mant) {
+        std::mem::swap(&mut x, &mut y);
+    }
+
+    // 2 bits libres arriba para el acarreo
+    let big = (x.mant as u128) << 62;
+    let mut small = (y.mant as u128) << 62;
+    let mut sticky = false;
+
+    let diff = (x.exp - y.exp) as u32;
+    if diff >= 126 {
+        sticky = true;
+        small = 0;
+    } else if diff > 0 {
+        sticky = small & ((1 << diff) - 1) != 0;
+        small >>= diff;
+    }
+
+    let sum = if x.sign == y.sign {
+        big + small
+    } else {
+        let res = big - small - sticky as u128;
+        if res == 0 && !sticky {
+            return F80::zero(rc == Rounding::Down);
+        }
+        res
+    };
+
+    round(x.sign, x.exp + 2, sum, sticky, format, rc, ex)
+}
+
+pub fn sub(a: F80, b: F80, format: Format, rc: Rounding, ex: &mut u8) -> F80 {
+    if b.is_nan() {
+        return propagate_nan(a, b, ex);
+    }
+    add(a, -b, format, rc, ex)
+}
+
+pub fn mul(a: F80, b: F80, format: Format, rc: Rounding, ex: &mut u8) -> F80 {
+    if a.is_nan() || b.is_nan() {
+        return propagate_nan(a, b, ex);
+    }
+
+    let sign = a.sign != b.sign;
+    if a.is_infinite() || b.is_infinite() {
+        if a.is_zero() || b.is_zero() {
+            *ex |= INVALID;
+            return F80::INDEFINITE;
+        }
+        return F80::infinity(sign);
+    }
+
+    check_denormal(a, b, ex);
+
+    if a.is_zero() || b.is_zero() {
+        return F80::zero(sign);
+    }
+
+    let (x, y) = (a.unpack(), b.unpack());
+    let product = x.mant as u128 * y.mant as u128;
+    round(sign, x.exp + y.exp + 1, product, false, format, rc, ex)
+}
+
+pub fn div(a: F80, b: F80, format: Format, rc: Rounding, ex: &mut u8) -> F80 {
+    if a.is_nan() || b.is_nan() {
+        return propagate_nan(a, b, ex);
+    }
+
+    let sign = a.sign != b.sign;
+    if a.is_infinite() {
+        if b.is_infinite() {
+            *ex |= INVALID;
+            return F80::INDEFINITE;
+        }
+        return F80::infinity(sign);
+    }
+    if b.is_infinite() {
+        return F80::zero(sign);
+    }
+    if b.is_zero() {
+        if a.is_zero() {
+            *ex |= INVALID;
+            return F80::INDEFINITE;
+        }
+        *ex |= ZERO_DIVIDE;
+        return F80::infinity(sign);
+    }
+
+    check_denormal(a, b, ex);
+
+    if a.is_zero() {
+        return F80::zero(sign);
+    }
+
+    let (x, y) = (a.unpack(), b.unpack());
+    let divisor = y.mant as u128;
+    let dividend = (x.mant as u128) << 64;
+
+    // 2 bits mas de cociente para poder redondear bien
+    let (q, r) = (dividend / divisor, dividend % divisor);
+    let (q2, r2) = ((r << 2) / divisor, (r << 2) % divisor);
+    let quotient = (q << 2) | q2;
+
+    round(sign, x.exp - y.exp + 61, quotient, r2 != 0, format, rc, ex)
+}
+
+pub fn sqrt(a: F80, format: Format, rc: Rounding, ex: &mut u8) -> F80 {
+    if a.is_nan() {
+        return propagate_nan(a, a, ex);
+    }
+    if a.is_zero() {
+        return a;
+    }
+    if a.sign {
+        *ex |= INVALID;
+        return F80::INDEFINITE;
+    }
+    if a.is_infinite() {
+        return a;
+    }
+
+    check_denormal(a, a, ex);
+
+    // a = m * 2^k con k par
+    let x = a.unpack();
+    let mut k = x.exp - 63;
+    let mut m = x.mant as u128;
+    if k & 1 != 0 {
+        m <<= 1;
+        k -= 1;
+    }
+
+    // Raiz bit a bit: 33 pares de m y 34 pares de ceros mas
+    const EXTRA: i32 = 34;
+    let mut rem: u128 = 0;
+    let mut root: u128 = 0;
+    for i in 0..(33 + EXTRA) {
+        let pair = if i < 33 {(m >> (2 * (32 - i))) & 0x03} else {0};
+        rem = (rem << 2) | pair;
+        let trial = (root << 2) | 1;
+        root <<= 1;
+        if rem >= trial {
+            rem -= trial;
+            root |= 1;
+        }
+    }
+
+    round(false, k / 2 - EXTRA + 127, root, rem != 0, format, rc, ex)
+}
+
+// Comparacion de FCOM/FTST. None si alguno es NaN
+pub fn compare(a: F80, b: F80) -> Option<Ordering> {
+    if a.is_nan() || b.is_nan() {
+        return None;
+    }
+    if a.is_zero() && b.is_zero() {
+        return Some(Ordering::Equal);
+    }
+    if a.sign != b.sign {
+        return Some(if a.sign {Ordering::Less} else {Ordering::Greater});
+    }
+
+    let magnitude = |v: F80| -> (i32, u64) {
+        if v.is_zero() {
+            (i32::MIN, 0)
+        } else if v.is_infinite() {
+            (i32::MAX, 0)
+        } else {
+            let u = v.unpack();
+            (u.exp, u.mant)
+        }
+    };
+
+    let order = magnitude(a).cmp(&magnitude(b));
+    Some(if a.sign {order.reverse()} else {order})
+}
+
+// Resto parcial de FPREM: resto, bits bajos del cociente y si esta completo
+pub fn partial_remainder(a: F80, b: F80) -> (F80, u64, bool) {
+    let (x, y) = (a.unpack(), b.unpack());
+    let diff = x.exp - y.exp;
+
+    if diff < 0 {
+        return (a, 0, true);
+    }
+
+    // Como mucho se reducen 63 bits de exponente cada vez
+    let (shift, exp, complete) = if diff < 64 {
+        (diff as u32, y.exp, true)
+    } else {
+        (63, x.exp - 63, false)
+    };
+
+    let dividend = (x.mant as u128) << shift;
+    let divisor = y.mant as u128;
+    let quotient = (dividend / divisor) as u64;
+    let rem = (dividend % divisor) as u64;
+
+    let res = if rem == 0 {F80::zero(a.sign)} else {F80::normalized(a.sign, exp, rem)};
+    (res, quotient, complete)
+}
+
+impl Neg for F80 {
+    type Output = F80;
+
+    fn neg(self) -> F80 {
+        F80 { sign: !self.sign, ..self }
+    }
+}
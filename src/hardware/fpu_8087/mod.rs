@@ -0,0 +1,259 @@
+pub mod float80;
+mod execute;
+
+use crate::hardware::bus::Bus;
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+use float80::*;
+
+// Bits de la palabra de estado
+const STATUS_IR: u16 = 0x0080;
+const STATUS_C0: u16 = 0x0100;
+const STATUS_C1: u16 = 0x0200;
+const STATUS_C2: u16 = 0x0400;
+const STATUS_C3: u16 = 0x4000;
+const STATUS_BUSY: u16 = 0x8000;
+const STATUS_TOP_SHIFT: u16 = 11;
+
+// Bit IEM de la palabra de control (FDISI/FENI), solo existe en el 8087
+const CONTROL_IEM: u16 = 0x0080;
+const CONTROL_INIT: u16 = 0x03FF;
+
+// Etiquetas de cada registro
+const TAG_VALID: u16 = 0b00;
+const TAG_ZERO: u16 = 0b01;
+const TAG_SPECIAL: u16 = 0b10;
+const TAG_EMPTY: u16 = 0b11;
+
+pub const ENV_SIZE: u16 = 14;
+
+#[derive(Clone)]
+pub struct FPU8087 {
+    // Registros fisicos, ST(i) es regs[(top + i) & 7]
+    regs: [F80; 8],
+    pub control: u16,
+    status: u16,
+    tag: u16,
+
+    // Direccion (20 bits) y opcode (11 bits) de la ultima instruccion y de su operando
+    instr_pointer: u32,
+    opcode: u16,
+    operand_pointer: u32,
+    // Direccion de la instruccion que se esta decodificando
+    fetched: u32,
+
+    // Ciclos que le quedan a la instruccion en curso
+    pub busy: u32,
+}
+
+impl FPU8087 {
+    pub fn new() -> Self {
+        FPU8087 {
+            regs: [F80::ZERO; 8],
+            control: CONTROL_INIT,
+            status: 0x0000,
+            tag: 0xFFFF,
+
+            instr_pointer: 0,
+            opcode: 0,
+            operand_pointer: 0,
+            fetched: 0,
+
+            busy: 0,
+        }
+    }
+
+    pub fn init(&mut self) {
+        self.control = CONTROL_INIT;
+        self.status = 0x0000;
+        self.tag = 0xFFFF;
+        self.instr_pointer = 0;
+        self.opcode = 0;
+        self.operand_pointer = 0;
+    }
+
+    // Palabra de estado como la leeria FSTSW
+    pub fn status(&self) -> u16 {
+        let busy = if self.busy > 0 {STATUS_BUSY} else {0};
+        self.status | busy
+    }
+
+    pub fn top(&self) -> usize {
+        ((self.status >> STATUS_TOP_SHIFT) & 0x07) as usize
+    }
+
+    fn set_top(&mut self, top: usize) {
+        self.status = (self.status & !(0x07 << STATUS_TOP_SHIFT)) | ((top as u16 & 0x07) << STATUS_TOP_SHIFT);
+    }
+
+    fn physical(&self, i: usize) -> usize {
+        (self.top() + i) & 0x07
+    }
+
+    fn tag_of(&self, phys: usize) -> u16 {
+        (self.tag >> (phys * 2)) & 0x03
+    }
+
+    fn set_tag(&mut self, phys: usize, tag: u16) {
+        self.tag = (self.tag & !(0x03 << (phys * 2))) | (tag << (phys * 2));
+    }
+
+    pub fn is_empty(&self, i: usize) -> bool {
+        self.tag_of(self.physical(i)) == TAG_EMPTY
+    }
+
+    // Valor de ST(i) aunque este vacio
+    pub fn st(&self, i: usize) -> F80 {
+        self.regs[self.physical(i)]
+    }
+
+    pub fn set_st(&mut self, i: usize, val: F80) {
+        let phys = self.physical(i);
+        self.regs[phys] = val;
+
+        let tag = if val.is_zero() {
+            TAG_ZERO
+        } else if !val.is_finite() || val.is_denormal() || val.is_unnormal() {
+            TAG_SPECIAL
+        } else {
+            TAG_VALID
+        };
+        self.set_tag(phys, tag);
+    }
+
+    fn free(&mut self, i: usize) {
+        let phys = self.physical(i);
+        self.set_tag(phys, TAG_EMPTY);
+    }
+
+    pub fn push(&mut self, val: F80) {
+        let top = (self.top() + 7) & 0x07;
+        self.set_top(top);
+        self.set_st(0, val);
+    }
+
+    pub fn pop(&mut self) {
+        self.free(0);
+        self.set_top(self.top() + 1);
+    }
+
+    fn set_condition(&mut self, c3: bool, c2: bool, c1: bool, c0: bool) {
+        self.status &= !(STATUS_C0 | STATUS_C1 | STATUS_C2 | STATUS_C3);
+        if c0 { self.status |= STATUS_C0; }
+        if c1 { self.status |= STATUS_C1; }
+        if c2 { self.status |= STATUS_C2; }
+        if c3 { self.status |= STATUS_C3; }
+    }
+
+    // Excepciones no enmascaradas en ex
+    fn unmasked(&self, ex: u8) -> u8 {
+        ex & !(self.control as u8) & 0x3F
+    }
+
+    // Marca las excepciones. Las no enmascaradas activan IR
+    fn raise(&mut self, ex: u8) {
+        self.status |= ex as u16;
+        if self.unmasked(ex) != 0 {
+            self.status |= STATUS_IR;
+        }
+    }
+
+    // Alguna excepcion pendiente que ahora no esta enmascarada
+    fn check_pending(&mut self) {
+        if self.unmasked(self.status as u8) != 0 {
+            self.status |= STATUS_IR;
+        }
+    }
+
+    // Linea INT del 8087, que en el PC va a la NMI
+    pub fn interrupt(&self) -> bool {
+        self.status & STATUS_IR != 0 && self.control & CONTROL_IEM == 0
+    }
+
+    pub fn rounding(&self) -> Rounding {
+        Rounding::from_control(self.control)
+    }
+
+    pub fn precision(&self) -> Format {
+        Format::from_control(self.control)
+    }
+
+    // El 8088 avisa de cada ESC que pasa por la cola
+    pub fn fetched(&mut self, address: u32) {
+        self.fetched = address & 0xFFFFF;
+    }
+
+    pub fn elapse(&mut self, cycles: u32) {
+        self.busy = self.busy.saturating_sub(cycles);
+    }
+
+    fn write_env(&self, bus: &mut Bus, segment: u16, offset: u16) {
+        let words = [
+            self.control,
+            self.status(),
+            self.tag,
+            self.instr_pointer as u16,
+            ((self.instr_pointer >> 4) & 0xF000) as u16 | (self.opcode & 0x07FF),
+            self.operand_pointer as u16,
+            ((self.operand_pointer >> 4) & 0xF000) as u16,
+        ];
+
+        for (i, word) in words.iter().enumerate() {
+            bus.write_16(segment, offset.wrapping_add(i as u16 * 2), *word);
+        }
+    }
+
+    fn read_env(&mut self, bus: &mut Bus, segment: u16, offset: u16) {
+        let word = |i: u16| bus.read_16(segment, offset.wrapping_add(i * 2));
+
+        self.control = word(0);
+        self.status = word(1) & !STATUS_BUSY;
+        self.tag = word(2);
+        self.instr_pointer = word(3) as u32 | ((word(4) as u32 & 0xF000) << 4);
+        self.opcode = word(4) & 0x07FF;
+        self.operand_pointer = word(5) as u32 | ((word(6) as u32 & 0xF000) << 4);
+
+        self.check_pending();
+    }
+}
+
+impl Default for FPU8087 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Snapshot for FPU8087 {
+    fn save_state(&self, w: &mut StateWriter) {
+        for reg in &self.regs {
+            w.write_bytes(&reg.to_bytes());
+        }
+        w.write_u16(self.control);
+        w.write_u16(self.status);
+        w.write_u16(self.tag);
+
+        w.write_u32(self.instr_pointer);
+        w.write_u16(self.opcode);
+        w.write_u32(self.operand_pointer);
+        w.write_u32(self.fetched);
+        w.write_u32(self.busy);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for reg in &mut self.regs {
+            let mut bytes = [0; 10];
+            r.read_into(&mut bytes, "FPU register")?;
+            *reg = F80::from_bytes(bytes);
+        }
+        self.control = r.read_u16()?;
+        self.status = r.read_u16()?;
+        self.tag = r.read_u16()?;
+
+        self.instr_pointer = r.read_u32()?;
+        self.opcode = r.read_u16()?;
+        self.operand_pointer = r.read_u32()?;
+        self.fetched = r.read_u32()?;
+        self.busy = r.read_u32()?;
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 pub mod sys;
 pub mod cpu_8088;
+pub mod fpu_8087;
 pub mod bus;
 pub mod peripheral;
 pub mod display;
+pub mod config;
+pub mod state;
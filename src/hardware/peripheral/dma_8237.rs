@@ -1,72 +1,374 @@
 use super::Peripheral;
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+// Bits del registro de comando
+const CMD_DISABLE: u8 = 0x04;
+
+// Bits del registro de modo
+const MODE_AUTOINIT: u8 = 0x10;
+const MODE_DECREMENT: u8 = 0x20;
+
+#[derive(Clone, Copy, PartialEq)]
+enum TransferType {
+    Verify,
+    // Del dispositivo a memoria
+    Write,
+    // De memoria al dispositivo
+    Read,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TransferMode {
+    Demand,
+    Single,
+    Block,
+    Cascade,
+}
+
+// Lado del dispositivo en una transferencia (DACK)
+pub trait DmaDevice {
+    // Byte que el DMA va a escribir en memoria
+    fn dma_read(&mut self) -> u8;
+    // Byte que el DMA ha leido de memoria
+    fn dma_write(&mut self, val: u8);
+    // Se ha llegado a TC en el canal del dispositivo
+    fn terminal_count(&mut self);
+    // Estado de DREQ despues de cada ciclo
+    fn dreq(&self) -> bool;
+}
+
+// Refresco de memoria del canal 0, no transfiere nada
+pub struct MemoryRefresh;
+
+impl DmaDevice for MemoryRefresh {
+    fn dma_read(&mut self) -> u8 {
+        0xFF
+    }
+
+    fn dma_write(&mut self, _val: u8) {}
+
+    fn terminal_count(&mut self) {}
+
+    // DACK0 borra la peticion de refresco
+    fn dreq(&self) -> bool {
+        false
+    }
+}
 
 #[derive(Default, Clone, Copy)]
 struct Channel {
+    base_addr: u16,
+    base_count: u16,
     addr: u16,
-    length: u16,
+    count: u16,
+    mode: u8,
+}
+
+impl Channel {
+    fn transfer_type(&self) -> TransferType {
+        match (self.mode >> 2) & 0x03 {
+            0b01 => TransferType::Write,
+            0b10 => TransferType::Read,
+            _ => TransferType::Verify,
+        }
+    }
 
-    toggle: bool,
+    fn transfer_mode(&self) -> TransferMode {
+        match self.mode >> 6 {
+            0b00 => TransferMode::Demand,
+            0b01 => TransferMode::Single,
+            0b10 => TransferMode::Block,
+            0b11 => TransferMode::Cascade,
+            _ => unreachable!(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DMA8237 {
     channels: [Channel; 4],
+    // Registros de pagina (bits 16-19 de la direccion) de cada canal
+    page: [u8; 4],
+
+    command: u8,
+    // Bits 0-3: TC alcanzado, bits 4-7: peticiones
+    status: u8,
+    mask: u8,
+    // Peticiones por software
+    request: u8,
+    // Lineas DREQ de los dispositivos
+    dreq: u8,
+    flip_flop: bool,
+
+    // Para rebobinar: lo que habia en la RAM antes de cada escritura
+    pub journal: bool,
+    pub overwritten: Vec<(u32, u8)>,
+}
+
+// El PC solo tiene registros de pagina para los canales 1, 2 y 3
+fn page_channel(port: u16) -> usize {
+    match port {
+        0x81 => 2,
+        0x82 => 3,
+        0x83 => 1,
+        _ => 0,
+    }
 }
 
 impl DMA8237 {
     pub fn new() -> Self {
         DMA8237 {
-            channels: [Channel::default(); 4]
+            channels: [Channel::default(); 4],
+            page: [0; 4],
+
+            command: 0x00,
+            status: 0x00,
+            mask: 0x0F,
+            request: 0x00,
+            dreq: 0x00,
+            flip_flop: false,
+
+            journal: false,
+            overwritten: Vec::new(),
+        }
+    }
+
+    fn master_clear(&mut self) {
+        self.command = 0x00;
+        self.status = 0x00;
+        self.mask = 0x0F;
+        self.request = 0x00;
+        self.flip_flop = false;
+    }
+
+    fn write_register(&mut self, val: u8, channel: usize, count: bool) {
+        let ch = &mut self.channels[channel];
+        let reg = if count { &mut ch.base_count } else { &mut ch.base_addr };
+
+        *reg = if self.flip_flop {
+            (*reg & 0x00FF) | ((val as u16) << 8)
+        } else {
+            (*reg & 0xFF00) | val as u16
+        };
+
+        // Se escriben a la vez el registro base y el actual
+        if count {
+            ch.count = ch.base_count;
+        } else {
+            ch.addr = ch.base_addr;
         }
+
+        self.flip_flop = !self.flip_flop;
+    }
+
+    fn read_register(&mut self, channel: usize, count: bool) -> u8 {
+        let ch = &self.channels[channel];
+        let val = if count { ch.count } else { ch.addr };
+
+        let byte = if self.flip_flop { (val >> 8) as u8 } else { val as u8 };
+        self.flip_flop = !self.flip_flop;
+        byte
+    }
+
+    fn read_status(&mut self) -> u8 {
+        let status = self.status | ((self.dreq | self.request) << 4);
+        // Leer el estado borra los bits de TC
+        self.status &= 0xF0;
+        status
+    }
+
+    pub fn page_in(&self, port: u16) -> u16 {
+        self.page[page_channel(port)] as u16
+    }
+
+    pub fn page_out(&mut self, val: u16, port: u16) {
+        self.page[page_channel(port)] = val as u8 & 0x0F;
     }
 
-    fn write(&mut self, val: u16, channel: usize, opt: u16) {
-        if !self.channels[channel].toggle {
-            if opt == 0 {
-                let addr = (self.channels[channel].addr & 0xFF00) | (val as u8) as u16;
-                self.channels[channel].addr = addr;
+    // Linea DREQ del dispositivo conectado al canal
+    pub fn set_dreq(&mut self, channel: usize, active: bool) {
+        if active {
+            self.dreq |= 1 << channel;
+        } else {
+            self.dreq &= !(1 << channel);
+        }
+    }
+
+    pub fn terminal_count(&self, channel: usize) -> bool {
+        self.status & (1 << channel) != 0
+    }
+
+    fn requested(&self, channel: usize) -> bool {
+        (self.dreq | self.request) & (1 << channel) != 0
+    }
+
+    fn ready(&self, channel: usize) -> bool {
+        self.command & CMD_DISABLE == 0 && self.mask & (1 << channel) == 0 && self.requested(channel)
+    }
+
+    // Un ciclo de DMA. Devuelve true al llegar a TC
+    fn cycle(&mut self, channel: usize, device: &mut dyn DmaDevice, memory: &mut [u8]) -> bool {
+        let ch = self.channels[channel];
+        let address = ((self.page[channel] as usize) << 16) | ch.addr as usize;
+
+        match ch.transfer_type() {
+            TransferType::Write => {
+                let val = device.dma_read();
+                // NO ESCRIBIR EN ROM
+                if address < 0xC0000 {
+                    if self.journal {
+                        self.overwritten.push((address as u32, memory[address]));
+                    }
+                    memory[address] = val;
+                }
+            },
+            TransferType::Read => device.dma_write(memory[address]),
+            TransferType::Verify => {},
+        }
+
+        let ch = &mut self.channels[channel];
+        ch.addr = if ch.mode & MODE_DECREMENT != 0 {
+            ch.addr.wrapping_sub(1)
+        } else {
+            ch.addr.wrapping_add(1)
+        };
+
+        let tc = ch.count == 0;
+        ch.count = ch.count.wrapping_sub(1);
+
+        if tc {
+            self.status |= 1 << channel;
+            self.request &= !(1 << channel);
+
+            if ch.mode & MODE_AUTOINIT != 0 {
+                ch.addr = ch.base_addr;
+                ch.count = ch.base_count;
             } else {
-                let length = (self.channels[channel].length & 0xFF00) | (val as u8) as u16;
-                self.channels[channel].length = length;
+                self.mask |= 1 << channel;
             }
-        } else if opt == 0 {
-            let addr = (self.channels[channel].addr & 0x00FF) | (val << 8);
-            self.channels[channel].addr = addr;
-        } else {
-            let length = (self.channels[channel].length & 0x00FF) | (val & 0xFF00);
-            self.channels[channel].length = length;
+
+            device.terminal_count();
+        }
+
+        self.set_dreq(channel, device.dreq());
+        tc
+    }
+
+    // Atiende la peticion del canal si la hay. Devuelve los bytes transferidos
+    pub fn service(&mut self, channel: usize, device: &mut dyn DmaDevice, memory: &mut [u8]) -> u32 {
+        if !self.ready(channel) {
+            return 0;
         }
 
-        self.channels[channel].toggle = !self.channels[channel].toggle;
-    } 
+        let mode = self.channels[channel].transfer_mode();
+        if mode == TransferMode::Cascade {
+            return 0;
+        }
+
+        let mut transferred = 0;
+
+        loop {
+            let tc = self.cycle(channel, device, memory);
+            transferred += 1;
+
+            let more = match mode {
+                TransferMode::Single | TransferMode::Cascade => false,
+                TransferMode::Block => !tc,
+                // El dispositivo controla la transferencia con DREQ
+                TransferMode::Demand => !tc && self.requested(channel),
+            };
+
+            if !more {
+                break;
+            }
+        }
+
+        transferred
+    }
+}
+
+impl Default for DMA8237 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Peripheral for DMA8237 {
     fn port_in(&mut self, port: u16) -> u16 {
-        if port <= 0x07 {
-            let channel = port >> 1;
-            let opt = port & 1;
-    
-            if opt == 0 {
-                self.channels[channel as usize].addr
-            } else {
-                self.channels[channel as usize].length
-            }
-        } else {
-            0
+        match port {
+            0x00..=0x07 => self.read_register((port >> 1) as usize, port & 1 == 1) as u16,
+            0x08 => self.read_status() as u16,
+            _ => 0,
         }
-        
     }
 
     fn port_out(&mut self, val: u16, port: u16) {
-        if port <= 0x07 {
-            let channel = port >> 1;
-            let opt = port & 1;
-    
-            self.write(val, channel as usize, opt);
-        } else {
+        let val = val as u8;
+
+        match port {
+            0x00..=0x07 => self.write_register(val, (port >> 1) as usize, port & 1 == 1),
+            0x08 => self.command = val,
+            0x09 => {
+                let channel = val & 0x03;
+                if val & 0x04 != 0 {
+                    self.request |= 1 << channel;
+                } else {
+                    self.request &= !(1 << channel);
+                }
+            },
+            0x0A => {
+                let channel = val & 0x03;
+                if val & 0x04 != 0 {
+                    self.mask |= 1 << channel;
+                } else {
+                    self.mask &= !(1 << channel);
+                }
+            },
+            0x0B => self.channels[(val & 0x03) as usize].mode = val,
+            0x0C => self.flip_flop = false,
+            0x0D => self.master_clear(),
+            0x0E => self.mask = 0x00,
+            0x0F => self.mask = val & 0x0F,
+            _ => {},
+        }
+    }
+}
+
+impl Snapshot for DMA8237 {
+    fn save_state(&self, w: &mut StateWriter) {
+        for channel in &self.channels {
+            w.write_u16(channel.base_addr);
+            w.write_u16(channel.base_count);
+            w.write_u16(channel.addr);
+            w.write_u16(channel.count);
+            w.write_u8(channel.mode);
+        }
+        w.write_bytes(&self.page);
+
+        w.write_u8(self.command);
+        w.write_u8(self.status);
+        w.write_u8(self.mask);
+        w.write_u8(self.request);
+        w.write_u8(self.dreq);
+        w.write_bool(self.flip_flop);
+    }
 
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for channel in &mut self.channels {
+            channel.base_addr = r.read_u16()?;
+            channel.base_count = r.read_u16()?;
+            channel.addr = r.read_u16()?;
+            channel.count = r.read_u16()?;
+            channel.mode = r.read_u8()?;
         }
+        r.read_into(&mut self.page, "DMA page registers")?;
 
+        self.command = r.read_u8()?;
+        self.status = r.read_u8()?;
+        self.mask = r.read_u8()?;
+        self.request = r.read_u8()?;
+        self.dreq = r.read_u8()?;
+        self.flip_flop = r.read_bool()?;
+        Ok(())
     }
 }
@@ -0,0 +1,664 @@
+use super::Peripheral;
+use super::dma_8237::{DMA8237, DmaDevice};
+use super::floppy_disk::{FloppyDisk, Geometry, SECTOR_SIZE};
+use super::pic_8259::{PIC8259, IRQs};
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+const FDC_DMA_CHANNEL: usize = 2;
+// Ciclos que tardan en terminar los comandos que generan interrupcion
+const FDC_DELAY_CYCLES: u32 = 1000;
+
+// Bits del Digital Output Register (0x3F2)
+const DOR_RESET: u8 = 0x04;
+const DOR_IRQ_DMA: u8 = 0x08;
+
+// Bits del Main Status Register (0x3F4)
+const MSR_RQM: u8 = 0x80;
+const MSR_DIO: u8 = 0x40;
+const MSR_CB: u8 = 0x10;
+
+// ST0
+const ST0_ABNORMAL: u8 = 0x40;
+const ST0_INVALID: u8 = 0x80;
+const ST0_SEEK_END: u8 = 0x20;
+const ST0_READY_CHANGE: u8 = 0xC0;
+// ST1
+const ST1_END_OF_CYLINDER: u8 = 0x80;
+const ST1_NO_DATA: u8 = 0x04;
+const ST1_NOT_WRITABLE: u8 = 0x02;
+const ST1_MISSING_AM: u8 = 0x01;
+// ST2
+const ST2_WRONG_CYLINDER: u8 = 0x10;
+// ST3
+const ST3_WRITE_PROTECTED: u8 = 0x40;
+const ST3_READY: u8 = 0x20;
+const ST3_TRACK_0: u8 = 0x10;
+const ST3_TWO_SIDE: u8 = 0x08;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Command,
+    Execution,
+    Result,
+}
+
+// Lo que esta esperando a que pasen FDC_DELAY_CYCLES
+#[derive(Clone, Copy, PartialEq)]
+enum Pending {
+    None,
+    Reset,
+    Seek(u8, u8, u8),
+    Command,
+}
+
+// Bytes totales de cada comando, incluido el primero
+fn command_length(command: u8) -> usize {
+    match command & 0x1F {
+        0x02 | 0x05 | 0x06 | 0x09 | 0x0C | 0x11 | 0x19 | 0x1D => 9,
+        0x0D => 6,
+        0x03 | 0x0F => 3,
+        0x04 | 0x07 | 0x0A => 2,
+        _ => 1,
+    }
+}
+
+#[derive(Clone)]
+pub struct FDC765 {
+    dor: u8,
+    phase: Phase,
+    command: Vec<u8>,
+    result: Vec<u8>,
+    result_pos: usize,
+
+    // Cilindro donde esta el cabezal de cada unidad
+    cylinder: [u8; 4],
+    // ST0 y cilindro para el siguiente SENSE INTERRUPT STATUS
+    int_status: Option<(u8, u8)>,
+
+    pending: Pending,
+    delay: u32,
+
+    // Sector que se esta transfiriendo por DMA en la fase de ejecucion
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    // ID (C, H, R, N) del sector en curso y cabezal fisico
+    id: [u8; 4],
+    head: u8,
+    // Se ha recibido TC del DMA
+    tc: bool,
+    formatted: u8,
+
+    pub drives: [Option<FloppyDisk>; 4],
+}
+
+impl FDC765 {
+    pub fn new() -> Self {
+        FDC765 {
+            dor: 0x00,
+            phase: Phase::Command,
+            command: Vec::new(),
+            result: Vec::new(),
+            result_pos: 0,
+
+            cylinder: [0; 4],
+            int_status: None,
+
+            pending: Pending::None,
+            delay: 0,
+
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            id: [0; 4],
+            head: 0,
+            tc: false,
+            formatted: 0,
+
+            drives: [None, None, None, None],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.phase = Phase::Command;
+        self.command.clear();
+        self.result.clear();
+        self.result_pos = 0;
+        self.int_status = None;
+        self.pending = Pending::None;
+        self.delay = 0;
+        self.buffer.clear();
+        self.buffer_pos = 0;
+    }
+
+    fn schedule(&mut self, pending: Pending) {
+        self.pending = pending;
+        self.delay = FDC_DELAY_CYCLES;
+    }
+
+    fn interrupt(&self, pic: &mut PIC8259) {
+        if self.dor & DOR_IRQ_DMA != 0 {
+            pic.irq(IRQs::Irq6);
+        }
+    }
+
+    fn set_result(&mut self, result: &[u8]) {
+        self.result = result.to_vec();
+        self.result_pos = 0;
+        self.phase = Phase::Result;
+    }
+
+    fn read_msr(&self) -> u8 {
+        let seeking = match self.pending {
+            Pending::Seek(drive, _, _) => 1 << drive,
+            _ => 0,
+        };
+
+        let status = match self.phase {
+            Phase::Command if self.command.is_empty() => MSR_RQM,
+            Phase::Command => MSR_RQM | MSR_CB,
+            Phase::Execution => MSR_CB,
+            Phase::Result => MSR_RQM | MSR_DIO | MSR_CB,
+        };
+
+        status | seeking
+    }
+
+    fn write_dor(&mut self, val: u8) {
+        let old = self.dor;
+        self.dor = val;
+
+        if val & DOR_RESET == 0 {
+            self.reset();
+        } else if old & DOR_RESET == 0 {
+            // Al salir del reset el controlador interrumpe
+            self.schedule(Pending::Reset);
+        }
+    }
+
+    fn write_data(&mut self, val: u8) {
+        if self.phase != Phase::Command || self.dor & DOR_RESET == 0 {
+            return;
+        }
+
+        self.command.push(val);
+        if self.command.len() == command_length(self.command[0]) {
+            self.dispatch();
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        if self.phase != Phase::Result {
+            return 0;
+        }
+
+        let val = self.result[self.result_pos];
+        self.result_pos += 1;
+
+        if self.result_pos == self.result.len() {
+            self.phase = Phase::Command;
+            self.command.clear();
+        }
+
+        val
+    }
+
+    // Ejecuta los comandos inmediatos o programa los que tardan
+    fn dispatch(&mut self) {
+        let drive = (self.command.get(1).copied().unwrap_or(0) & 0x03) as usize;
+        let head = (self.command.get(1).copied().unwrap_or(0) >> 2) & 0x01;
+
+        match self.command[0] & 0x1F {
+            // SPECIFY
+            0x03 => {
+                self.command.clear();
+            },
+            // SENSE DRIVE STATUS
+            0x04 => {
+                let mut st3 = ST3_READY | (head << 2) | drive as u8;
+                if self.cylinder[drive] == 0 {
+                    st3 |= ST3_TRACK_0;
+                }
+                if let Some(disk) = &self.drives[drive] {
+                    if disk.write_protected {
+                        st3 |= ST3_WRITE_PROTECTED;
+                    }
+                    if disk.geometry.heads == 2 {
+                        st3 |= ST3_TWO_SIDE;
+                    }
+                }
+                self.set_result(&[st3]);
+            },
+            // RECALIBRATE
+            0x07 => {
+                self.command.clear();
+                self.schedule(Pending::Seek(drive as u8, 0, 0));
+            },
+            // SENSE INTERRUPT STATUS
+            0x08 => {
+                match self.int_status.take() {
+                    Some((st0, pcn)) => self.set_result(&[st0, pcn]),
+                    None => self.set_result(&[ST0_INVALID]),
+                }
+            },
+            // SEEK
+            0x0F => {
+                let ncn = self.command[2];
+                self.command.clear();
+                self.schedule(Pending::Seek(drive as u8, head, ncn));
+            },
+            // READ/WRITE DATA, READ TRACK, READ ID, FORMAT TRACK
+            0x02 | 0x05 | 0x06 | 0x09 | 0x0A | 0x0C | 0x0D => {
+                self.phase = Phase::Execution;
+                self.schedule(Pending::Command);
+            },
+            // Comandos no soportados
+            _ => self.set_result(&[ST0_INVALID]),
+        }
+    }
+
+    pub fn update(&mut self, cycles: u32, pic: &mut PIC8259, dma: &mut DMA8237) {
+        if self.pending != Pending::None {
+            if self.delay > cycles {
+                self.delay -= cycles;
+            } else {
+                self.complete(pic);
+            }
+        } else if self.phase == Phase::Execution && self.buffer_pos == self.buffer.len() {
+            self.sector_done(pic);
+        }
+
+        dma.set_dreq(FDC_DMA_CHANNEL, self.dreq());
+    }
+
+    fn complete(&mut self, pic: &mut PIC8259) {
+        let pending = self.pending;
+        self.pending = Pending::None;
+        self.delay = 0;
+
+        match pending {
+            Pending::Reset => self.int_status = Some((ST0_READY_CHANGE, 0)),
+            Pending::Seek(drive, head, cylinder) => {
+                // El cabezal no pasa del ultimo cilindro, y se informa de donde ha quedado
+                let max = self.drives[drive as usize].as_ref().map_or(40, |disk| disk.geometry.cylinders);
+                let cylinder = cylinder.min(max.saturating_sub(1));
+                self.cylinder[drive as usize] = cylinder;
+                self.int_status = Some((ST0_SEEK_END | (head << 2) | drive, cylinder));
+            },
+            Pending::Command => {
+                if let Err(result) = self.start_execution() {
+                    self.finish(&result, pic);
+                }
+                return;
+            },
+            Pending::None => unreachable!(),
+        }
+
+        self.interrupt(pic);
+    }
+
+    fn finish(&mut self, result: &[u8], pic: &mut PIC8259) {
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        self.set_result(result);
+        self.interrupt(pic);
+    }
+
+    fn drive(&self) -> usize {
+        (self.command[1] & 0x03) as usize
+    }
+
+    fn st0(&self) -> u8 {
+        (self.head << 2) | self.drive() as u8
+    }
+
+    // Resultado de una transferencia que termina con error
+    fn error(&self, st1: u8, st2: u8) -> Vec<u8> {
+        let [c, h, r, n] = self.id;
+        vec![self.st0() | ST0_ABNORMAL, st1, st2, c, h, r, n]
+    }
+
+    // Prepara la fase de ejecucion. Si el comando falla devuelve el resultado
+    fn start_execution(&mut self) -> Result<(), Vec<u8>> {
+        let cmd = self.command[0] & 0x1F;
+        let drive = self.drive();
+        let cylinder = self.cylinder[drive];
+
+        self.head = (self.command[1] >> 2) & 0x01;
+        self.tc = false;
+        self.formatted = 0;
+        self.id = match cmd {
+            0x0A => [cylinder, self.head, 1, 2],
+            0x0D => [cylinder, self.head, 1, self.command[2]],
+            _ => [self.command[2], self.command[3], self.command[4], self.command[5]],
+        };
+
+        // READ TRACK empieza siempre por el primer sector
+        if cmd == 0x02 {
+            self.id[2] = 1;
+        }
+
+        let disk = match &self.drives[drive] {
+            Some(disk) => disk,
+            // Sin disquete no se encuentra ninguna marca de direccion
+            None => return Err(self.error(ST1_MISSING_AM, 0)),
+        };
+
+        match cmd {
+            // READ ID
+            0x0A => {
+                let [c, h, r, n] = self.id;
+                Err(vec![self.st0(), 0, 0, c, h, r, n])
+            },
+            0x05 | 0x09 | 0x0D if disk.write_protected => Err(self.error(ST1_NOT_WRITABLE, 0)),
+            _ => self.load_sector(),
+        }
+    }
+
+    // Prepara el buffer del siguiente sector para el DMA
+    fn load_sector(&mut self) -> Result<(), Vec<u8>> {
+        let cmd = self.command[0] & 0x1F;
+        let drive = self.drive();
+        let cylinder = self.cylinder[drive];
+        let [c, _, r, n] = self.id;
+
+        self.buffer_pos = 0;
+
+        // FORMAT TRACK recibe el ID de cada sector
+        if cmd == 0x0D {
+            self.buffer = vec![0; 4];
+            return Ok(());
+        }
+
+        // El disquete se ha podido sacar en mitad del comando
+        let Some(disk) = self.drives[drive].as_ref() else {
+            return Err(self.error(ST1_MISSING_AM, 0));
+        };
+        let Geometry { heads, sectors, .. } = disk.geometry;
+
+        if n != 2 || r == 0 || r > sectors || self.head >= heads {
+            return Err(self.error(ST1_NO_DATA, 0));
+        }
+        if c != cylinder {
+            return Err(self.error(ST1_NO_DATA, ST2_WRONG_CYLINDER));
+        }
+
+        self.buffer = match cmd {
+            0x05 | 0x09 => vec![0; SECTOR_SIZE],
+            _ => match disk.read_sector(cylinder, self.head, r) {
+                Some(data) => data.to_vec(),
+                None => return Err(self.error(ST1_NO_DATA, 0)),
+            },
+        };
+
+        Ok(())
+    }
+
+    // El DMA ha terminado con el buffer del sector
+    fn sector_done(&mut self, pic: &mut PIC8259) {
+        let cmd = self.command[0] & 0x1F;
+        let drive = self.drive();
+        let cylinder = self.cylinder[drive];
+        let head = self.head;
+        let Some(disk) = self.drives[drive].as_mut() else {
+            let result = self.error(ST1_MISSING_AM, 0);
+            self.finish(&result, pic);
+            return;
+        };
+
+        match cmd {
+            0x0D => {
+                self.id.copy_from_slice(&self.buffer);
+                disk.write_sector(cylinder, head, self.id[2], &[self.command[5]; SECTOR_SIZE]);
+                self.formatted += 1;
+
+                if self.tc || self.formatted == self.command[3] {
+                    let [c, h, r, n] = self.id;
+                    self.finish(&[self.st0(), 0, 0, c, h, r, n], pic);
+                } else if let Err(result) = self.load_sector() {
+                    self.finish(&result, pic);
+                }
+                return;
+            },
+            0x05 | 0x09 => {
+                disk.write_sector(cylinder, head, self.id[2], &self.buffer);
+            },
+            _ => {},
+        }
+
+        // Siguiente sector
+        let multitrack = self.command[0] & 0x80 != 0;
+        let eot = self.command[6];
+        let mut end_of_cylinder = false;
+
+        if self.id[2] == eot {
+            self.id[2] = 1;
+            if multitrack && self.head == 0 {
+                self.head = 1;
+                self.id[1] ^= 1;
+            } else {
+                self.id[0] = self.id[0].wrapping_add(1);
+                if multitrack {
+                    self.id[1] ^= 1;
+                }
+                end_of_cylinder = true;
+            }
+        } else {
+            self.id[2] += 1;
+        }
+
+        let [c, h, r, n] = self.id;
+        if self.tc {
+            self.finish(&[self.st0(), 0, 0, c, h, r, n], pic);
+        } else if end_of_cylinder {
+            self.finish(&[self.st0() | ST0_ABNORMAL, ST1_END_OF_CYLINDER, 0, c, h, r, n], pic);
+        } else if let Err(result) = self.load_sector() {
+            self.finish(&result, pic);
+        }
+    }
+
+    // Lo que se carga de un estado tiene que cuadrar con la fase, o los puertos se salen de los vectores
+    fn valid_phase(&self) -> bool {
+        let cmd = self.command.first().map(|cmd| cmd & 0x1F);
+        let complete = self.command.first().is_some_and(|&cmd| self.command.len() == command_length(cmd));
+
+        match self.phase {
+            Phase::Command => self.pending != Pending::Command
+                && self.command.first().is_none_or(|&cmd| self.command.len() < command_length(cmd)),
+            Phase::Result => self.pending != Pending::Command && self.result_pos < self.result.len(),
+            Phase::Execution => complete && match cmd {
+                Some(0x02 | 0x06 | 0x0C) => true,
+                Some(0x0A) => self.pending == Pending::Command,
+                // Con el sector ya cargado, el buffer es lo que se escribe en el disquete
+                Some(0x05 | 0x09) => self.pending == Pending::Command || self.buffer.len() == SECTOR_SIZE,
+                Some(0x0D) => self.pending == Pending::Command || self.buffer.len() == 4,
+                _ => false,
+            },
+        }
+    }
+
+    fn dreq(&self) -> bool {
+        self.phase == Phase::Execution
+            && self.pending == Pending::None
+            && self.buffer_pos < self.buffer.len()
+            && self.dor & DOR_IRQ_DMA != 0
+    }
+}
+
+impl DmaDevice for FDC765 {
+    fn dma_read(&mut self) -> u8 {
+        match self.buffer.get(self.buffer_pos) {
+            Some(val) => {
+                self.buffer_pos += 1;
+                *val
+            },
+            None => 0xFF,
+        }
+    }
+
+    fn dma_write(&mut self, val: u8) {
+        if self.buffer_pos < self.buffer.len() {
+            self.buffer[self.buffer_pos] = val;
+            self.buffer_pos += 1;
+        }
+    }
+
+    // El FDC acaba el sector en curso y termina el comando
+    fn terminal_count(&mut self) {
+        if self.phase == Phase::Execution {
+            self.tc = true;
+            self.buffer_pos = self.buffer.len();
+        }
+    }
+
+    fn dreq(&self) -> bool {
+        FDC765::dreq(self)
+    }
+}
+
+impl Default for FDC765 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Peripheral for FDC765 {
+    fn port_in(&mut self, port: u16) -> u16 {
+        match port {
+            0x3F4 => self.read_msr() as u16,
+            0x3F5 => self.read_data() as u16,
+            _ => 0,
+        }
+    }
+
+    fn port_out(&mut self, val: u16, port: u16) {
+        match port {
+            0x3F2 => self.write_dor(val as u8),
+            0x3F5 => self.write_data(val as u8),
+            _ => {},
+        }
+    }
+}
+
+// Los registros van aparte de los disquetes: los puntos de control del rebobinado solo llevan los registros
+impl FDC765 {
+    pub fn save_controller(&self, w: &mut StateWriter) {
+        w.write_u8(self.dor);
+        w.write_u8(match self.phase {
+            Phase::Command => 0,
+            Phase::Execution => 1,
+            Phase::Result => 2,
+        });
+        w.write_bytes(&self.command);
+        w.write_bytes(&self.result);
+        w.write_u8(self.result_pos as u8);
+        w.write_bytes(&self.cylinder);
+
+        let (has_status, st0, pcn) = match self.int_status {
+            Some((st0, pcn)) => (true, st0, pcn),
+            None => (false, 0, 0),
+        };
+        w.write_bool(has_status);
+        w.write_u8(st0);
+        w.write_u8(pcn);
+
+        let (tag, a, b, c) = match self.pending {
+            Pending::None => (0, 0, 0, 0),
+            Pending::Reset => (1, 0, 0, 0),
+            Pending::Seek(drive, head, cylinder) => (2, drive, head, cylinder),
+            Pending::Command => (3, 0, 0, 0),
+        };
+        w.write_bytes(&[tag, a, b, c]);
+        w.write_u32(self.delay);
+
+        w.write_bytes(&self.buffer);
+        w.write_u16(self.buffer_pos as u16);
+        w.write_bytes(&self.id);
+        w.write_u8(self.head);
+        w.write_bool(self.tc);
+        w.write_u8(self.formatted);
+    }
+
+    pub fn load_controller(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.dor = r.read_u8()?;
+        self.phase = match r.read_u8()? {
+            0 => Phase::Command,
+            1 => Phase::Execution,
+            2 => Phase::Result,
+            _ => return Err(StateError::Invalid("FDC phase")),
+        };
+        self.command = r.read_bytes()?.to_vec();
+        self.result = r.read_bytes()?.to_vec();
+        self.result_pos = r.read_u8()? as usize;
+        if self.result_pos > self.result.len() {
+            return Err(StateError::Invalid("FDC result"));
+        }
+        r.read_into(&mut self.cylinder, "FDC cylinders")?;
+
+        let has_status = r.read_bool()?;
+        let st0 = r.read_u8()?;
+        let pcn = r.read_u8()?;
+        self.int_status = if has_status { Some((st0, pcn)) } else { None };
+
+        let mut pending = [0; 4];
+        r.read_into(&mut pending, "FDC pending operation")?;
+        self.pending = match pending {
+            [0, ..] => Pending::None,
+            [1, ..] => Pending::Reset,
+            [2, drive, head, cylinder] if drive < 4 => Pending::Seek(drive, head, cylinder),
+            [3, ..] => Pending::Command,
+            _ => return Err(StateError::Invalid("FDC pending operation")),
+        };
+        self.delay = r.read_u32()?;
+
+        self.buffer = r.read_bytes()?.to_vec();
+        self.buffer_pos = r.read_u16()? as usize;
+        if self.buffer_pos > self.buffer.len() {
+            return Err(StateError::Invalid("FDC buffer"));
+        }
+        r.read_into(&mut self.id, "FDC sector ID")?;
+        self.head = r.read_u8()?;
+        self.tc = r.read_bool()?;
+        self.formatted = r.read_u8()?;
+        if !self.valid_phase() {
+            return Err(StateError::Invalid("FDC command"));
+        }
+        Ok(())
+    }
+
+    pub fn save_media(&self, w: &mut StateWriter) {
+        for drive in &self.drives {
+            w.write_bool(drive.is_some());
+            if let Some(disk) = drive {
+                w.write_bytes(&disk.data);
+                w.write_bool(disk.write_protected);
+            }
+        }
+    }
+
+    pub fn load_media(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for drive in &mut self.drives {
+            // El disquete del estado no tiene fichero, para no volcarlo encima del que este puesto ahora
+            *drive = if r.read_bool()? {
+                let mut disk = FloppyDisk::from_bytes(r.read_bytes()?.to_vec())
+                    .map_err(|_| StateError::Invalid("disk image size"))?;
+                disk.write_protected = r.read_bool()?;
+                Some(disk)
+            } else {
+                None
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl Snapshot for FDC765 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.save_controller(w);
+        self.save_media(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.load_controller(r)?;
+        self.load_media(r)
+    }
+}
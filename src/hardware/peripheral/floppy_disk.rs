@@ -0,0 +1,114 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+pub const SECTOR_SIZE: usize = 512;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Geometry {
+    pub cylinders: u8,
+    pub heads: u8,
+    pub sectors: u8,
+}
+
+impl Geometry {
+    // Formatos de 5.25" que entiende el PC: 160K, 180K, 320K y 360K
+    pub fn from_size(size: usize) -> Option<Self> {
+        let (heads, sectors) = match size {
+            163_840 => (1, 8),
+            184_320 => (1, 9),
+            327_680 => (2, 8),
+            368_640 => (2, 9),
+            _ => return None,
+        };
+
+        Some(Geometry { cylinders: 40, heads, sectors })
+    }
+
+    pub fn size(&self) -> usize {
+        self.cylinders as usize * self.heads as usize * self.sectors as usize * SECTOR_SIZE
+    }
+}
+
+#[derive(Debug)]
+pub enum DiskError {
+    Io(PathBuf, std::io::Error),
+    UnknownSize(usize),
+    NoDrive(usize),
+}
+
+impl Display for DiskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiskError::Io(path, err) => write!(f, "could not access {}: {}", path.display(), err),
+            DiskError::UnknownSize(size) => write!(f, "{} bytes is not a 160K/180K/320K/360K disk image", size),
+            DiskError::NoDrive(drive) => write!(f, "there is no floppy drive {}", drive),
+        }
+    }
+}
+
+impl std::error::Error for DiskError {}
+
+// Imagen raw de un disquete, sector tras sector (C, H, S)
+#[derive(Clone)]
+pub struct FloppyDisk {
+    pub geometry: Geometry,
+    pub data: Vec<u8>,
+    pub write_protected: bool,
+    pub path: Option<PathBuf>,
+}
+
+impl FloppyDisk {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, DiskError> {
+        let geometry = Geometry::from_size(data.len()).ok_or(DiskError::UnknownSize(data.len()))?;
+
+        Ok(FloppyDisk {
+            geometry,
+            data,
+            write_protected: false,
+            path: None,
+        })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, DiskError> {
+        let path = path.as_ref().to_path_buf();
+        let data = std::fs::read(&path).map_err(|e| DiskError::Io(path.clone(), e))?;
+
+        let mut disk = FloppyDisk::from_bytes(data)?;
+        disk.path = Some(path);
+        Ok(disk)
+    }
+
+    // Vuelca la imagen al fichero del que se leyo
+    pub fn flush(&self) -> Result<(), DiskError> {
+        match &self.path {
+            Some(path) => std::fs::write(path, &self.data).map_err(|e| DiskError::Io(path.clone(), e)),
+            None => Ok(()),
+        }
+    }
+
+    // Offset del sector en la imagen. Los sectores empiezan en 1
+    fn offset(&self, cylinder: u8, head: u8, sector: u8) -> Option<usize> {
+        let g = self.geometry;
+        if cylinder >= g.cylinders || head >= g.heads || sector == 0 || sector > g.sectors {
+            return None;
+        }
+
+        let lba = (cylinder as usize * g.heads as usize + head as usize) * g.sectors as usize + sector as usize - 1;
+        Some(lba * SECTOR_SIZE)
+    }
+
+    pub fn read_sector(&self, cylinder: u8, head: u8, sector: u8) -> Option<&[u8]> {
+        let offset = self.offset(cylinder, head, sector)?;
+        Some(&self.data[offset..offset + SECTOR_SIZE])
+    }
+
+    pub fn write_sector(&mut self, cylinder: u8, head: u8, sector: u8, data: &[u8]) -> bool {
+        match self.offset(cylinder, head, sector) {
+            Some(offset) => {
+                self.data[offset..offset + SECTOR_SIZE].copy_from_slice(&data[..SECTOR_SIZE]);
+                true
+            },
+            None => false,
+        }
+    }
+}
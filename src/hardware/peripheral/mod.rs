@@ -1,6 +1,9 @@
 pub mod dma_8237;
+pub mod fdc_765;
+pub mod floppy_disk;
 pub mod pic_8259;
 pub mod ppi_8255;
+pub mod speaker;
 pub mod timer_8253;
 
 pub trait Peripheral {
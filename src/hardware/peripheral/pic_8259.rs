@@ -1,4 +1,21 @@
 use super::Peripheral;
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+// Bits de ICW1
+const ICW1_IC4: u8 = 0x01;
+const ICW1_SNGL: u8 = 0x02;
+const ICW1_INIT: u8 = 0x10;
+
+// Bits de ICW4
+const ICW4_AEOI: u8 = 0x02;
+
+// Bits de OCW3
+const OCW3: u8 = 0x08;
+const OCW3_RIS: u8 = 0x01;
+const OCW3_RR: u8 = 0x02;
+const OCW3_POLL: u8 = 0x04;
+const OCW3_SMM: u8 = 0x20;
+const OCW3_ESMM: u8 = 0x40;
 
 #[derive(Copy, Clone)]
 pub struct PIC8259 {
@@ -6,10 +23,18 @@ pub struct PIC8259 {
     imr: u8,
     pub irr: u8,
 
-    // max_prio: IRQs,
-
     icw: [u8; 4],
+    // Siguiente ICW que se espera (2-4), 0 si ya esta inicializado
     icw_step: usize,
+
+    // IRQ con la prioridad mas baja, la siguiente es la de mayor prioridad
+    lowest: u8,
+    auto_eoi: bool,
+    rotate_aeoi: bool,
+    special_mask: bool,
+    // OCW3: leer ISR en vez de IRR en el puerto 0x20
+    read_isr: bool,
+    poll: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -31,30 +56,86 @@ impl PIC8259 {
             imr: 0xFF,              // Interrupt Mask Register
             irr: 0,                 // Interrupt Request Register
 
-            // max_prio: IRQs::Irq0,
-
             icw: [0; 4],
             icw_step: 0,
+
+            lowest: 7,
+            auto_eoi: false,
+            rotate_aeoi: false,
+            special_mask: false,
+            read_isr: false,
+            poll: false,
         }
     }
 }
 
+impl Default for PIC8259 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PIC8259 {
-    pub fn get_next(&mut self) -> u8 {
-        let requested_ints = self.irr & !self.imr;
-        for i in 0..8 {
-            if requested_ints & 1 << (7 - i) > 0 {
-                self.isr |= 1 << (7 - i);
-                self.irr ^= 1 << (7 - i);
-                return self.icw[1] + (7 - i);
+    // IRQs de mayor a menor prioridad
+    fn priorities(&self) -> impl Iterator<Item = u8> {
+        let highest = (self.lowest + 1) & 0x07;
+        (0..8).map(move |i| (highest + i) & 0x07)
+    }
+
+    // IRQ que se atenderia ahora, si la hay
+    fn pending(&self) -> Option<u8> {
+        let requested = self.irr & !self.imr;
+
+        for irq in self.priorities() {
+            let bit = 1 << irq;
+
+            // En special mask mode solo bloquea la propia IRQ en servicio
+            if self.isr & bit != 0 && !self.special_mask {
+                return None;
             }
+            if requested & bit != 0 && self.isr & bit == 0 {
+                return Some(irq);
+            }
+        }
+
+        None
+    }
+
+    // IRQ en servicio con mas prioridad
+    fn highest_in_service(&self) -> Option<u8> {
+        self.priorities().find(|&irq| self.isr & (1 << irq) != 0)
+    }
+
+    // Ciclo INTA. Devuelve la IRQ reconocida
+    fn acknowledge(&mut self) -> Option<u8> {
+        let irq = self.pending()?;
+        self.irr &= !(1 << irq);
+
+        if !self.auto_eoi {
+            self.isr |= 1 << irq;
+        } else if self.rotate_aeoi {
+            self.lowest = irq;
         }
 
-        0
+        Some(irq)
+    }
+
+    // Vector de la interrupcion. Sin IRQ el 8259A devuelve la 7 (espuria)
+    pub fn get_next(&mut self) -> u8 {
+        let irq = self.acknowledge().unwrap_or(7);
+        (self.icw[1] & 0xF8) | irq
+    }
+
+    pub fn isr(&self) -> u8 {
+        self.isr
+    }
+
+    pub fn imr(&self) -> u8 {
+        self.imr
     }
 
     pub fn has_int(&mut self) -> bool {
-        (!self.imr & self.irr) > 0
+        self.pending().is_some()
     }
 
     pub fn irq(&mut self, irq: IRQs) {
@@ -64,42 +145,155 @@ impl PIC8259 {
     pub fn clear_int(&mut self, irq: IRQs) {
         self.irr &= !(irq as u8);
     }
+
+    fn icw1(&mut self, val: u8) {
+        self.icw[0] = val;
+        self.icw_step = 2;
+
+        self.imr = 0;
+        self.isr = 0;
+        self.lowest = 7;
+        self.special_mask = false;
+        self.read_isr = false;
+        self.poll = false;
+        if val & ICW1_IC4 == 0 {
+            self.icw[3] = 0;
+            self.auto_eoi = false;
+        }
+    }
+
+    fn icw(&mut self, val: u8) {
+        let ic4 = self.icw[0] & ICW1_IC4 != 0;
+        let single = self.icw[0] & ICW1_SNGL != 0;
+
+        self.icw[self.icw_step - 1] = val;
+        self.icw_step = match self.icw_step {
+            2 if !single => 3,
+            2 | 3 if ic4 => 4,
+            4 => {
+                self.auto_eoi = val & ICW4_AEOI != 0;
+                0
+            },
+            _ => 0,
+        };
+    }
+
+    fn ocw2(&mut self, val: u8) {
+        let level = val & 0x07;
+
+        // Bits R, SL y EOI
+        match val >> 5 {
+            // EOI no especifico
+            0b001 => if let Some(irq) = self.highest_in_service() {
+                self.isr &= !(1 << irq);
+            },
+            // EOI especifico
+            0b011 => self.isr &= !(1 << level),
+            // Rotar con EOI no especifico
+            0b101 => if let Some(irq) = self.highest_in_service() {
+                self.isr &= !(1 << irq);
+                self.lowest = irq;
+            },
+            0b100 => self.rotate_aeoi = true,
+            0b000 => self.rotate_aeoi = false,
+            // Rotar con EOI especifico
+            0b111 => {
+                self.isr &= !(1 << level);
+                self.lowest = level;
+            },
+            // Fijar la prioridad
+            0b110 => self.lowest = level,
+            _ => {},
+        }
+    }
+
+    fn ocw3(&mut self, val: u8) {
+        if val & OCW3_ESMM != 0 {
+            self.special_mask = val & OCW3_SMM != 0;
+        }
+        if val & OCW3_RR != 0 {
+            self.read_isr = val & OCW3_RIS != 0;
+        }
+        self.poll = val & OCW3_POLL != 0;
+    }
+
+    // Comando poll: reconoce la IRQ sin ciclo INTA
+    fn read_poll(&mut self) -> u8 {
+        self.poll = false;
+        match self.acknowledge() {
+            Some(irq) => 0x80 | irq,
+            None => 0x00,
+        }
+    }
 }
 
 impl Peripheral for PIC8259 {
     fn port_in(&mut self, port: u16) -> u16 {
-        if port == 0x20 {self.irr as u16} else {self.imr as u16}
+        if port & 0x01 == 0 {
+            if self.poll {
+                self.read_poll() as u16
+            } else if self.read_isr {
+                self.isr as u16
+            } else {
+                self.irr as u16
+            }
+        } else {
+            self.imr as u16
+        }
     }
 
     fn port_out(&mut self, val: u16, port: u16) {
         let val = val as u8;
         
-        if port == 0x20 {
-            if val & 0x10 > 0 {
-                // ICW1
-                self.icw[self.icw_step] = val;
-                self.imr = 0;
-                self.icw_step += 1;
-            }
-            // TODO OCW
-            if val & 0x20 > 0 {
-                for i in 0..8 {
-                    if self.isr & 1 << (7  - i) > 0 {
-                        self.isr ^= 1 << (7  - i);
-                    }
-                }
-            }
-        } else if self.icw_step == 1 {
-            self.icw[self.icw_step] = val;
-            self.icw_step += 1;
-            if self.icw[0] & 0x02 > 0 {
-                self.icw_step += 1;
+        if port & 0x01 == 0 {
+            if val & ICW1_INIT != 0 {
+                self.icw1(val);
+            } else if val & OCW3 != 0 {
+                self.ocw3(val);
+            } else {
+                self.ocw2(val);
             }
-        } else if self.icw_step < 4 {
-            self.icw[self.icw_step] = val;
-            self.icw_step += 1;
+        } else if self.icw_step != 0 {
+            self.icw(val);
         } else {
+            // OCW1
             self.imr = val;
         }
     }
 }
+
+impl Snapshot for PIC8259 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.isr);
+        w.write_u8(self.imr);
+        w.write_u8(self.irr);
+        w.write_bytes(&self.icw);
+        w.write_u8(self.icw_step as u8);
+
+        w.write_u8(self.lowest);
+        w.write_bool(self.auto_eoi);
+        w.write_bool(self.rotate_aeoi);
+        w.write_bool(self.special_mask);
+        w.write_bool(self.read_isr);
+        w.write_bool(self.poll);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.isr = r.read_u8()?;
+        self.imr = r.read_u8()?;
+        self.irr = r.read_u8()?;
+        r.read_into(&mut self.icw, "PIC ICW")?;
+        self.icw_step = match r.read_u8()? {
+            step @ 0..=4 => step as usize,
+            _ => return Err(StateError::Invalid("PIC ICW step")),
+        };
+
+        self.lowest = r.read_u8()? & 0x07;
+        self.auto_eoi = r.read_bool()?;
+        self.rotate_aeoi = r.read_bool()?;
+        self.special_mask = r.read_bool()?;
+        self.read_isr = r.read_bool()?;
+        self.poll = r.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
 // use ggez::event::{self, KeyCode};
 
+use std::collections::VecDeque;
+
 use ggez::event::KeyCode;
 
 use super::{Peripheral, pic_8259::{PIC8259, IRQs}};
+use crate::CPU_CLOCK;
+use crate::hardware::config::DisplayType;
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
 
 // IMPORTANTE: ESTAN AL REVES, LA POSICION 1 ES EL BIT 0.
 //             ON = 0, OFF = 1
@@ -12,7 +17,17 @@ const SW1: u8 = 0b00110100;
 const SW2: u8 = 0b11100000;
 
 const KBD_RESET_CYCLES: u32 = 47700; // 20 ms
-const KBD_RESET_CYCLE_DELAY: u32 = 100;
+// El teclado tarda en contestar con 0xAA despues del reset (~1 ms)
+const KBD_RESET_CYCLE_DELAY: u32 = 4_773;
+
+// Buffer interno del 8048 del teclado
+const KBD_BUFFER_SIZE: usize = 20;
+// Tiempo minimo entre dos scancodes por la linea serie (~1 ms)
+const KBD_BYTE_CYCLES: u32 = 4_773;
+
+// Repeticion automatica por defecto: 500 ms y 10 caracteres por segundo
+pub const TYPEMATIC_DELAY_MS: u32 = 500;
+pub const TYPEMATIC_RATE: u32 = 10;
 
 #[derive(Clone)]
 pub struct PPI8255 {
@@ -20,6 +35,7 @@ pub struct PPI8255 {
     pub port_b: u8,
     pub port_c: u8,
     mode_reg: u8,
+    sw1: u8,
 
     kbd: Keyboard,
 }
@@ -35,6 +51,18 @@ pub struct Keyboard {
 
     count_until_reset: u32,
     resets_counter: u32,
+
+    // Scancodes pendientes de enviar
+    buffer: VecDeque<u8>,
+    // Hay un scancode en el puerto A que la BIOS no ha reconocido con PB7
+    full: bool,
+    send_delay: u32,
+
+    // Tecla que se repite (0 si ninguna) y ciclos hasta la siguiente repeticion
+    repeat_key: u8,
+    repeat_cycles: u32,
+    typematic_delay: u32,
+    typematic_interval: u32,
 }
 
 impl Keyboard {
@@ -49,43 +77,216 @@ impl Keyboard {
 
             count_until_reset: 0,
             resets_counter: 0,
+
+            buffer: VecDeque::with_capacity(KBD_BUFFER_SIZE),
+            full: false,
+            send_delay: 0,
+
+            repeat_key: 0,
+            repeat_cycles: 0,
+            typematic_delay: TYPEMATIC_DELAY_MS * (CPU_CLOCK / 1000) as u32,
+            typematic_interval: (CPU_CLOCK / TYPEMATIC_RATE as u64) as u32,
+        }
+    }
+
+    // Con el buffer lleno el teclado pierde las teclas
+    fn send(&mut self, key_code: u8) {
+        if self.buffer.len() < KBD_BUFFER_SIZE {
+            self.buffer.push_back(key_code);
+        }
+    }
+
+    fn typematic(&mut self, key_code: u8) {
+        let make = key_code & 0x7F;
+
+        if key_code & 0x80 == 0 {
+            // Solo la ultima tecla pulsada se repite
+            if make != self.repeat_key {
+                self.repeat_key = make;
+                self.repeat_cycles = self.typematic_delay;
+            }
+        } else if make == self.repeat_key {
+            self.repeat_key = 0;
+        }
+    }
+
+    fn update_repeat(&mut self, cycles: u32) {
+        if self.repeat_key == 0 || self.typematic_interval == 0 {
+            return;
+        }
+
+        if self.repeat_cycles > cycles {
+            self.repeat_cycles -= cycles;
+        } else {
+            self.repeat_cycles = self.typematic_interval;
+            self.send(self.repeat_key);
         }
     }
 }
 
-fn decode_key(keycode: KeyCode) -> u8 {
-    println!("{:?}", keycode);
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Scancodes (set 1) del teclado de 83 teclas del PC. 0 si la tecla no existe
+pub fn decode_key(keycode: KeyCode) -> u8 {
     match keycode {
-        KeyCode::Escape => 1,
-        KeyCode::Key1 => 2, 
-        KeyCode::Key2 => 3,
-        KeyCode::Key3 => 4,
-        KeyCode::Key4 => 5,
-        KeyCode::Key5 => 6,
-        KeyCode::Key6 => 7,
-        KeyCode::Key7 => 8,
-        KeyCode::Key8 => 9,
-        KeyCode::Key9 => 10,
-        KeyCode::Key0 => 11,
-        KeyCode::Minus => 12,
-        KeyCode::Equals => 13,
-        KeyCode::Back => 14,
-        KeyCode::Tab => 15,
-        KeyCode::Q => 16,
-        KeyCode::W => 17,
-        KeyCode::E => 18,
-        KeyCode::R => 19,
-        KeyCode::T => 20,
-        KeyCode::Y => 21,
-        KeyCode::U => 22,
-        KeyCode::I => 23,
-        KeyCode::O => 24,
-        KeyCode::P => 25,
+        KeyCode::Escape => 0x01,
+        KeyCode::Key1 => 0x02,
+        KeyCode::Key2 => 0x03,
+        KeyCode::Key3 => 0x04,
+        KeyCode::Key4 => 0x05,
+        KeyCode::Key5 => 0x06,
+        KeyCode::Key6 => 0x07,
+        KeyCode::Key7 => 0x08,
+        KeyCode::Key8 => 0x09,
+        KeyCode::Key9 => 0x0A,
+        KeyCode::Key0 => 0x0B,
+        KeyCode::Minus => 0x0C,
+        KeyCode::Equals => 0x0D,
+        KeyCode::Back => 0x0E,
+        KeyCode::Tab => 0x0F,
+        KeyCode::Q => 0x10,
+        KeyCode::W => 0x11,
+        KeyCode::E => 0x12,
+        KeyCode::R => 0x13,
+        KeyCode::T => 0x14,
+        KeyCode::Y => 0x15,
+        KeyCode::U => 0x16,
+        KeyCode::I => 0x17,
+        KeyCode::O => 0x18,
+        KeyCode::P => 0x19,
+        KeyCode::LBracket => 0x1A,
+        KeyCode::RBracket => 0x1B,
+        KeyCode::Return | KeyCode::NumpadEnter => 0x1C,
+        KeyCode::LControl | KeyCode::RControl => 0x1D,
+        KeyCode::A => 0x1E,
+        KeyCode::S => 0x1F,
+        KeyCode::D => 0x20,
+        KeyCode::F => 0x21,
+        KeyCode::G => 0x22,
+        KeyCode::H => 0x23,
+        KeyCode::J => 0x24,
+        KeyCode::K => 0x25,
+        KeyCode::L => 0x26,
+        KeyCode::Semicolon => 0x27,
+        KeyCode::Apostrophe => 0x28,
+        KeyCode::Grave => 0x29,
+        KeyCode::LShift => 0x2A,
+        KeyCode::Backslash => 0x2B,
+        KeyCode::Z => 0x2C,
+        KeyCode::X => 0x2D,
+        KeyCode::C => 0x2E,
+        KeyCode::V => 0x2F,
+        KeyCode::B => 0x30,
+        KeyCode::N => 0x31,
+        KeyCode::M => 0x32,
+        KeyCode::Comma => 0x33,
+        KeyCode::Period => 0x34,
+        KeyCode::Slash | KeyCode::NumpadDivide => 0x35,
+        KeyCode::RShift => 0x36,
+        KeyCode::NumpadMultiply | KeyCode::Snapshot => 0x37,
+        KeyCode::LAlt | KeyCode::RAlt => 0x38,
+        KeyCode::Space => 0x39,
+        KeyCode::Capital => 0x3A,
+        KeyCode::F1 => 0x3B,
+        KeyCode::F2 => 0x3C,
+        KeyCode::F3 => 0x3D,
+        KeyCode::F4 => 0x3E,
+        KeyCode::F5 => 0x3F,
+        KeyCode::F6 => 0x40,
+        KeyCode::F7 => 0x41,
+        KeyCode::F8 => 0x42,
+        KeyCode::F9 => 0x43,
+        KeyCode::F10 => 0x44,
+        KeyCode::Numlock => 0x45,
+        KeyCode::Scroll => 0x46,
+        // Teclado numerico, que tambien hace de cursores
+        KeyCode::Numpad7 | KeyCode::Home => 0x47,
+        KeyCode::Numpad8 | KeyCode::Up => 0x48,
+        KeyCode::Numpad9 | KeyCode::PageUp => 0x49,
+        KeyCode::NumpadSubtract => 0x4A,
+        KeyCode::Numpad4 | KeyCode::Left => 0x4B,
+        KeyCode::Numpad5 => 0x4C,
+        KeyCode::Numpad6 | KeyCode::Right => 0x4D,
+        KeyCode::NumpadAdd => 0x4E,
+        KeyCode::Numpad1 | KeyCode::End => 0x4F,
+        KeyCode::Numpad2 | KeyCode::Down => 0x50,
+        KeyCode::Numpad3 | KeyCode::PageDown => 0x51,
+        KeyCode::Numpad0 | KeyCode::Insert => 0x52,
+        KeyCode::NumpadDecimal | KeyCode::Delete => 0x53,
 
         _ => 0,
     }
 }
 
+pub const SCANCODE_LSHIFT: u8 = 0x2A;
+
+// Scancode de un caracter ASCII en el teclado US y si necesita Shift
+pub fn ascii_key(c: char) -> Option<(u8, bool)> {
+    const ROW_Q: &[u8] = b"qwertyuiop";
+    const ROW_A: &[u8] = b"asdfghjkl";
+    const ROW_Z: &[u8] = b"zxcvbnm";
+    const DIGITS: &[u8] = b"1234567890";
+    const SHIFTED_DIGITS: &[u8] = b"!@#$%^&*()";
+
+    if !c.is_ascii() {
+        return None;
+    }
+    let b = c as u8;
+    let lower = b.to_ascii_lowercase();
+    let shift = b.is_ascii_uppercase();
+
+    if let Some(i) = ROW_Q.iter().position(|&k| k == lower) {
+        return Some((0x10 + i as u8, shift));
+    }
+    if let Some(i) = ROW_A.iter().position(|&k| k == lower) {
+        return Some((0x1E + i as u8, shift));
+    }
+    if let Some(i) = ROW_Z.iter().position(|&k| k == lower) {
+        return Some((0x2C + i as u8, shift));
+    }
+    if let Some(i) = DIGITS.iter().position(|&k| k == b) {
+        return Some((0x02 + i as u8, false));
+    }
+    if let Some(i) = SHIFTED_DIGITS.iter().position(|&k| k == b) {
+        return Some((0x02 + i as u8, true));
+    }
+
+    Some(match c {
+        '-' => (0x0C, false),
+        '_' => (0x0C, true),
+        '=' => (0x0D, false),
+        '+' => (0x0D, true),
+        '\x08' => (0x0E, false),
+        '\t' => (0x0F, false),
+        '[' => (0x1A, false),
+        '{' => (0x1A, true),
+        ']' => (0x1B, false),
+        '}' => (0x1B, true),
+        '\n' | '\r' => (0x1C, false),
+        ';' => (0x27, false),
+        ':' => (0x27, true),
+        '\'' => (0x28, false),
+        '"' => (0x28, true),
+        '`' => (0x29, false),
+        '~' => (0x29, true),
+        '\\' => (0x2B, false),
+        '|' => (0x2B, true),
+        ',' => (0x33, false),
+        '<' => (0x33, true),
+        '.' => (0x34, false),
+        '>' => (0x34, true),
+        '/' => (0x35, false),
+        '?' => (0x35, true),
+        ' ' => (0x39, false),
+        '\x1B' => (0x01, false),
+        _ => return None,
+    })
+}
+
 impl PPI8255 {
     pub fn new() -> Self {
         PPI8255 { 
@@ -93,33 +294,56 @@ impl PPI8255 {
             port_b: 0x00,
             port_c: 0x00,
             mode_reg: 0x00,
+            sw1: SW1,
 
             kbd: Keyboard::new(),
         }
     }
 
-    pub fn key_up(&mut self, keycode: KeyCode, pic: &mut PIC8259) {
-        // if self.keyboard_enabled {
-        let key_code = decode_key(keycode) + 0x80;
-        self.key_input(key_code, pic);
-        // }
+    // SW1 bit 0: hay disqueteras, bits 6-7: numero de unidades - 1
+    pub fn set_floppy_drives(&mut self, drives: u8) {
+        self.sw1 &= 0b00111110;
+        if drives > 0 {
+            self.sw1 |= 0x01 | ((drives.min(4) - 1) << 6);
+        }
     }
 
-    pub fn key_down(&mut self, keycode: KeyCode, pic: &mut PIC8259) {
-        // if self.keyboard_enabled {
-        let key_code = decode_key(keycode);
-        self.key_input(key_code, pic);
-        // }
+    // SW1 bit 1: coprocesador 8087 instalado
+    pub fn set_fpu(&mut self, present: bool) {
+        self.sw1 = (self.sw1 & 0b11111101) | ((present as u8) << 1);
     }
-    
-    pub fn key_input(&mut self, key_code: u8, pic: &mut PIC8259) {
-        self.key_code = key_code;
-        pic.irq(IRQs::Irq1);
+
+    // SW1 bits 4-5: 01 CGA 40x25, 10 CGA 80x25, 11 MDA
+    pub fn set_display(&mut self, display: DisplayType) {
+        let bits = match display {
+            DisplayType::Cga40 => 0b01,
+            DisplayType::Cga80 => 0b10,
+            DisplayType::Mda => 0b11,
+        };
+        self.sw1 = (self.sw1 & 0b11001111) | (bits << 4);
+    }
+
+    // Retardo antes de repetir y repeticiones por segundo (0 para no repetir)
+    pub fn set_typematic(&mut self, delay_ms: u32, rate: u32) {
+        // En u64 para que un retardo muy largo no desborde: se queda en el maximo
+        let delay = delay_ms as u64 * (CPU_CLOCK / 1000);
+        self.kbd.typematic_delay = u32::try_from(delay).unwrap_or(u32::MAX);
+        self.kbd.typematic_interval = CPU_CLOCK.checked_div(rate as u64).unwrap_or(0) as u32;
+    }
+
+    // El scancode entra en el buffer del teclado y se envia en update
+    pub fn key_input(&mut self, key_code: u8) {
+        self.kbd.typematic(key_code);
+        self.kbd.send(key_code);
+    }
+
+    pub fn pending_keys(&self) -> usize {
+        self.kbd.buffer.len()
     }
     
     fn read_pa(&mut self) -> u8 {
         if self.port_b & 0x80 == 0x80 {
-            SW1
+            self.sw1
         } else {
             self.key_code
         }
@@ -137,6 +361,8 @@ impl PPI8255 {
     pub fn update(&mut self, pic: &mut PIC8259, cycles: u32) {
         if self.kbd.clear {
             self.kbd.clear = false;
+            self.kbd.full = false;
+            self.kbd.send_delay = KBD_BYTE_CYCLES;
             self.key_code = 0;
             pic.clear_int(IRQs::Irq1);
         }
@@ -153,11 +379,34 @@ impl PPI8255 {
                 self.kbd.count_until_reset = 0;
                 self.kbd.resets_counter += 1;
 
+                self.kbd.buffer.clear();
+                self.kbd.repeat_key = 0;
+                self.kbd.full = true;
                 self.key_code = 0xAA;
                 pic.irq(IRQs::Irq1);
             }
             
         }
+
+        self.kbd.update_repeat(cycles);
+        self.kbd.send_delay = self.kbd.send_delay.saturating_sub(cycles);
+
+        // El siguiente scancode solo sale cuando se ha reconocido el anterior,
+        // con PB7 otra vez a 0 y el reloj del teclado (PB6) activo
+        let ready = !self.kbd.full && self.kbd.send_delay == 0 && self.port_b & 0xC0 == 0x40;
+        if ready {
+            if let Some(key_code) = self.kbd.buffer.pop_front() {
+                self.kbd.full = true;
+                self.key_code = key_code;
+                pic.irq(IRQs::Irq1);
+            }
+        }
+    }
+}
+
+impl Default for PPI8255 {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -198,7 +447,7 @@ impl Peripheral for PPI8255 {
         } else if self.kbd.clk_low {
             self.kbd.clk_low = false;
             
-            if self.kbd.low_count > KBD_RESET_CYCLES {
+            if self.kbd.low_count >= KBD_RESET_CYCLES {
                 self.kbd.reset = true;
                 self.kbd.low_count = 0;
                 self.kbd.count_until_reset = 0;
@@ -208,4 +457,57 @@ impl Peripheral for PPI8255 {
             self.kbd.low_count = 0;
         }
     }
-}
\ No newline at end of file
+}
+
+impl Snapshot for Keyboard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.clear);
+        w.write_bool(self.reset);
+        w.write_bool(self.clk_low);
+        w.write_bool(self.counting_low);
+        w.write_u32(self.low_count);
+        w.write_u32(self.count_until_reset);
+        w.write_u32(self.resets_counter);
+
+        w.write_bytes(&self.buffer.iter().copied().collect::<Vec<_>>());
+        w.write_bool(self.full);
+        w.write_u32(self.send_delay);
+        w.write_u8(self.repeat_key);
+        w.write_u32(self.repeat_cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.clear = r.read_bool()?;
+        self.reset = r.read_bool()?;
+        self.clk_low = r.read_bool()?;
+        self.counting_low = r.read_bool()?;
+        self.low_count = r.read_u32()?;
+        self.count_until_reset = r.read_u32()?;
+        self.resets_counter = r.read_u32()?;
+
+        self.buffer = r.read_bytes()?.iter().copied().collect();
+        self.full = r.read_bool()?;
+        self.send_delay = r.read_u32()?;
+        self.repeat_key = r.read_u8()?;
+        self.repeat_cycles = r.read_u32()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for PPI8255 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.key_code);
+        w.write_u8(self.port_b);
+        w.write_u8(self.port_c);
+        w.write_u8(self.mode_reg);
+        self.kbd.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.key_code = r.read_u8()?;
+        self.port_b = r.read_u8()?;
+        self.port_c = r.read_u8()?;
+        self.mode_reg = r.read_u8()?;
+        self.kbd.load_state(r)
+    }
+}
@@ -0,0 +1,85 @@
+pub const SAMPLE_RATE: u32 = 44100;
+// Reloj del PIT: 14.31818 MHz / 12
+const PIT_CLOCK: u32 = 1_193_182;
+const AMPLITUDE: f32 = 8000.;
+
+// Altavoz del PC: suena la salida del canal 2 del PIT cuando el bit 1 del puerto B esta a 1
+#[derive(Clone)]
+pub struct Speaker {
+    // Solo se generan muestras si alguien las va a recoger
+    pub capture: bool,
+    samples: Vec<i16>,
+
+    // Ciclos del PIT a 1 y totales en la muestra actual
+    high: u32,
+    clocks: u32,
+    phase: u32,
+}
+
+impl Speaker {
+    pub fn new() -> Self {
+        Speaker {
+            capture: false,
+            samples: Vec::new(),
+
+            high: 0,
+            clocks: 0,
+            phase: 0,
+        }
+    }
+
+    // Llamar en cada ciclo del PIT con el nivel del altavoz
+    pub fn clock(&mut self, level: bool) {
+        if !self.capture {
+            return;
+        }
+
+        self.high += level as u32;
+        self.clocks += 1;
+
+        self.phase += SAMPLE_RATE;
+        if self.phase >= PIT_CLOCK {
+            self.phase -= PIT_CLOCK;
+
+            // Media del nivel durante la muestra
+            let level = self.high as f32 / self.clocks as f32;
+            self.samples.push((level * AMPLITUDE) as i16);
+            self.high = 0;
+            self.clocks = 0;
+        }
+    }
+
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+impl Default for Speaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// WAV PCM de 16 bits mono
+pub fn write_wav<W: std::io::Write>(mut out: W, samples: &[i16]) -> std::io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+
+    for sample in samples {
+        out.write_all(&sample.to_le_bytes())?;
+    }
+
+    Ok(())
+}
@@ -1,7 +1,9 @@
-use super::{Peripheral, pic_8259::{PIC8259, IRQs}, ppi_8255::PPI8255};
+use super::{Peripheral, pic_8259::{PIC8259, IRQs}, ppi_8255::PPI8255, speaker::Speaker};
+use crate::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Default)]
 enum Mode {
+    #[default]
     Mode0,
     Mode1,
     Mode2,
@@ -10,210 +12,509 @@ enum Mode {
     Mode5
 }
 
-impl Default for Mode {
-    fn default() -> Self {
-        Mode::Mode0
-    }
-}
-
-#[derive(Default, Clone)]
-pub struct TIM8253 {
-    pub cycles: u32,
+#[derive(Clone, Copy)]
+struct Counter {
+    // Valor del contador en binario, 0 equivale a 65536 (10000 en BCD)
+    count: u32,
+    // Valor inicial tal cual se escribio (CR)
+    reload: u16,
+    latched: bool,
+    latch_val: u16,
+    status_latched: bool,
+    status: u8,
 
-    count: [u16; 3],
-    reload: [u16; 3],
-    latched: [bool; 3],
-    latch_val: [u16; 3],
-    rl_mode: [u8; 3],
-    mode: [Mode; 3],
-    out: [bool; 3],
-    active: [bool; 3],
-    first_clk: [bool; 3],
-    reload_clk: [bool; 3],
+    rl_mode: u8,
+    mode: Mode,
+    bcd: bool,
 
-    toggle: [bool; 3],
+    out: bool,
+    gate: bool,
+    // Hay un valor en CR que todavia no ha pasado al contador
+    null_count: bool,
+    counting: bool,
+    // La siguiente llegada a 0 afecta a la salida (modos 1, 4 y 5)
+    armed: bool,
+    // Flanco de subida en GATE sin atender
+    triggered: bool,
 
-    mode_reg: u8,
+    // Falta el MSB en lectura/escritura LSB+MSB
+    write_msb: bool,
+    read_msb: bool,
 }
 
-impl TIM8253 {
-    pub fn new() -> Self {
-        Self {
-            active: [false; 3],
-            first_clk: [true; 3],
-            reload_clk: [false; 3],
-            toggle: [true; 3],
-            ..Default::default()
+impl Counter {
+    fn new() -> Self {
+        Counter {
+            count: 0,
+            reload: 0,
+            latched: false,
+            latch_val: 0,
+            status_latched: false,
+            status: 0,
+
+            rl_mode: 0b11,
+            mode: Mode::Mode0,
+            bcd: false,
+
+            out: false,
+            gate: true,
+            null_count: false,
+            counting: false,
+            armed: false,
+            triggered: false,
+
+            write_msb: false,
+            read_msb: false,
         }
     }
 
-    fn output(&mut self, channel: usize, state: bool, pic: &mut PIC8259) {
-        if !self.out[channel] && state && channel == 0 {
-            pic.irq(IRQs::Irq0);
+    fn modulus(&self) -> u32 {
+        if self.bcd {10000} else {0x10000}
+    }
+
+    fn initial(&self) -> u32 {
+        let val = if self.bcd {from_bcd(self.reload)} else {self.reload as u32};
+        val % self.modulus()
+    }
+
+    fn load(&mut self) {
+        self.count = self.initial();
+        self.null_count = false;
+        self.counting = true;
+    }
+
+    // En modo 3 la salida esta alta (N+1)/2 ciclos y baja (N-1)/2
+    fn load_half(&mut self) {
+        let mut n = self.initial();
+        if n == 0 {
+            n = self.modulus();
         }
-        self.out[channel] = state;
+
+        let odd = n & 1;
+        let val = if self.out {n + odd} else {n - odd};
+        self.count = val % self.modulus();
+        self.null_count = false;
+        self.counting = true;
+    }
+
+    fn decrement(&mut self, by: u32) {
+        let modulus = self.modulus();
+        self.count = (self.count + modulus - by) % modulus;
+    }
+
+    fn value(&self) -> u16 {
+        if self.bcd {to_bcd(self.count)} else {self.count as u16}
     }
 
-    fn mode0(&mut self, i: usize, pic: &mut PIC8259) {
-        self.count[i] = self.count[i].wrapping_sub(1);
-        if self.count[i] == 0 {
-            self.output(i, true, pic)
+    fn read_status(&self) -> u8 {
+        (self.out as u8) << 7
+            | (self.null_count as u8) << 6
+            | self.rl_mode << 4
+            | (self.mode as u8) << 1
+            | self.bcd as u8
+    }
+
+    fn set_gate(&mut self, level: bool) {
+        if level && !self.gate {
+            self.triggered = true;
+        }
+        if !level && matches!(self.mode, Mode::Mode2 | Mode::Mode3) {
+            self.out = true;
         }
+        self.gate = level;
     }
 
-    fn mode2(&mut self, i: usize, pic: &mut PIC8259) {
-        self.count[i] = self.count[i].wrapping_sub(1);
-        if self.count[i] == 1 {
-            self.output(i, false, pic);
-        } else {
-            self.output(i, true, pic);
-            if self.count[i] == 0 {
-                self.count[i] = self.reload[i];
-            }
+    fn control(&mut self, val: u8) {
+        self.rl_mode = (val >> 4) & 0b11;
+        self.mode = match (val >> 1) & 0b111 {
+            0b000 => Mode::Mode0,
+            0b001 => Mode::Mode1,
+            0b010 | 0b110 => Mode::Mode2,
+            0b011 | 0b111 => Mode::Mode3,
+            0b100 => Mode::Mode4,
+            0b101 => Mode::Mode5,
+            _ => unreachable!(),
+        };
+        self.bcd = val & 1 == 1;
+
+        self.out = self.mode != Mode::Mode0;
+        self.null_count = false;
+        self.counting = false;
+        self.armed = false;
+        self.triggered = false;
+        self.latched = false;
+        self.status_latched = false;
+        self.write_msb = false;
+        self.read_msb = false;
+    }
+
+    fn write(&mut self, val: u8) {
+        match self.rl_mode {
+            0b01 => self.reload = val as u16,
+            0b10 => self.reload = (val as u16) << 8,
+            0b11 => {
+                if self.write_msb {
+                    self.reload = self.reload & 0x00FF | (val as u16) << 8;
+                } else {
+                    self.reload = val as u16;
+                }
+                self.write_msb = !self.write_msb;
+                if self.write_msb {
+                    return;
+                }
+            },
+            _ => unreachable!(),
+        }
+
+        self.null_count = true;
+        match self.mode {
+            // Se carga en el siguiente ciclo y vuelve a empezar
+            Mode::Mode0 => {
+                self.out = false;
+                self.counting = false;
+            },
+            Mode::Mode4 => self.counting = false,
+            // En el resto se usa en la siguiente recarga o disparo
+            _ => {},
         }
     }
 
-    fn mode3(&mut self, i: usize, pic: &mut PIC8259) {
-        let half = (self.reload[i] as f32 / 2.0).ceil() as u16;
+    fn read(&mut self) -> u8 {
+        if self.status_latched {
+            self.status_latched = false;
+            return self.status;
+        }
 
-        self.output(i, self.count[i] <= half, pic);
+        let val = if self.latched {self.latch_val} else {self.value()};
 
-        if self.count[i] % 2 == 0 && self.out[i] && self.first_clk[i] {
-            if self.reload_clk[i] {
-                self.count[i] = self.count[i].wrapping_sub(3);
-                self.reload_clk[i] = false;
-            } else {
-                self.count[i] = self.count[i].wrapping_sub(1);
-            }
-            
-            self.first_clk[i] = false;
-        } else {
-            self.count[i] = self.count[i].wrapping_sub(2);
+        match self.rl_mode {
+            0b01 => {
+                self.latched = false;
+                val as u8
+            },
+            0b10 => {
+                self.latched = false;
+                (val >> 8) as u8
+            },
+            0b11 => {
+                self.read_msb = !self.read_msb;
+                if self.read_msb {
+                    val as u8
+                } else {
+                    self.latched = false;
+                    (val >> 8) as u8
+                }
+            },
+            _ => unreachable!(),
         }
+    }
 
-        if self.count[i] == 0 {
-            self.count[i] = self.reload[i];
-            self.first_clk[i] = true;
-            self.reload_clk[i] = true;
+    fn latch(&mut self) {
+        if !self.latched {
+            self.latch_val = self.value();
+            self.latched = true;
         }
     }
 
-    pub fn update(&mut self, pic: &mut PIC8259, _ppi: &mut PPI8255) {
-        while self.cycles > 3 {
-            for i in 0..3 {
-                if self.active[i] {
-                    match self.mode[i] {
-                        Mode::Mode0 => self.mode0(i, pic),
-                        Mode::Mode2 => self.mode2(i, pic), 
-                        Mode::Mode3 => self.mode3(i, pic),
-
-                        _ => {}, // TODO
+    fn latch_status(&mut self) {
+        if !self.status_latched {
+            self.status = self.read_status();
+            self.status_latched = true;
+        }
+    }
+
+    // Un ciclo de CLK
+    fn tick(&mut self) {
+        match self.mode {
+            // Interrupt on terminal count
+            Mode::Mode0 => {
+                if self.null_count && !self.write_msb {
+                    self.load();
+                } else if self.counting && self.gate && !self.write_msb {
+                    self.decrement(1);
+                    if self.count == 0 {
+                        self.out = true;
                     }
                 }
+            },
+            // Hardware retriggerable one-shot
+            Mode::Mode1 | Mode::Mode5 if self.triggered => {
+                self.triggered = false;
+                if self.null_count || self.counting {
+                    self.load();
+                    self.armed = true;
+                    if self.mode == Mode::Mode1 {
+                        self.out = false;
+                    }
+                }
+            },
+            Mode::Mode1 => {
+                if self.counting {
+                    self.decrement(1);
+                    if self.count == 0 && self.armed {
+                        self.out = true;
+                        self.armed = false;
+                    }
+                }
+            },
+            // Rate generator
+            Mode::Mode2 => {
+                if !self.gate {
+                    return;
+                }
+
+                if self.triggered || (self.null_count && !self.counting) {
+                    self.triggered = false;
+                    self.load();
+                } else if self.counting {
+                    if self.count == 1 {
+                        self.out = true;
+                        self.load();
+                    } else {
+                        self.decrement(1);
+                        if self.count == 1 {
+                            self.out = false;
+                        }
+                    }
+                }
+            },
+            // Square wave
+            Mode::Mode3 => {
+                if !self.gate {
+                    return;
+                }
+
+                if self.triggered || (self.null_count && !self.counting) {
+                    self.triggered = false;
+                    self.out = true;
+                    self.load_half();
+                } else if self.counting {
+                    self.decrement(2);
+                    if self.count == 0 {
+                        self.out = !self.out;
+                        self.load_half();
+                    }
+                }
+            },
+            // Software triggered strobe
+            Mode::Mode4 => {
+                if self.null_count && !self.counting {
+                    self.load();
+                    self.armed = true;
+                } else if self.counting && self.gate {
+                    self.strobe();
+                }
+            },
+            // Hardware triggered strobe
+            Mode::Mode5 => {
+                if self.counting {
+                    self.strobe();
+                }
+            },
+        }
+    }
+
+    // Modos 4 y 5: la salida baja un ciclo al llegar a 0
+    fn strobe(&mut self) {
+        self.out = true;
+        self.decrement(1);
+        if self.count == 0 && self.armed {
+            self.out = false;
+            self.armed = false;
+        }
+    }
+}
+
+fn from_bcd(val: u16) -> u32 {
+    ((val >> 12) & 0xF) as u32 * 1000 + ((val >> 8) & 0xF) as u32 * 100 + ((val >> 4) & 0xF) as u32 * 10 + (val & 0xF) as u32
+}
+
+fn to_bcd(val: u32) -> u16 {
+    (((val / 1000 % 10) << 12) | ((val / 100 % 10) << 8) | ((val / 10 % 10) << 4) | (val % 10)) as u16
+}
+
+#[derive(Clone)]
+pub struct TIM8253 {
+    pub cycles: u32,
+
+    counters: [Counter; 3],
+
+    mode_reg: u8,
+    // Flancos de subida del canal 1 sin atender por el DMA
+    refresh_requests: u32,
+}
+
+impl TIM8253 {
+    pub fn new() -> Self {
+        Self {
+            cycles: 0,
+            counters: [Counter::new(); 3],
+            mode_reg: 0,
+            refresh_requests: 0,
+        }
+    }
+
+    pub fn take_refresh_requests(&mut self) -> u32 {
+        std::mem::take(&mut self.refresh_requests)
+    }
+
+    pub fn out(&self, channel: usize) -> bool {
+        self.counters[channel].out
+    }
+
+    // Para el depurador: cuenta, valor inicial y modo
+    pub fn channel(&self, channel: usize) -> (u32, u16, u8) {
+        let counter = &self.counters[channel];
+        (counter.count, counter.reload, counter.mode as u8)
+    }
+
+    // En el PC las puertas de los canales 0 y 1 estan siempre a 1
+    pub fn set_gate(&mut self, channel: usize, level: bool) {
+        self.counters[channel].set_gate(level);
+    }
+
+    pub fn update(&mut self, pic: &mut PIC8259, ppi: &mut PPI8255, speaker: &mut Speaker) {
+        // El bit 0 del puerto B del PPI es la puerta del canal 2
+        self.set_gate(2, ppi.port_b & 0x01 != 0);
+        // El bit 1 deja pasar la salida del canal 2 al altavoz
+        let speaker_enabled = ppi.port_b & 0x02 != 0;
+
+        while self.cycles > 3 {
+            let out0 = self.counters[0].out;
+            let out1 = self.counters[1].out;
+
+            for counter in &mut self.counters {
+                counter.tick();
+            }
+
+            if !out0 && self.counters[0].out {
+                pic.irq(IRQs::Irq0);
+            }
+            if !out1 && self.counters[1].out {
+                self.refresh_requests += 1;
             }
-            // let bit = (self.out[2] as u8) << 5;
-            // let prev_pc = ppi.read_pc() & 0b11011111;
-            // let new_pc = prev_pc | bit;
-            // ppi.port_c = new_pc;
+
+            speaker.clock(speaker_enabled && self.counters[2].out);
 
             self.cycles -= 4;
         }
+
+        // La salida del canal 2 se lee en el bit 5 del puerto C
+        ppi.port_c = (ppi.port_c & !0x20) | ((self.counters[2].out as u8) << 5);
+    }
+}
+
+impl Default for TIM8253 {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Peripheral for TIM8253 {
     fn port_in(&mut self, port: u16) -> u16 {
         match port {
-            0x40..=0x42 => {
-                let channel = (port & 0b11) as usize;
-
-                let val = if self.latched[channel] {
-                    self.latch_val[channel]
-                } else {
-                    self.count[channel]
-                };
-
-                match self.rl_mode[channel] {
-                    0b01 => {
-                        // Invertir si latched esta activo
-                        self.latched[channel] ^= self.latched[channel];
-                        val as u8 as u16
-                    },
-                    0b10 => {
-                        self.latched[channel] ^= self.latched[channel];
-                        val >> 8
-                    },
-                    0b11 => {
-                        if self.toggle[channel] {
-                            self.toggle[channel] = false;
-                            val as u8 as u16
-                        } else {
-                            self.toggle[channel] = true;
-                            self.latched[channel] ^= self.latched[channel];
-                            val >> 8
-                        }
-                    }
-                    _ => unreachable!()
-                }
-            },
+            0x40..=0x42 => self.counters[(port & 0b11) as usize].read() as u16,
             _ => 0
         }
     }
 
     fn port_out(&mut self, val: u16, port: u16) {
         match port {
-            0x40..=0x42 => {
-                let channel = (port & 0b11) as usize;
-                
-                match self.rl_mode[channel] {
-                    0b01 => {
-                        self.reload[channel] = self.reload[channel] & 0xFF00 | val & 0x00FF;
-                    },
-                    0b10 => {
-                        self.reload[channel] = self.reload[channel] & 0x00FF | val & 0xFF00;
-                    },
-                    0b11 => {
-                        if self.toggle[channel] {
-                            self.toggle[channel] = false;
-                            self.reload[channel] = self.reload[channel] & 0xFF00 | val & 0x00FF;
-                        } else {
-                            self.toggle[channel] = true;
-                            self.reload[channel] = self.reload[channel] & 0x00FF | val & 0xFF00;
-                        }
-                    },
-                    _ => unreachable!()
-                }
-
-                if self.rl_mode[channel] < 0b11 || self.toggle[channel] {
-                    self.count[channel] = self.reload[channel];
-                    self.active[channel] = true;
-                    self.out[channel] = self.mode[channel] == Mode::Mode2 || self.mode[channel] == Mode::Mode3;
-                }
-            },
+            0x40..=0x42 => self.counters[(port & 0b11) as usize].write(val as u8),
             0x43 => {
                 self.mode_reg = val as u8;
                 let channel = ((self.mode_reg & 0b11000000) >> 6) as usize;
                 let access_mode = (self.mode_reg & 0b00110000) >> 4;
-                
-                if access_mode == 0b00 {
-                    self.latch_val[channel] = self.count[channel];
-                    self.latched[channel] = true;
-                } else {
-                    self.rl_mode[channel] = access_mode;
-                    let mode = (self.mode_reg & 0b00001110) >> 1;
-
-                    match mode {
-                        0b000 => self.mode[channel] = Mode::Mode0,
-                        0b001 => self.mode[channel] = Mode::Mode1,
-                        0b010 | 0b110 => self.mode[channel] = Mode::Mode2,
-                        0b011 | 0b111 => self.mode[channel] = Mode::Mode3,
-                        0b100 => self.mode[channel] = Mode::Mode4,
-                        0b101 => self.mode[channel] = Mode::Mode5,
-                        _ => unreachable!(),
+
+                if channel == 0b11 {
+                    // Read-back: bit 5 a 0 latch del contador, bit 4 a 0 latch del estado
+                    for (i, counter) in self.counters.iter_mut().enumerate() {
+                        if self.mode_reg & (2 << i) == 0 {
+                            continue;
+                        }
+                        if self.mode_reg & 0x20 == 0 {
+                            counter.latch();
+                        }
+                        if self.mode_reg & 0x10 == 0 {
+                            counter.latch_status();
+                        }
                     }
+                } else if access_mode == 0b00 {
+                    self.counters[channel].latch();
+                } else {
+                    self.counters[channel].control(self.mode_reg);
                 }
             },
             _ => unreachable!(),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Snapshot for TIM8253 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.cycles);
+
+        for counter in &self.counters {
+            w.write_u32(counter.count);
+            w.write_u16(counter.reload);
+            w.write_bool(counter.latched);
+            w.write_u16(counter.latch_val);
+            w.write_bool(counter.status_latched);
+            w.write_u8(counter.status);
+            w.write_u8(counter.rl_mode);
+            w.write_u8(counter.mode as u8);
+            w.write_bool(counter.bcd);
+            w.write_bool(counter.out);
+            w.write_bool(counter.gate);
+            w.write_bool(counter.null_count);
+            w.write_bool(counter.counting);
+            w.write_bool(counter.armed);
+            w.write_bool(counter.triggered);
+            w.write_bool(counter.write_msb);
+            w.write_bool(counter.read_msb);
+        }
+
+        w.write_u8(self.mode_reg);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.cycles = r.read_u32()?;
+
+        for counter in &mut self.counters {
+            counter.count = r.read_u32()?;
+            counter.reload = r.read_u16()?;
+            counter.latched = r.read_bool()?;
+            counter.latch_val = r.read_u16()?;
+            counter.status_latched = r.read_bool()?;
+            counter.status = r.read_u8()?;
+            counter.rl_mode = r.read_u8()?;
+            if !(1..=3).contains(&counter.rl_mode) {
+                return Err(StateError::Invalid("PIT access mode"));
+            }
+            counter.mode = match r.read_u8()? {
+                0 => Mode::Mode0,
+                1 => Mode::Mode1,
+                2 => Mode::Mode2,
+                3 => Mode::Mode3,
+                4 => Mode::Mode4,
+                5 => Mode::Mode5,
+                _ => return Err(StateError::Invalid("PIT mode")),
+            };
+            counter.bcd = r.read_bool()?;
+            if counter.count >= counter.modulus() {
+                return Err(StateError::Invalid("PIT count"));
+            }
+            counter.out = r.read_bool()?;
+            counter.gate = r.read_bool()?;
+            counter.null_count = r.read_bool()?;
+            counter.counting = r.read_bool()?;
+            counter.armed = r.read_bool()?;
+            counter.triggered = r.read_bool()?;
+            counter.write_msb = r.read_bool()?;
+            counter.read_msb = r.read_bool()?;
+        }
+
+        self.mode_reg = r.read_u8()?;
+        Ok(())
+    }
+}
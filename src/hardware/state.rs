@@ -0,0 +1,170 @@
+use std::fmt::Display;
+
+pub const STATE_MAGIC: &[u8; 8] = b"IBM5150S";
+// Incrementar cada vez que cambie lo que guarda algun componente
+pub const STATE_VERSION: u16 = 12;
+
+// Componentes cuyo estado se puede guardar y restaurar
+pub trait Snapshot {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(std::io::Error),
+    BadMagic,
+    Version(u16),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::Io(err) => write!(f, "{}", err),
+            StateError::BadMagic => write!(f, "not a save state file"),
+            StateError::Version(v) => write!(f, "unsupported save state version {} (expected {})", v, STATE_VERSION),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "invalid value for {} in save state", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<std::io::Error> for StateError {
+    fn from(err: std::io::Error) -> Self {
+        StateError::Io(err)
+    }
+}
+
+// Todos los valores se escriben en little endian
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { buf: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    // Bloque de bytes precedido de su longitud
+    pub fn write_bytes(&mut self, val: &[u8]) {
+        self.write_u32(val.len() as u32);
+        self.buf.extend_from_slice(val);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.pos + len > self.data.len() {
+            return Err(StateError::Truncated);
+        }
+
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("bool")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    // Lee un bloque de bytes que tiene que medir exactamente lo mismo que dst
+    pub fn read_into(&mut self, dst: &mut [u8], what: &'static str) -> Result<(), StateError> {
+        let src = self.read_bytes()?;
+        if src.len() != dst.len() {
+            return Err(StateError::Invalid(what));
+        }
+        dst.copy_from_slice(src);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+pub fn write_header(w: &mut StateWriter) {
+    for b in STATE_MAGIC {
+        w.write_u8(*b);
+    }
+    w.write_u16(STATE_VERSION);
+}
+
+pub fn read_header(r: &mut StateReader) -> Result<(), StateError> {
+    if r.take(STATE_MAGIC.len()).map_err(|_| StateError::BadMagic)? != STATE_MAGIC {
+        return Err(StateError::BadMagic);
+    }
+
+    let version = r.read_u16()?;
+    if version != STATE_VERSION {
+        return Err(StateError::Version(version));
+    }
+
+    Ok(())
+}
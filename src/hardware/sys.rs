@@ -1,56 +1,117 @@
-// use std::fs::File;
-use std::fs::File;
-
-use super::cpu_8088::{CPU, cpu_utils::get_address};
+use super::cpu_8088::{CPU, biu::BIU};
 use super::bus::Bus;
+use super::fpu_8087::FPU8087;
+use super::config::{MachineConfig, RomError, read_rom, read_font};
+use super::peripheral::floppy_disk::{FloppyDisk, DiskError};
+use super::peripheral::ppi_8255::decode_key;
+use super::state::{Snapshot, StateWriter, StateReader, StateError, write_header, read_header};
+use crate::util::debugger::{Debugger, linear};
+use crate::util::trace::{Tracer, TraceRecord};
+use crate::util::rewind::Rewind;
+use crate::util::input::{InputLog, InputEvent, InputReplay};
+use ggez::input::keyboard::KeyCode;
 
-use std::fs::OpenOptions;
+use std::path::Path;
 
 pub struct System {
     pub cpu: CPU,
     pub bus: Bus,
 
     pub running: bool,
+    pub config: MachineConfig,
 
-    pub file: File,
     cycles_step: u32,
+    pub total_cycles: u64,
+
+    pub debugger: Debugger,
+    // Traza de las instrucciones ejecutadas
+    pub tracer: Option<Tracer>,
+    // Historia para volver atras
+    pub rewind: Option<Rewind>,
+    // Grabacion de lo que se teclea, y grabacion que se esta repitiendo
+    pub recording: Option<InputLog>,
+    pub replay: Option<InputReplay>,
 }
 
 impl System {
     pub fn new() -> Self {
-        let sys = System { 
-            cpu: CPU::new(),
-            bus: Bus::new(),
+        System::with_config(MachineConfig::default())
+    }
+
+    pub fn with_config(config: MachineConfig) -> Self {
+        System { 
+            cpu: configured_cpu(&config),
+            bus: configured_bus(&config),
 
             running: false,
+            config,
 
-            file: OpenOptions::new().create(true).write(true).open("logs/logs.txt").unwrap(),
             cycles_step: 0,
-        };
-      
-        sys
+            total_cycles: 0,
+
+            debugger: Debugger::new(),
+            tracer: None,
+            rewind: None,
+            recording: None,
+            replay: None,
+        }
+    }
+}
+
+fn configured_cpu(config: &MachineConfig) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.fpu = config.fpu.then(FPU8087::new);
+    cpu.biu = config.accurate_timing.then(BIU::new);
+    cpu
+}
+
+// Bus con los switches de la placa segun la configuracion
+fn configured_bus(config: &MachineConfig) -> Bus {
+    let mut bus = Bus::new();
+    bus.ppi.set_floppy_drives(config.floppy_drives);
+    bus.ppi.set_display(config.display);
+    bus.ppi.set_typematic(config.typematic_delay, config.typematic_rate);
+    bus.ppi.set_fpu(config.fpu);
+    bus
+}
+
+impl Default for System {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-use crate::{DESIRED_FPS, util::debug_bios::debug_82};
+use crate::{FRAME_CYCLES, util::debug_bios::debug_82};
 
 impl System {
     pub fn rst(&mut self) {
-        self.cpu = CPU::new();
-        self.bus = Bus::new();
+        // Los disquetes siguen en las unidades despues del reset
+        let drives = std::mem::take(&mut self.bus.fdc.drives);
+        let capture = self.bus.speaker.capture;
+
+        self.cpu = configured_cpu(&self.config);
+        self.bus = configured_bus(&self.config);
+        self.bus.fdc.drives = drives;
+        self.bus.speaker.capture = capture;
 
         self.running = false;
+        self.total_cycles = 0;
+        self.reset_rewind();
     }
 
     // Llamar cada frame
     pub fn update(&mut self) {
-        let max_cycles = (4_772_726.7 / DESIRED_FPS) as u32;
+        self.run_cycles(FRAME_CYCLES);
+    }
+
+    pub fn run_cycles(&mut self, max_cycles: u32) {
         let mut cycles_ran = 0;
 
         while cycles_ran <= max_cycles {
             if self.cpu.halted {
-                print!("HALTED\r");
+                self.replay_input();
                 cycles_ran += 1;
+                self.total_cycles += 1;
                 continue;
             }
             self.step(&mut cycles_ran);
@@ -59,11 +120,24 @@ impl System {
 
     #[inline]
     pub fn step(&mut self, cycles_ran: &mut u32) {
-        if get_address(&mut self.cpu) == 0xFF123 {
-            let _a = 0;
+        // Las teclas entran antes del punto de control, como cuando llegan entre dos frames
+        self.replay_input();
+        match &self.rewind {
+            Some(rewind) if rewind.due(self.total_cycles) => self.checkpoint(),
+            // Sin rebobinado el diario no tiene que crecer
+            None if self.bus.journal.enabled => self.bus.journal = Default::default(),
+            _ => {},
         }
-        
+
         debug_82(&mut self.cpu);
+
+        let record = self.tracer.as_ref()
+            .filter(|tracer| tracer.wants(self.total_cycles, linear(self.cpu.cs, self.cpu.ip)))
+            .map(|_| TraceRecord::capture(&self.cpu, &self.bus, self.total_cycles));
+        if record.is_some() {
+            self.bus.trace.enabled = true;
+        }
+
         let (cycles, _ip) = self.cpu.fetch_decode_execute(&mut self.bus);
         self.cycles_step = cycles;
         // println!("{:04X}", _ip);
@@ -73,30 +147,174 @@ impl System {
         // ACTUALIZAR PERIFERICOS
         self.bus.update_peripherals(cycles);
 
+        if let (Some(mut record), Some(tracer)) = (record, &mut self.tracer) {
+            self.bus.trace.enabled = false;
+            record.accesses = self.bus.trace.take();
+            tracer.record(&record);
+        }
+
         *cycles_ran += cycles;
+        self.total_cycles += cycles as u64;
+        if let Some(rewind) = &mut self.rewind {
+            rewind.instructions += 1;
+        }
     }
 
-    pub fn load_roms(&mut self) {
-        // BASIC
-        for (idx, element) in std::fs::read("roms/basic_1.10/IBM_5150-C1.10-U29-5000019.bin").unwrap().into_iter().enumerate() {
-            self.bus.memory[0xF6000 + idx] = element;
+    pub fn load_roms(&mut self) -> Result<(), RomError> {
+        for rom in self.config.roms() {
+            let data = read_rom(rom)?;
+            self.bus.memory[rom.address..rom.address + data.len()].copy_from_slice(&data);
         }
 
-        for (idx, element) in std::fs::read("roms/basic_1.10/IBM_5150-C1.10-U30-5000021.bin").unwrap().into_iter().enumerate() {
-            self.bus.memory[0xF8000 + idx] = element;
+        let font = read_font(&self.config.font)?;
+        self.bus.cga.font = font.clone();
+        self.bus.mda.font = font;
+
+        Ok(())
+    }
+}
+
+// Disquetes
+impl System {
+    pub fn mount_floppy<P: AsRef<Path>>(&mut self, drive: usize, path: P) -> Result<(), DiskError> {
+        let disk = FloppyDisk::open(path)?;
+        self.insert_floppy(drive, disk)
+    }
+
+    pub fn insert_floppy(&mut self, drive: usize, disk: FloppyDisk) -> Result<(), DiskError> {
+        if drive >= self.config.floppy_drives as usize || drive >= self.bus.fdc.drives.len() {
+            return Err(DiskError::NoDrive(drive));
         }
-        
-        for (idx, element) in std::fs::read("roms/basic_1.10/IBM_5150-C1.10-U31-5000022.bin").unwrap().into_iter().enumerate() {
-            self.bus.memory[0xFA000 + idx] = element;
+
+        self.bus.fdc.drives[drive] = Some(disk);
+        Ok(())
+    }
+
+    pub fn eject_floppy(&mut self, drive: usize) -> Option<FloppyDisk> {
+        self.bus.fdc.drives.get_mut(drive)?.take()
+    }
+
+    // Guarda en sus ficheros lo que se haya escrito en los disquetes
+    pub fn flush_floppies(&self) -> Result<(), DiskError> {
+        for disk in self.bus.fdc.drives.iter().flatten() {
+            disk.flush()?;
         }
-        
-        for (idx, element) in std::fs::read("roms/basic_1.10/IBM_5150-C1.10-U32-5000023.bin").unwrap().into_iter().enumerate() {
-            self.bus.memory[0xFC000 + idx] = element;
+        Ok(())
+    }
+}
+
+// Save states
+impl System {
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        write_header(&mut w);
+
+        w.write_u64(self.total_cycles);
+        w.write_u32(self.cycles_step);
+        w.write_bool(self.running);
+        self.cpu.save_state(&mut w);
+        self.bus.save_state(&mut w);
+
+        w.into_inner()
+    }
+
+    // Si el estado no es valido el System se queda como estaba
+    pub fn restore(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data);
+        read_header(&mut r)?;
+
+        let total_cycles = r.read_u64()?;
+        let cycles_step = r.read_u32()?;
+        let running = r.read_bool()?;
+
+        let mut cpu = CPU::new();
+        cpu.load_state(&mut r)?;
+        let mut bus = self.bus.clone();
+        bus.load_state(&mut r)?;
+
+        if !r.is_empty() {
+            return Err(StateError::Invalid("trailing data"));
         }
-        
-        // BIOS
-        for (idx, element) in std::fs::read("roms/BIOS_IBM5150_27OCT82_1501476_U33.BIN").unwrap().into_iter().enumerate() {
-            self.bus.memory[0xFE000 + idx] = element;
+
+        self.total_cycles = total_cycles;
+        self.cycles_step = cycles_step;
+        self.running = running;
+        self.cpu = cpu;
+        self.bus = bus;
+        self.reset_rewind();
+
+        Ok(())
+    }
+
+    // Como snapshot pero sin la cabecera, la RAM ni los disquetes, para los puntos de control del rebobinado
+    pub fn save_devices(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.write_u64(self.total_cycles);
+        w.write_u32(self.cycles_step);
+        w.write_bool(self.running);
+        self.cpu.save_state(&mut w);
+        self.bus.save_devices(&mut w);
+        w.into_inner()
+    }
+
+    pub fn restore_devices(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data);
+        let total_cycles = r.read_u64()?;
+        let cycles_step = r.read_u32()?;
+        let running = r.read_bool()?;
+
+        let mut cpu = CPU::new();
+        cpu.load_state(&mut r)?;
+        let mut bus = self.bus.clone();
+        bus.load_devices(&mut r)?;
+
+        if !r.is_empty() {
+            return Err(StateError::Invalid("trailing data"));
         }
+
+        self.total_cycles = total_cycles;
+        self.cycles_step = cycles_step;
+        self.running = running;
+        self.cpu = cpu;
+        self.bus = bus;
+
+        Ok(())
+    }
+
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), StateError> {
+        std::fs::write(path, self.snapshot())?;
+        Ok(())
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(&mut self, path: P) -> Result<(), StateError> {
+        let data = std::fs::read(path)?;
+        self.restore(&data)
     }
-}
\ No newline at end of file
+}
+
+// Teclado. Lo que se teclea entra por aqui para que el rebobinado lo pueda repetir
+impl System {
+    pub fn key_input(&mut self, key_code: u8) {
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(InputEvent { cycle: self.total_cycles, key_code });
+        }
+        if let Some(rewind) = &mut self.rewind {
+            rewind.key_input(key_code);
+        }
+        self.bus.ppi.key_input(key_code);
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode) {
+        let key_code = decode_key(keycode);
+        if key_code != 0 {
+            self.key_input(key_code);
+        }
+    }
+
+    pub fn key_up(&mut self, keycode: KeyCode) {
+        let key_code = decode_key(keycode);
+        if key_code != 0 {
+            self.key_input(key_code | 0x80);
+        }
+    }
+}
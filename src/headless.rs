@@ -0,0 +1,133 @@
+use crate::FRAME_CYCLES;
+use crate::hardware::config::{MachineConfig, RomError, DisplayType};
+use crate::hardware::peripheral::speaker::write_wav;
+use crate::hardware::peripheral::ppi_8255::{ascii_key, SCANCODE_LSHIFT};
+use crate::hardware::sys::System;
+
+use std::path::Path;
+
+const MDA_VRAM_START: usize = 0xB0000;
+const MDA_VRAM_END: usize = 0xB0FA0;
+const CGA_VRAM_START: usize = 0xB8000;
+const CGA_VRAM_END: usize = 0xBC000;
+
+// Ciclos que se mantiene pulsada una tecla al usar press_key, un frame (~20 ms)
+const KEY_HOLD_CYCLES: u32 = FRAME_CYCLES;
+
+// Ejecuta el System sin ventana, para tests y CI
+pub struct Headless {
+    pub sys: System,
+}
+
+impl Headless {
+    pub fn new() -> Self {
+        Headless {
+            sys: System::new(),
+        }
+    }
+
+    // Crea la maquina con las ROMs ya cargadas, lista para arrancar
+    pub fn with_config(config: MachineConfig) -> Result<Self, RomError> {
+        let mut sys = System::with_config(config);
+        sys.load_roms()?;
+
+        Ok(Headless { sys })
+    }
+
+    pub fn run_cycles(&mut self, cycles: u32) {
+        self.sys.run_cycles(cycles);
+    }
+
+    pub fn run_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.sys.update();
+        }
+    }
+
+    // Ejecuta frames hasta que aparezca el texto en pantalla o se acaben los frames
+    pub fn run_until_text(&mut self, text: &str, max_frames: u32) -> bool {
+        for _ in 0..max_frames {
+            if self.screen_contains(text) {
+                return true;
+            }
+            self.sys.update();
+        }
+
+        self.screen_contains(text)
+    }
+
+    pub fn screen_rows(&self) -> Vec<String> {
+        match self.sys.config.display {
+            DisplayType::Mda => self.sys.bus.mda.text_rows(&self.sys.bus.memory[MDA_VRAM_START..MDA_VRAM_END]),
+            DisplayType::Cga40 | DisplayType::Cga80 => self.sys.bus.cga.text_rows(&self.sys.bus.memory[CGA_VRAM_START..CGA_VRAM_END]),
+        }
+    }
+
+    pub fn screen_text(&self) -> String {
+        self.screen_rows().join("\n")
+    }
+
+    pub fn screen_contains(&self, text: &str) -> bool {
+        self.screen_rows().iter().any(|row| row.contains(text))
+    }
+
+    // Empieza a guardar las muestras del altavoz
+    pub fn start_audio(&mut self) {
+        self.sys.bus.speaker.capture = true;
+    }
+
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        self.sys.bus.speaker.take_samples()
+    }
+
+    // Escribe las muestras pendientes en un WAV
+    pub fn save_wav<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        write_wav(file, &self.take_samples())
+    }
+
+    pub fn key_down(&mut self, scancode: u8) {
+        self.sys.key_input(scancode);
+    }
+
+    pub fn key_up(&mut self, scancode: u8) {
+        self.sys.key_input(scancode | 0x80);
+    }
+
+    pub fn press_key(&mut self, scancode: u8) {
+        self.key_down(scancode);
+        self.run_cycles(KEY_HOLD_CYCLES);
+        self.key_up(scancode);
+        self.run_cycles(KEY_HOLD_CYCLES);
+    }
+
+    // Escribe el texto como si se tecleara, pulsando Shift cuando hace falta.
+    // Si hay un caracter sin tecla no se escribe nada y se devuelve
+    pub fn type_text(&mut self, text: &str) -> Result<(), char> {
+        let keys = text.chars()
+            .map(|c| ascii_key(c).ok_or(c))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (scancode, shift) in keys {
+            if shift {
+                self.key_down(SCANCODE_LSHIFT);
+                self.run_cycles(KEY_HOLD_CYCLES);
+            }
+
+            self.press_key(scancode);
+
+            if shift {
+                self.key_up(SCANCODE_LSHIFT);
+                self.run_cycles(KEY_HOLD_CYCLES);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,10 +1,22 @@
 pub mod hardware;
 pub mod util;
+pub mod headless;
+pub mod audio;
 
 // A
 use ggez::graphics::{Drawable, DrawParam};
 use hardware::display::DisplayAdapter;
+use std::path::PathBuf;
 pub use hardware::sys::System;
+pub use hardware::config::{MachineConfig, RomImage, RomError, DisplayType};
+pub use hardware::state::StateError;
+pub use hardware::peripheral::floppy_disk::{FloppyDisk, DiskError};
+pub use headless::Headless;
+pub use audio::AudioOutput;
+pub use util::gdb::GdbStub;
+pub use util::trace::{Tracer, TraceError};
+pub use util::rewind::{Rewind, RewindError};
+pub use util::input::{InputLog, InputError};
 
 pub use ggez::conf::WindowMode;
 pub use ggez::{GameError, GameResult};
@@ -14,17 +26,51 @@ pub use ggez::graphics::{self, Color};
 pub use ggez::timer::check_update_time;
 
 pub const DESIRED_FPS: f32 = 50.;
+// Ciclos por segundo del 8088 a 4.77 MHz, y los que corre cada frame
+pub const CPU_CLOCK: u64 = 4_772_727;
+pub const FRAME_CYCLES: u32 = (CPU_CLOCK as f32 / DESIRED_FPS) as u32;
+// Lo que vuelve atras cada pulsacion de F12
+pub const REWIND_KEY_SECONDS: u64 = 5;
 
 pub struct IbmPc {
     pub sys: System,
+    pub audio: Option<AudioOutput>,
+    // Con GDB conectado la maquina solo corre cuando este la deja
+    pub gdb: Option<GdbStub>,
+    // Donde se guarda al salir lo que se ha tecleado
+    pub record: Option<PathBuf>,
 }
 
 impl IbmPc {
     pub fn new() -> Self {
         IbmPc {
-            sys: System::new()
+            sys: System::new(),
+            audio: None,
+            gdb: None,
+            record: None,
         }
     }
+
+    pub fn with_config(config: MachineConfig) -> Self {
+        IbmPc {
+            sys: System::with_config(config),
+            audio: None,
+            gdb: None,
+            record: None,
+        }
+    }
+
+    // Abre la salida de audio para el altavoz si hay dispositivo
+    pub fn enable_audio(&mut self) {
+        self.audio = AudioOutput::open();
+        self.sys.bus.speaker.capture = self.audio.is_some();
+    }
+}
+
+impl Default for IbmPc {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EventHandler for IbmPc {
@@ -32,10 +78,21 @@ impl EventHandler for IbmPc {
         // let mut veces = 0;
 
         while check_update_time(ctx, DESIRED_FPS as u32) {
-            self.sys.update();
+            match &mut self.gdb {
+                Some(gdb) => {
+                    if let Err(err) = gdb.update(&mut self.sys) {
+                        eprintln!("gdb: {}", err);
+                    }
+                },
+                None => self.sys.update(),
+            }
             // veces += 1;
         }
 
+        if let Some(audio) = &self.audio {
+            audio.push(&self.sys.bus.speaker.take_samples());
+        }
+
         // println!("{}", ggez::timer::fps(ctx));
 
         Ok(())
@@ -44,20 +101,56 @@ impl EventHandler for IbmPc {
     fn draw(&mut self, ctx: &mut ggez::Context) -> Result<(), GameError> {
         // graphics::clear(ctx, Color::RED);
         // TODO
-        let img = self.sys.bus.mda.create_frame(ctx, &self.sys.bus.memory[0xB0000..0xB0FA0]);
-
-        img.draw(ctx, DrawParam::default())?;
+        match self.sys.config.display {
+            DisplayType::Mda => {
+                let img = self.sys.bus.mda.create_frame(ctx, &self.sys.bus.memory[0xB0000..0xB0FA0]);
+                img.draw(ctx, DrawParam::default())?;
+            },
+            DisplayType::Cga40 | DisplayType::Cga80 => {
+                let mut img = self.sys.bus.cga.create_frame(ctx, &self.sys.bus.memory[0xB8000..0xBC000]);
+                img.set_filter(graphics::FilterMode::Nearest);
+                // 640x200 estirado a la ventana de 720x350
+                img.draw(ctx, DrawParam::default().scale([720. / 640., 350. / 200.]))?;
+            },
+        }
 
         graphics::present(ctx)
     }
 
+    fn quit_event(&mut self, _ctx: &mut ggez::Context) -> bool {
+        if let Err(err) = self.sys.flush_floppies() {
+            eprintln!("{}", err);
+        }
+        if let Some(tracer) = self.sys.tracer.take() {
+            if let Err(err) = tracer.finish() {
+                eprintln!("trace: {}", err);
+            }
+        }
+        if let (Some(path), Some(recording)) = (&self.record, &self.sys.recording) {
+            if let Err(err) = recording.save_file(path) {
+                eprintln!("record: {}", err);
+            }
+        }
+
+        false
+    }
+
     fn key_up_event(&mut self, _ctx: &mut ggez::Context, keycode: event::KeyCode, _keymods: event::KeyMods) {
-        self.sys.bus.ppi.key_up(keycode, &mut self.sys.bus.pic);
+        self.sys.key_up(keycode);
     }
 
     fn key_down_event(&mut self, _ctx: &mut ggez::Context, keycode: event::KeyCode, _keymods: event::KeyMods, repeat: bool,) {
+        // F12 no existe en el teclado del PC: rebobina unos segundos
+        if keycode == KeyCode::F12 && self.sys.rewind.is_some() {
+            if let Err(err) = self.sys.rewind_cycles(REWIND_KEY_SECONDS * CPU_CLOCK) {
+                eprintln!("rewind: {}", err);
+            }
+            return;
+        }
+
+        // La repeticion la genera el propio teclado emulado
         if !repeat {
-            self.sys.bus.ppi.key_down(keycode, &mut self.sys.bus.pic);
+            self.sys.key_down(keycode);
         }
     }
 }
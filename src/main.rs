@@ -2,7 +2,11 @@ use ibm_5150::*;
 
 // #[cfg(not(debug_assertions))]
 fn main() -> GameResult {
-    let mut app = IbmPc::new();
+    let args: Vec<String> = std::env::args().collect();
+
+    let (config, floppies) = MachineConfig::from_args(&args).map_err(|e| GameError::ConfigError(e.to_string()))?;
+
+    let mut app = IbmPc::with_config(config);
     let win_mode = WindowMode::default()
                             .dimensions(720., 350.)
                             .resize_on_scale_factor_change(true);
@@ -14,8 +18,47 @@ fn main() -> GameResult {
 
     //graphics::set_mode(&mut ctx, win_mode)?;
 
+    // --gdb puerto, para conectar con "target remote localhost:puerto"
+    if let Some(port) = args.windows(2).find(|w| w[0] == "--gdb").map(|w| &w[1]) {
+        let port = port.parse::<u16>().map_err(|e| GameError::ConfigError(e.to_string()))?;
+        app.gdb = Some(GdbStub::bind(("127.0.0.1", port)).map_err(|e| GameError::ConfigError(e.to_string()))?);
+    }
+
+    // --trace fichero, con --trace-range INICIO-FIN (lineal, hex, se puede repetir) y --trace-cycles DESDE-HASTA
+    if let Some(path) = args.windows(2).find(|w| w[0] == "--trace").map(|w| &w[1]) {
+        let mut tracer = Tracer::create(path).map_err(|e| GameError::ResourceLoadError(e.to_string()))?;
+        for range in args.windows(2).filter(|w| w[0] == "--trace-range").map(|w| &w[1]) {
+            let range = range.split_once('-')
+                .and_then(|(start, end)| Some((u32::from_str_radix(start, 16).ok()?, u32::from_str_radix(end, 16).ok()?)))
+                .ok_or_else(|| GameError::ConfigError(format!("invalid trace range '{}'", range)))?;
+            tracer.ranges.push(range);
+        }
+        if let Some(window) = args.windows(2).find(|w| w[0] == "--trace-cycles").map(|w| &w[1]) {
+            tracer.cycles = window.split_once('-')
+                .and_then(|(start, end)| Some(start.parse().ok()?..end.parse().ok()?))
+                .ok_or_else(|| GameError::ConfigError(format!("invalid cycle window '{}'", window)))?;
+        }
+        app.sys.tracer = Some(tracer);
+    }
+
+    // --rewind segundos de historia, para volver atras con F12
+    if let Some(seconds) = args.windows(2).find(|w| w[0] == "--rewind").map(|w| &w[1]) {
+        let seconds = seconds.parse::<u64>().map_err(|e| GameError::ConfigError(e.to_string()))?;
+        app.sys.rewind = Some(Rewind::seconds(seconds));
+    }
+
     app.sys.rst();
-    app.sys.load_roms();
+    app.enable_audio();
+    app.sys.load_roms().map_err(|e| GameError::ResourceLoadError(e.to_string()))?;
+    for (drive, path) in floppies.iter().enumerate() {
+        app.sys.mount_floppy(drive, path).map_err(|e| GameError::ResourceLoadError(e.to_string()))?;
+    }
+
+    // --record fichero, graba lo que se teclea desde el arranque para repetirlo con replay
+    if let Some(path) = args.windows(2).find(|w| w[0] == "--record").map(|w| &w[1]) {
+        app.record = Some(path.into());
+        app.sys.start_recording();
+    }
 
     event::run(ctx, event_loop, app);
 }
@@ -0,0 +1,696 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+
+use crate::CPU_CLOCK;
+use crate::hardware::bus::Bus;
+use crate::hardware::cpu_8088::CPU;
+use crate::hardware::cpu_8088::instr_utils::Opcode;
+use crate::hardware::sys::System;
+use crate::util::rewind::RewindError;
+
+// Un segundo de la maquina emulada, lo que corre un "continue" sin limite
+pub const CONTINUE_CYCLES: u64 = CPU_CLOCK;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn matches(self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::ReadWrite => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+    // Antes de ejecutar la instruccion en CS:IP
+    Exec(u16, u16),
+    // Antes de ejecutar la instruccion en esa direccion lineal, con cualquier segmento
+    Linear(u32),
+    // Despues de una instruccion que accede al rango (inclusivo) de memoria
+    Memory(u32, u32, Access),
+    // Despues de una instruccion que accede al puerto
+    Port(u16, Access),
+    // Al entrar en la interrupcion, sea INT n, una excepcion o hardware
+    Interrupt(u8),
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let access = |access: &Access| match access {
+            Access::Read => "r",
+            Access::Write => "w",
+            Access::ReadWrite => "rw",
+        };
+
+        match self {
+            Breakpoint::Exec(cs, ip) => write!(f, "exec {:04X}:{:04X}", cs, ip),
+            Breakpoint::Linear(address) => write!(f, "exec {:05X}", address),
+            Breakpoint::Memory(start, end, a) => write!(f, "watch {} {:05X}-{:05X}", access(a), start, end),
+            Breakpoint::Port(port, a) => write!(f, "port {} {:04X}", access(a), port),
+            Breakpoint::Interrupt(vector) => write!(f, "int {:02X}", vector),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DebugError {
+    Syntax(String),
+    NoBreakpoint(usize),
+    Rewind(RewindError),
+}
+
+impl Display for DebugError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DebugError::Syntax(text) => write!(f, "could not parse '{}'", text),
+            DebugError::NoBreakpoint(id) => write!(f, "no breakpoint {}", id),
+            DebugError::Rewind(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for DebugError {}
+
+impl From<RewindError> for DebugError {
+    fn from(err: RewindError) -> Self {
+        DebugError::Rewind(err)
+    }
+}
+
+fn syntax(text: &str) -> DebugError {
+    DebugError::Syntax(text.to_string())
+}
+
+// Los numeros van en hexadecimal, como en el DEBUG de DOS. El 0x es opcional
+fn parse_hex(text: &str) -> Result<u32, DebugError> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u32::from_str_radix(digits, 16).map_err(|_| syntax(text))
+}
+
+// SEG:OFF o una direccion lineal
+fn parse_address(text: &str) -> Result<u32, DebugError> {
+    match text.split_once(':') {
+        Some((segment, offset)) => Ok(linear(parse_hex(segment)? as u16, parse_hex(offset)? as u16)),
+        None => Ok(parse_hex(text)? & 0xFFFFF),
+    }
+}
+
+fn parse_access(text: &str) -> Result<Access, DebugError> {
+    match text {
+        "r" => Ok(Access::Read),
+        "w" => Ok(Access::Write),
+        "rw" => Ok(Access::ReadWrite),
+        _ => Err(syntax(text)),
+    }
+}
+
+pub fn linear(segment: u16, offset: u16) -> u32 {
+    (((segment as u32) << 4) + offset as u32) & 0xFFFFF
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Register {
+    AX, BX, CX, DX,
+    AL, BL, CL, DL,
+    AH, BH, CH, DH,
+    SI, DI, BP, SP,
+    CS, DS, ES, SS, IP,
+    Flags,
+    CF, PF, AF, ZF, SF, TF, IF, DF, OF,
+}
+
+impl Register {
+    fn parse(text: &str) -> Result<Self, DebugError> {
+        Ok(match text.to_ascii_uppercase().as_str() {
+            "AX" => Register::AX,
+            "BX" => Register::BX,
+            "CX" => Register::CX,
+            "DX" => Register::DX,
+            "AL" => Register::AL,
+            "BL" => Register::BL,
+            "CL" => Register::CL,
+            "DL" => Register::DL,
+            "AH" => Register::AH,
+            "BH" => Register::BH,
+            "CH" => Register::CH,
+            "DH" => Register::DH,
+            "SI" => Register::SI,
+            "DI" => Register::DI,
+            "BP" => Register::BP,
+            "SP" => Register::SP,
+            "CS" => Register::CS,
+            "DS" => Register::DS,
+            "ES" => Register::ES,
+            "SS" => Register::SS,
+            "IP" => Register::IP,
+            "FLAGS" => Register::Flags,
+            "CF" => Register::CF,
+            "PF" => Register::PF,
+            "AF" => Register::AF,
+            "ZF" => Register::ZF,
+            "SF" => Register::SF,
+            "TF" => Register::TF,
+            "IF" => Register::IF,
+            "DF" => Register::DF,
+            "OF" => Register::OF,
+            _ => return Err(syntax(text)),
+        })
+    }
+
+    fn value(self, cpu: &CPU) -> u32 {
+        (match self {
+            Register::AX => cpu.ax.get_x(),
+            Register::BX => cpu.bx.get_x(),
+            Register::CX => cpu.cx.get_x(),
+            Register::DX => cpu.dx.get_x(),
+            Register::AL => cpu.ax.low as u16,
+            Register::BL => cpu.bx.low as u16,
+            Register::CL => cpu.cx.low as u16,
+            Register::DL => cpu.dx.low as u16,
+            Register::AH => cpu.ax.high as u16,
+            Register::BH => cpu.bx.high as u16,
+            Register::CH => cpu.cx.high as u16,
+            Register::DH => cpu.dx.high as u16,
+            Register::SI => cpu.si,
+            Register::DI => cpu.di,
+            Register::BP => cpu.bp,
+            Register::SP => cpu.sp,
+            Register::CS => cpu.cs,
+            Register::DS => cpu.ds,
+            Register::ES => cpu.es,
+            Register::SS => cpu.ss,
+            Register::IP => cpu.ip,
+            Register::Flags => cpu.flags.get_flags(),
+            Register::CF => cpu.flags.c as u16,
+            Register::PF => cpu.flags.p as u16,
+            Register::AF => cpu.flags.a as u16,
+            Register::ZF => cpu.flags.z as u16,
+            Register::SF => cpu.flags.s as u16,
+            Register::TF => cpu.flags.t as u16,
+            Register::IF => cpu.flags.i as u16,
+            Register::DF => cpu.flags.d as u16,
+            Register::OF => cpu.flags.o as u16,
+        }) as u32
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Term {
+    register: Register,
+    compare: Compare,
+    value: u32,
+}
+
+impl Term {
+    fn parse(text: &str) -> Result<Self, DebugError> {
+        // Los de dos caracteres primero para que "<=" no se lea como "<"
+        const OPERATORS: [(&str, Compare); 6] = [
+            ("==", Compare::Eq), ("!=", Compare::Ne), ("<=", Compare::Le),
+            (">=", Compare::Ge), ("<", Compare::Lt), (">", Compare::Gt),
+        ];
+
+        for (operator, compare) in OPERATORS {
+            if let Some((register, value)) = text.split_once(operator) {
+                return Ok(Term {
+                    register: Register::parse(register.trim())?,
+                    compare,
+                    value: parse_hex(value.trim())?,
+                });
+            }
+        }
+
+        // Un flag solo es "flag == 1"
+        Ok(Term {
+            register: Register::parse(text.trim())?,
+            compare: Compare::Ne,
+            value: 0,
+        })
+    }
+
+    fn eval(&self, cpu: &CPU) -> bool {
+        let reg = self.register.value(cpu);
+        match self.compare {
+            Compare::Eq => reg == self.value,
+            Compare::Ne => reg != self.value,
+            Compare::Lt => reg < self.value,
+            Compare::Le => reg <= self.value,
+            Compare::Gt => reg > self.value,
+            Compare::Ge => reg >= self.value,
+        }
+    }
+}
+
+// Condicion sobre los registros, como "AX == 1234 && CF || CX > 10"
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Condition {
+    text: String,
+    // OR de ANDs
+    terms: Vec<Vec<Term>>,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, DebugError> {
+        let terms = text.split("||")
+            .map(|all| all.split("&&").map(Term::parse).collect::<Result<Vec<_>, _>>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Condition {
+            text: text.trim().to_string(),
+            terms,
+        })
+    }
+
+    pub fn eval(&self, cpu: &CPU) -> bool {
+        self.terms.iter().any(|all| all.iter().all(|term| term.eval(cpu)))
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// Los watchpoints que comprueba el Bus en cada acceso. Solo hay algo mientras corre el depurador
+#[derive(Clone, Default)]
+pub struct Watchpoints {
+    memory: Vec<(usize, u32, u32, Access)>,
+    ports: Vec<(usize, u16, Access)>,
+    hits: RefCell<Vec<usize>>,
+}
+
+impl Watchpoints {
+    #[inline]
+    pub fn memory(&self, address: u32, write: bool) {
+        // Modulo 1 MB, asi un rango que pasa de FFFFF sigue en 00000
+        for &(id, start, end, access) in &self.memory {
+            if address.wrapping_sub(start) & 0xFFFFF <= end.wrapping_sub(start) & 0xFFFFF && access.matches(write) {
+                self.hits.borrow_mut().push(id);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn port(&self, port: u16, write: bool) {
+        for &(id, watched, access) in &self.ports {
+            if watched == port && access.matches(write) {
+                self.hits.borrow_mut().push(id);
+            }
+        }
+    }
+
+    pub fn take_hits(&self) -> Vec<usize> {
+        std::mem::take(&mut self.hits.borrow_mut())
+    }
+}
+
+pub struct BreakpointEntry {
+    pub id: usize,
+    pub breakpoint: Breakpoint,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    pub hits: u64,
+}
+
+impl Display for BreakpointEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>3} {} {}", self.id, if self.enabled {' '} else {'-'}, self.breakpoint)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        write!(f, " ({} hits)", self.hits)
+    }
+}
+
+// Por que se ha parado la ejecucion
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    Breakpoint(usize),
+    // Ha terminado el step, step over o step out
+    Step,
+    Halted,
+    // Se acabaron los ciclos sin llegar a nada
+    Timeout,
+}
+
+pub struct Debugger {
+    breakpoints: Vec<BreakpointEntry>,
+    next_id: usize,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint, condition: Option<Condition>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(BreakpointEntry {
+            id,
+            breakpoint,
+            condition,
+            enabled: true,
+            hits: 0,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Result<(), DebugError> {
+        let index = self.breakpoints.iter().position(|entry| entry.id == id).ok_or(DebugError::NoBreakpoint(id))?;
+        self.breakpoints.remove(index);
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> Result<(), DebugError> {
+        let entry = self.breakpoints.iter_mut().find(|entry| entry.id == id).ok_or(DebugError::NoBreakpoint(id))?;
+        entry.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[BreakpointEntry] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> Watchpoints {
+        let mut watch = Watchpoints::default();
+        for entry in self.breakpoints.iter().filter(|entry| entry.enabled) {
+            match entry.breakpoint {
+                Breakpoint::Memory(start, end, access) => watch.memory.push((entry.id, start, end, access)),
+                Breakpoint::Port(port, access) => watch.ports.push((entry.id, port, access)),
+                _ => {},
+            }
+        }
+        watch
+    }
+
+    fn hit(&mut self, cpu: &CPU, matches: impl Fn(&BreakpointEntry) -> bool) -> Option<usize> {
+        let entry = self.breakpoints.iter_mut().find(|entry| {
+            entry.enabled
+                && matches(entry)
+                && entry.condition.as_ref().is_none_or(|condition| condition.eval(cpu))
+        })?;
+        entry.hits += 1;
+        Some(entry.id)
+    }
+
+    // Antes de ejecutar la instruccion en CS:IP
+    pub fn check_exec(&mut self, cpu: &CPU) -> Option<usize> {
+        let address = linear(cpu.cs, cpu.ip);
+        self.hit(cpu, |entry| match entry.breakpoint {
+            Breakpoint::Exec(cs, ip) => cs == cpu.cs && ip == cpu.ip,
+            Breakpoint::Linear(linear) => linear == address,
+            _ => false,
+        })
+    }
+
+    // Despues de la instruccion, con las interrupciones que se han atendido y los accesos que ha visto el Bus
+    pub fn check_access(&mut self, cpu: &CPU, hits: &[usize]) -> Option<usize> {
+        self.hit(cpu, |entry| match entry.breakpoint {
+            Breakpoint::Interrupt(vector) => cpu.interrupts_taken.contains(&vector),
+            Breakpoint::Memory(..) | Breakpoint::Port(..) => hits.contains(&entry.id),
+            _ => false,
+        })
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Si en CS:IP hay un CALL, un INT o una cadena con REP devuelve donde sigue despues
+pub fn call_return(bus: &Bus, cpu: &CPU) -> Option<u32> {
+    let byte = |i: u16| bus.read_dir(linear(cpu.cs, cpu.ip.wrapping_add(i)) as usize);
+
+    let mut len = 0;
+    let mut rep = false;
+    while len < 16 {
+        match byte(len) {
+            0x26 | 0x2E | 0x36 | 0x3E | 0xF0 | 0xF1 => {},
+            0xF2 | 0xF3 => rep = true,
+            _ => break,
+        }
+        len += 1;
+    }
+
+    let size = match byte(len) {
+        0xE8 => 3,
+        0x9A => 5,
+        0xCC | 0xCE => 1,
+        0xCD => 2,
+        0xA4..=0xA7 | 0xAA..=0xAF if rep => 1,
+        0xFF if matches!(byte(len + 1) & 0x38, 0x10 | 0x18) => {
+            let modrm = byte(len + 1);
+            2 + match (modrm >> 6, modrm & 0x07) {
+                (0, 6) => 2,
+                (1, _) => 1,
+                (2, _) => 2,
+                _ => 0,
+            }
+        },
+        _ => return None,
+    };
+
+    Some(linear(cpu.cs, cpu.ip.wrapping_add(len + size)))
+}
+
+pub fn registers(cpu: &CPU) -> String {
+    let flags = [
+        (cpu.flags.o, "OV", "NV"), (cpu.flags.d, "DN", "UP"), (cpu.flags.i, "EI", "DI"),
+        (cpu.flags.s, "NG", "PL"), (cpu.flags.z, "ZR", "NZ"), (cpu.flags.a, "AC", "NA"),
+        (cpu.flags.p, "PE", "PO"), (cpu.flags.c, "CY", "NC"),
+    ];
+    let flags: Vec<&str> = flags.iter().map(|&(set, on, off)| if set {on} else {off}).collect();
+
+    format!(
+        "AX={:04X}  BX={:04X}  CX={:04X}  DX={:04X}  SP={:04X}  BP={:04X}  SI={:04X}  DI={:04X}\n\
+         DS={:04X}  ES={:04X}  SS={:04X}  CS={:04X}  IP={:04X}   {}{}",
+        cpu.ax.get_x(), cpu.bx.get_x(), cpu.cx.get_x(), cpu.dx.get_x(), cpu.sp, cpu.bp, cpu.si, cpu.di,
+        cpu.ds, cpu.es, cpu.ss, cpu.cs, cpu.ip, flags.join(" "), if cpu.flags.t {" TF"} else {""},
+    )
+}
+
+// Volcado de memoria, 16 bytes por linea
+pub fn dump(bus: &Bus, start: u32, len: u32) -> String {
+    // Como mucho toda la memoria una vez
+    let len = len.min(0x100000);
+    let mut lines = Vec::new();
+    for line in (0..len).step_by(16) {
+        let address = start.wrapping_add(line) & 0xFFFFF;
+        let bytes: Vec<u8> = (0..16.min(len - line)).map(|i| bus.read_dir(((address + i) & 0xFFFFF) as usize)).collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let ascii: String = bytes.iter().map(|&byte| if (0x20..0x7F).contains(&byte) {byte as char} else {'.'}).collect();
+        lines.push(format!("{:05X}  {:<47}  {}", address, hex.join(" "), ascii));
+    }
+    lines.join("\n")
+}
+
+pub const HELP: &str = "\
+b SEG:OFF|LINEAR [if COND]      execution breakpoint
+watch r|w|rw ADDR[-END] [if COND]   memory watchpoint
+port r|w|rw PORT [if COND]      I/O port watchpoint
+int N [if COND]                 interrupt breakpoint
+bl | del ID | enable ID | disable ID
+s [COUNT] | n | o | c [CYCLES]  step, step over, step out, continue
+bs [COUNT] | back CYCLES        step back, rewind (needs rewind enabled)
+r | x ADDR [LEN]                registers, memory dump
+Numbers are hex except COUNT and CYCLES. COND is like 'AX == 1234 && CF || CX > 10'";
+
+impl System {
+    // Ejecuta hasta un breakpoint, hasta que done diga que se ha llegado o hasta agotar los ciclos.
+    // La primera instruccion no comprueba los breakpoints de ejecucion para poder seguir despues de uno
+    fn debug_until(&mut self, max_cycles: u64, done: impl Fn(&System) -> bool) -> Stop {
+        self.bus.watchpoints = self.debugger.watchpoints();
+        let end = self.total_cycles.saturating_add(max_cycles);
+        let mut first = true;
+
+        let stop = loop {
+            if self.cpu.halted {
+                break Stop::Halted;
+            }
+            if !first {
+                if let Some(id) = self.debugger.check_exec(&self.cpu) {
+                    break Stop::Breakpoint(id);
+                }
+            }
+            if self.total_cycles >= end {
+                break Stop::Timeout;
+            }
+            first = false;
+
+            let mut cycles = 0;
+            self.step(&mut cycles);
+
+            let hits = self.bus.watchpoints.take_hits();
+            if let Some(id) = self.debugger.check_access(&self.cpu, &hits) {
+                break Stop::Breakpoint(id);
+            }
+            if done(self) {
+                break Stop::Step;
+            }
+        };
+
+        self.bus.watchpoints = Watchpoints::default();
+        stop
+    }
+
+    pub fn debug_step(&mut self) -> Stop {
+        self.debug_until(u64::MAX, |_| true)
+    }
+
+    pub fn debug_run(&mut self, max_cycles: u64) -> Stop {
+        self.debug_until(max_cycles, |_| false)
+    }
+
+    // Como step, pero los CALL, INT y REP se ejecutan enteros
+    pub fn step_over(&mut self, max_cycles: u64) -> Stop {
+        match call_return(&self.bus, &self.cpu) {
+            Some(next) => {
+                let sp = self.cpu.sp;
+                self.debug_until(max_cycles, move |sys| linear(sys.cpu.cs, sys.cpu.ip) == next && sys.cpu.sp >= sp)
+            },
+            None => self.debug_step(),
+        }
+    }
+
+    // Hasta que un RET o IRET deje la pila por encima de como estaba
+    pub fn step_out(&mut self, max_cycles: u64) -> Stop {
+        let sp = self.cpu.sp;
+        self.debug_until(max_cycles, move |sys| {
+            matches!(sys.cpu.instr.opcode, Opcode::RET | Opcode::IRET) && sys.cpu.sp > sp
+        })
+    }
+
+    pub fn describe_stop(&self, stop: Stop) -> String {
+        let at = format!("{:04X}:{:04X}", self.cpu.cs, self.cpu.ip);
+        match stop {
+            Stop::Breakpoint(id) => format!("breakpoint {} at {}", id, at),
+            Stop::Step => format!("stopped at {}", at),
+            Stop::Halted => format!("halted at {}", at),
+            Stop::Timeout => format!("still running at {}", at),
+        }
+    }
+
+    // Interfaz de texto para las herramientas de linea de comandos
+    pub fn debug_command(&mut self, line: &str) -> Result<String, DebugError> {
+        let (line, condition) = match line.split_once(" if ") {
+            Some((line, condition)) => (line, Some(Condition::parse(condition)?)),
+            None => (line, None),
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let count = |i: usize, default: u64| match args.get(i) {
+            Some(arg) => arg.parse::<u64>().map_err(|_| syntax(arg)),
+            None => Ok(default),
+        };
+        let id = |i: usize| args.get(i).and_then(|arg| arg.parse::<usize>().ok()).ok_or_else(|| syntax(line));
+
+        let breakpoint = match args.as_slice() {
+            ["b", address] => Some(match address.split_once(':') {
+                Some((cs, ip)) => Breakpoint::Exec(parse_hex(cs)? as u16, parse_hex(ip)? as u16),
+                None => Breakpoint::Linear(parse_address(address)?),
+            }),
+            ["watch", access, range] => {
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+                    None => (parse_address(range)?, parse_address(range)?),
+                };
+                Some(Breakpoint::Memory(start, end, parse_access(access)?))
+            },
+            ["port", access, port] => Some(Breakpoint::Port(parse_hex(port)? as u16, parse_access(access)?)),
+            ["int", vector] => Some(Breakpoint::Interrupt(parse_hex(vector)? as u8)),
+            _ => None,
+        };
+        if let Some(breakpoint) = breakpoint {
+            let id = self.debugger.add(breakpoint, condition);
+            return Ok(format!("breakpoint {}: {}", id, breakpoint));
+        }
+        if condition.is_some() {
+            return Err(syntax(line));
+        }
+
+        Ok(match args.first().copied().unwrap_or("s") {
+            "bl" => {
+                let list: Vec<String> = self.debugger.breakpoints().iter().map(|entry| entry.to_string()).collect();
+                list.join("\n")
+            },
+            "del" => {
+                self.debugger.remove(id(1)?)?;
+                String::new()
+            },
+            "enable" | "disable" => {
+                self.debugger.set_enabled(id(1)?, args[0] == "enable")?;
+                String::new()
+            },
+            "s" => {
+                let mut stop = Stop::Step;
+                for _ in 0..count(1, 1)? {
+                    stop = self.debug_step();
+                    if stop != Stop::Step {
+                        break;
+                    }
+                }
+                self.describe_stop(stop)
+            },
+            "bs" => {
+                for _ in 0..count(1, 1)? {
+                    self.step_back()?;
+                }
+                format!("back at {:04X}:{:04X}", self.cpu.cs, self.cpu.ip)
+            },
+            "back" => {
+                let cycles = self.rewind_cycles(count(1, 0)?)?;
+                format!("back {} cycles at {:04X}:{:04X}", cycles, self.cpu.cs, self.cpu.ip)
+            },
+            "n" => {
+                let stop = self.step_over(CONTINUE_CYCLES);
+                self.describe_stop(stop)
+            },
+            "o" => {
+                let stop = self.step_out(CONTINUE_CYCLES);
+                self.describe_stop(stop)
+            },
+            "c" => {
+                let stop = self.debug_run(count(1, CONTINUE_CYCLES)?);
+                self.describe_stop(stop)
+            },
+            "r" => registers(&self.cpu),
+            "x" => {
+                let start = parse_address(args.get(1).ok_or_else(|| syntax(line))?)?;
+                let len = match args.get(2) {
+                    Some(len) => parse_hex(len)?,
+                    None => 0x80,
+                };
+                dump(&self.bus, start, len)
+            },
+            "help" | "?" => HELP.to_string(),
+            _ => return Err(syntax(line)),
+        })
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::FRAME_CYCLES;
+use crate::hardware::cpu_8088::CPU;
+use crate::hardware::sys::System;
+use crate::util::debugger::{Access, Breakpoint, Stop};
+
+// Stub del protocolo remoto de GDB (RSP) sobre TCP. Con "set architecture i8086" GDB usa los
+// registros del i386: AX..DI, IP, FLAGS y los segmentos, 32 bits cada uno.
+// Las direcciones de memoria y de los breakpoints son lineales, de 20 bits
+
+// Registros que manda "g": eax ecx edx ebx esp ebp esi edi eip eflags cs ss ds es fs gs
+const REGISTERS: usize = 16;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+// Lo que hay que hacer despues de un paquete
+enum Reply {
+    Packet(String),
+    // "c" no se contesta hasta que la maquina se pare
+    Nothing,
+    // "D" contesta antes de cerrar, "k" no
+    Close(Option<String>),
+}
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    no_ack: bool,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes())
+    }
+
+    // Lee sin bloquear lo que haya llegado. Devuelve false si GDB ha cerrado
+    fn receive(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut data = [0; 1024];
+        let open = loop {
+            match self.stream.read(&mut data) {
+                Ok(0) => break false,
+                Ok(len) => self.buffer.extend_from_slice(&data[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break true,
+                Err(err) => {
+                    self.stream.set_nonblocking(false)?;
+                    return Err(err);
+                },
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        Ok(open)
+    }
+
+    // Siguiente paquete completo del buffer. Ctrl+C llega como un 0x03 suelto
+    fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let Some(&first) = self.buffer.first() else {
+                return Ok(None);
+            };
+            if first == 0x03 {
+                self.buffer.remove(0);
+                return Ok(Some(vec![0x03]));
+            }
+            if first != b'$' {
+                // Los + y - de GDB y cualquier basura
+                self.buffer.remove(0);
+                continue;
+            }
+
+            let Some(end) = self.buffer.iter().position(|&byte| byte == b'#') else {
+                return Ok(None);
+            };
+            if self.buffer.len() < end + 3 {
+                return Ok(None);
+            }
+
+            let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+            let data = packet[1..end].to_vec();
+            let checksum = std::str::from_utf8(&packet[end + 1..]).ok().and_then(|text| u8::from_str_radix(text, 16).ok());
+            let valid = checksum == Some(data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+
+            if !self.no_ack {
+                self.stream.write_all(if valid {b"+"} else {b"-"})?;
+            }
+            if valid {
+                return Ok(Some(data));
+            }
+        }
+    }
+}
+
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<Client>,
+    // Breakpoints puestos por GDB: (tipo de Z, direccion) -> id en el depurador
+    breakpoints: HashMap<(u8, u32), usize>,
+    // GDB ha mandado "c" y espera a que la maquina se pare
+    running: bool,
+}
+
+fn hex_u32(val: u32) -> String {
+    val.to_le_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+// Valor little endian de hasta 4 bytes en hexadecimal
+fn parse_le(text: &str) -> Option<u32> {
+    if !text.len().is_multiple_of(2) || text.len() > 8 {
+        return None;
+    }
+    let mut val = 0;
+    for i in (0..text.len()).step_by(2).rev() {
+        val = (val << 8) | parse_hex(text.get(i..i + 2)?)?;
+    }
+    Some(val)
+}
+
+fn register(cpu: &CPU, reg: usize) -> Option<u32> {
+    let val = match reg {
+        0 => cpu.ax.get_x(),
+        1 => cpu.cx.get_x(),
+        2 => cpu.dx.get_x(),
+        3 => cpu.bx.get_x(),
+        4 => cpu.sp,
+        5 => cpu.bp,
+        6 => cpu.si,
+        7 => cpu.di,
+        8 => cpu.ip,
+        9 => cpu.flags.get_flags(),
+        10 => cpu.cs,
+        11 => cpu.ss,
+        12 => cpu.ds,
+        13 => cpu.es,
+        // FS y GS no existen en el 8088
+        14 | 15 => 0,
+        _ => return None,
+    };
+    Some(val as u32)
+}
+
+fn set_register(cpu: &mut CPU, reg: usize, val: u32) -> Option<()> {
+    let val = val as u16;
+    match reg {
+        0 => cpu.ax.set_x(val),
+        1 => cpu.cx.set_x(val),
+        2 => cpu.dx.set_x(val),
+        3 => cpu.bx.set_x(val),
+        4 => cpu.sp = val,
+        5 => cpu.bp = val,
+        6 => cpu.si = val,
+        7 => cpu.di = val,
+        8 => {
+            // Si estaba a mitad de un REP la instruccion nueva hay que decodificarla
+            if cpu.ip != val {
+                cpu.to_decode = true;
+            }
+            cpu.ip = val;
+        },
+        9 => cpu.flags.set_flags(val),
+        10 => {
+            if cpu.cs != val {
+                cpu.to_decode = true;
+            }
+            cpu.cs = val;
+        },
+        11 => cpu.ss = val,
+        12 => cpu.ds = val,
+        13 => cpu.es = val,
+        14 | 15 => {},
+        _ => return None,
+    }
+    Some(())
+}
+
+impl GdbStub {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbStub {
+            listener,
+            client: None,
+            breakpoints: HashMap::new(),
+            running: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Llamar cada frame en lugar de System::update. Sin GDB la maquina corre normal,
+    // con GDB solo corre despues de un "c" y hasta que salte un breakpoint o llegue un Ctrl+C
+    pub fn update(&mut self, sys: &mut System) -> io::Result<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nodelay(true)?;
+                    // GDB espera encontrarse la maquina parada
+                    self.client = Some(Client { stream, buffer: Vec::new(), no_ack: false });
+                    self.running = false;
+                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {},
+                Err(err) => return Err(err),
+            }
+        }
+
+        if self.client.is_none() {
+            sys.update();
+            return Ok(());
+        }
+
+        if let Err(err) = self.poll(sys) {
+            self.detach(sys);
+            return Err(err);
+        }
+
+        if self.running {
+            let stop = sys.debug_run(FRAME_CYCLES as u64);
+            if stop != Stop::Timeout {
+                self.running = false;
+                let reply = self.stop_reply(stop);
+                self.send(sys, &reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Atiende los paquetes que hayan llegado
+    fn poll(&mut self, sys: &mut System) -> io::Result<()> {
+        if let Some(client) = &mut self.client {
+            if !client.receive()? {
+                self.detach(sys);
+                return Ok(());
+            }
+        }
+
+        loop {
+            let Some(client) = &mut self.client else {
+                return Ok(());
+            };
+            let Some(packet) = client.next_packet()? else {
+                return Ok(());
+            };
+
+            match self.packet(sys, &String::from_utf8_lossy(&packet)) {
+                Reply::Packet(reply) => self.send(sys, &reply)?,
+                Reply::Nothing => {},
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(sys, &reply)?;
+                    }
+                    self.detach(sys);
+                },
+            }
+        }
+    }
+
+    fn send(&mut self, sys: &mut System, data: &str) -> io::Result<()> {
+        let result = match &mut self.client {
+            Some(client) => client.send(data),
+            None => Ok(()),
+        };
+        if result.is_err() {
+            self.detach(sys);
+        }
+        result
+    }
+
+    // Al irse GDB se quitan sus breakpoints y la maquina sigue sola
+    fn detach(&mut self, sys: &mut System) {
+        for (_, id) in self.breakpoints.drain() {
+            let _ = sys.debugger.remove(id);
+        }
+        self.client = None;
+        self.running = false;
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        let watch = match stop {
+            Stop::Breakpoint(id) => self.breakpoints.iter().find(|(_, &other)| other == id).map(|(&key, _)| key),
+            _ => None,
+        };
+        match watch {
+            Some((2, address)) => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+            Some((3, address)) => format!("T{:02x}rwatch:{:x};", SIGTRAP, address),
+            Some((4, address)) => format!("T{:02x}awatch:{:x};", SIGTRAP, address),
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    fn packet(&mut self, sys: &mut System, packet: &str) -> Reply {
+        let Some(command) = packet.chars().next() else {
+            return Reply::Packet(String::new());
+        };
+        let args = &packet[command.len_utf8()..];
+
+        let reply = match command {
+            '\x03' => {
+                if !self.running {
+                    return Reply::Nothing;
+                }
+                self.running = false;
+                format!("S{:02x}", SIGINT)
+            },
+            '?' => format!("S{:02x}", SIGTRAP),
+            'g' => (0..REGISTERS).filter_map(|reg| register(&sys.cpu, reg)).map(hex_u32).collect(),
+            'G' => {
+                let ok = (0..REGISTERS).all(|reg| {
+                    let val = args.get(reg * 8..reg * 8 + 8).and_then(parse_le);
+                    val.and_then(|val| set_register(&mut sys.cpu, reg, val)).is_some()
+                });
+                String::from(if ok {"OK"} else {"E01"})
+            },
+            'p' => match parse_hex(args).and_then(|reg| register(&sys.cpu, reg as usize)) {
+                Some(val) => hex_u32(val),
+                None => String::from("E01"),
+            },
+            'P' => {
+                let set = args.split_once('=').and_then(|(reg, val)| {
+                    set_register(&mut sys.cpu, parse_hex(reg)? as usize, parse_le(val)?)
+                });
+                String::from(if set.is_some() {"OK"} else {"E01"})
+            },
+            'm' => match args.split_once(',').and_then(|(address, len)| Some((parse_hex(address)?, parse_hex(len)?))) {
+                Some((address, len)) => (0..len.min(0x1000))
+                    .map(|i| {
+                        let address = address.wrapping_add(i) & 0xFFFFF;
+                        let val = sys.bus.untimed(|bus| bus.read_8((address >> 4) as u16, (address & 0x0F) as u16));
+                        format!("{:02x}", val)
+                    })
+                    .collect(),
+                None => String::from("E01"),
+            },
+            'M' => {
+                let write = args.split_once(':').and_then(|(range, data)| {
+                    let address = parse_hex(range.split_once(',')?.0)?;
+                    let bytes: Option<Vec<u32>> = (0..data.len() / 2).map(|i| parse_hex(data.get(i * 2..i * 2 + 2)?)).collect();
+                    Some((address, bytes?))
+                });
+                match write {
+                    Some((address, bytes)) => {
+                        // La ROM no se puede escribir, igual que desde la CPU
+                        for (i, byte) in bytes.iter().enumerate() {
+                            let address = address.wrapping_add(i as u32) & 0xFFFFF;
+                            sys.bus.untimed(|bus| bus.write_8((address >> 4) as u16, (address & 0x0F) as u16, *byte as u8));
+                        }
+                        String::from("OK")
+                    },
+                    None => String::from("E01"),
+                }
+            },
+            'c' => {
+                self.running = true;
+                return Reply::Nothing;
+            },
+            's' => {
+                let stop = sys.debug_step();
+                self.stop_reply(stop)
+            },
+            'Z' | 'z' => self.breakpoint(sys, command == 'Z', args),
+            'D' => return Reply::Close(Some(String::from("OK"))),
+            'k' => return Reply::Close(None),
+            'H' | 'T' => String::from("OK"),
+            'q' => match args.split(':').next().unwrap_or("") {
+                "Supported" => String::from("PacketSize=1000"),
+                "Attached" => String::from("1"),
+                "C" => String::from("QC1"),
+                "fThreadInfo" => String::from("m1"),
+                "sThreadInfo" => String::from("l"),
+                _ => String::new(),
+            },
+            'Q' if args == "StartNoAckMode" => {
+                if let Some(client) = &mut self.client {
+                    client.no_ack = true;
+                }
+                String::from("OK")
+            },
+            // Lo que no se soporta se contesta vacio
+            _ => String::new(),
+        };
+        Reply::Packet(reply)
+    }
+
+    // Z0 y Z1 son el mismo breakpoint de ejecucion, Z2-Z4 watchpoints de escritura, lectura y acceso
+    fn breakpoint(&mut self, sys: &mut System, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(address), Some(len)) = (
+            fields.next().and_then(|kind| kind.parse::<u8>().ok()),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return String::from("E01");
+        };
+        let address = address & 0xFFFFF;
+        let end = address.wrapping_add(len.clamp(1, 0x100000) - 1) & 0xFFFFF;
+
+        let breakpoint = match kind {
+            0 | 1 => Breakpoint::Linear(address),
+            2 => Breakpoint::Memory(address, end, Access::Write),
+            3 => Breakpoint::Memory(address, end, Access::Read),
+            4 => Breakpoint::Memory(address, end, Access::ReadWrite),
+            _ => return String::new(),
+        };
+
+        if insert {
+            self.breakpoints.entry((kind, address)).or_insert_with(|| sys.debugger.add(breakpoint, None));
+        } else if let Some(id) = self.breakpoints.remove(&(kind, address)) {
+            let _ = sys.debugger.remove(id);
+        }
+        String::from("OK")
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::hardware::config::{MachineConfig, RomImage, DisplayType, RomError, MAX_FLOPPY_DRIVES};
+use crate::hardware::state::{StateWriter, StateReader, StateError};
+use crate::hardware::sys::System;
+
+// Grabacion de la entrada para repetir una sesion igual: la configuracion de la maquina, el estado
+// de partida si no se graba desde el arranque, y cada scancode con el ciclo en el que entra.
+// Las teclas entran justo antes de la instruccion que empieza en ese ciclo, asi que al repetir con
+// la misma configuracion y los mismos disquetes todo sale igual, byte a byte.
+pub const INPUT_MAGIC: &[u8; 8] = b"IBM5150I";
+pub const INPUT_VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum InputError {
+    Io(io::Error),
+    BadMagic,
+    Version(u16),
+    Truncated,
+    Invalid(&'static str),
+    Rom(RomError),
+    // El snapshot de partida no vale
+    State(StateError),
+}
+
+impl Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputError::Io(err) => write!(f, "{}", err),
+            InputError::BadMagic => write!(f, "not an input recording"),
+            InputError::Version(v) => write!(f, "unsupported input recording version {} (expected {})", v, INPUT_VERSION),
+            InputError::Truncated => write!(f, "input recording is truncated"),
+            InputError::Invalid(what) => write!(f, "invalid value for {} in input recording", what),
+            InputError::Rom(err) => write!(f, "{}", err),
+            InputError::State(err) => write!(f, "input recording state: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+impl From<io::Error> for InputError {
+    fn from(err: io::Error) -> Self {
+        InputError::Io(err)
+    }
+}
+
+impl From<RomError> for InputError {
+    fn from(err: RomError) -> Self {
+        InputError::Rom(err)
+    }
+}
+
+impl From<StateError> for InputError {
+    fn from(err: StateError) -> Self {
+        match err {
+            StateError::Io(err) => InputError::Io(err),
+            StateError::Truncated => InputError::Truncated,
+            StateError::Invalid(what) => InputError::Invalid(what),
+            err => InputError::State(err),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub key_code: u8,
+}
+
+#[derive(Clone)]
+pub struct InputLog {
+    pub config: MachineConfig,
+    // Snapshot del System al empezar. Sin el, la grabacion empieza en el arranque
+    pub state: Option<Vec<u8>>,
+    pub events: Vec<InputEvent>,
+}
+
+fn write_path(w: &mut StateWriter, path: &Path) {
+    w.write_bytes(path.to_string_lossy().as_bytes());
+}
+
+fn read_path(r: &mut StateReader) -> Result<PathBuf, InputError> {
+    let text = std::str::from_utf8(r.read_bytes()?).map_err(|_| InputError::Invalid("path"))?;
+    Ok(PathBuf::from(text))
+}
+
+fn write_roms(w: &mut StateWriter, roms: &[RomImage]) {
+    w.write_u8(roms.len() as u8);
+    for rom in roms {
+        write_path(w, &rom.path);
+        w.write_u32(rom.address as u32);
+    }
+}
+
+fn read_roms(r: &mut StateReader) -> Result<Vec<RomImage>, InputError> {
+    (0..r.read_u8()?).map(|_| Ok(RomImage::new(read_path(r)?, r.read_u32()? as usize))).collect()
+}
+
+impl InputLog {
+    // Empieza a grabar desde como esta ahora la maquina
+    pub fn new(sys: &System) -> Self {
+        InputLog {
+            config: sys.config.clone(),
+            state: (sys.total_cycles > 0).then(|| sys.snapshot()),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn save(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        for b in INPUT_MAGIC {
+            w.write_u8(*b);
+        }
+        w.write_u16(INPUT_VERSION);
+
+        let config = &self.config;
+        write_roms(&mut w, std::slice::from_ref(&config.bios));
+        write_roms(&mut w, &config.basic);
+        write_roms(&mut w, &config.option_roms);
+        write_path(&mut w, &config.font);
+        w.write_u8(config.floppy_drives);
+        w.write_u8(match config.display {
+            DisplayType::Mda => 0,
+            DisplayType::Cga40 => 1,
+            DisplayType::Cga80 => 2,
+        });
+        w.write_u32(config.typematic_delay);
+        w.write_u32(config.typematic_rate);
+        w.write_bool(config.fpu);
+        w.write_bool(config.accurate_timing);
+
+        w.write_bool(self.state.is_some());
+        if let Some(state) = &self.state {
+            w.write_bytes(state);
+        }
+
+        w.write_u32(self.events.len() as u32);
+        for event in &self.events {
+            w.write_u64(event.cycle);
+            w.write_u8(event.key_code);
+        }
+        w.into_inner()
+    }
+
+    pub fn load(data: &[u8]) -> Result<Self, InputError> {
+        if data.len() < INPUT_MAGIC.len() || &data[..INPUT_MAGIC.len()] != INPUT_MAGIC {
+            return Err(InputError::BadMagic);
+        }
+        let mut r = StateReader::new(&data[INPUT_MAGIC.len()..]);
+        let version = r.read_u16()?;
+        if version != INPUT_VERSION {
+            return Err(InputError::Version(version));
+        }
+
+        let bios = read_roms(&mut r)?.pop().ok_or(InputError::Invalid("bios"))?;
+        let config = MachineConfig {
+            bios,
+            basic: read_roms(&mut r)?,
+            option_roms: read_roms(&mut r)?,
+            font: read_path(&mut r)?,
+            floppy_drives: match r.read_u8()? {
+                drives if drives <= MAX_FLOPPY_DRIVES => drives,
+                _ => return Err(InputError::Invalid("floppy drives")),
+            },
+            display: match r.read_u8()? {
+                0 => DisplayType::Mda,
+                1 => DisplayType::Cga40,
+                2 => DisplayType::Cga80,
+                _ => return Err(InputError::Invalid("display")),
+            },
+            typematic_delay: r.read_u32()?,
+            typematic_rate: r.read_u32()?,
+            fpu: r.read_bool()?,
+            accurate_timing: r.read_bool()?,
+        };
+
+        let state = if r.read_bool()? {Some(r.read_bytes()?.to_vec())} else {None};
+
+        let mut events = Vec::new();
+        for _ in 0..r.read_u32()? {
+            events.push(InputEvent { cycle: r.read_u64()?, key_code: r.read_u8()? });
+        }
+        if events.windows(2).any(|w| w[0].cycle > w[1].cycle) {
+            return Err(InputError::Invalid("event order"));
+        }
+        if !r.is_empty() {
+            return Err(InputError::Invalid("trailing data"));
+        }
+
+        Ok(InputLog { config, state, events })
+    }
+
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<(), InputError> {
+        std::fs::write(path, self.save())?;
+        Ok(())
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, InputError> {
+        InputLog::load(&std::fs::read(path)?)
+    }
+
+    // Ciclo de la ultima tecla
+    pub fn end(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.cycle)
+    }
+}
+
+// Repite una grabacion metiendo cada tecla en su ciclo
+pub struct InputReplay {
+    pub log: InputLog,
+    next: usize,
+}
+
+impl InputReplay {
+    pub fn new(log: InputLog) -> Self {
+        InputReplay { log, next: 0 }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.log.events.len()
+    }
+
+    // Las teclas que ya tienen que haber entrado en cycle
+    fn due(&mut self, cycle: u64) -> Vec<u8> {
+        let start = self.next;
+        while self.log.events.get(self.next).is_some_and(|event| event.cycle <= cycle) {
+            self.next += 1;
+        }
+        self.log.events[start..self.next].iter().map(|event| event.key_code).collect()
+    }
+
+    // Despues de rebobinar, las teclas de despues de cycle tienen que volver a entrar
+    pub fn seek(&mut self, cycle: u64) {
+        self.next = self.log.events.partition_point(|event| event.cycle <= cycle);
+    }
+}
+
+impl System {
+    pub fn start_recording(&mut self) {
+        self.recording = Some(InputLog::new(self));
+    }
+
+    // Deja la maquina como al empezar la grabacion. Tiene que estar creada con su configuracion
+    // y con los mismos disquetes
+    pub fn start_replay(&mut self, log: InputLog) -> Result<(), InputError> {
+        match &log.state {
+            Some(state) => self.restore(state)?,
+            None => {
+                self.rst();
+                self.load_roms()?;
+            },
+        }
+        self.replay = Some(InputReplay::new(log));
+        Ok(())
+    }
+
+    // Mete las teclas que tocan antes de la siguiente instruccion
+    pub fn replay_input(&mut self) {
+        let keys = match &mut self.replay {
+            Some(replay) if !replay.finished() => replay.due(self.total_cycles),
+            _ => return,
+        };
+        for key_code in keys {
+            self.key_input(key_code);
+        }
+    }
+}
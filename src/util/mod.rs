@@ -1,2 +1,8 @@
-// pub mod debug;
 pub mod debug_bios;
+pub mod debugger;
+pub mod monitor;
+pub mod gdb;
+pub mod trace;
+pub mod trace_diff;
+pub mod rewind;
+pub mod input;
@@ -0,0 +1,302 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::FRAME_CYCLES;
+use crate::hardware::cpu_8088::dissasemble::{Dissasembly, dissasemble_bus};
+use crate::hardware::sys::System;
+use crate::util::debugger::{Breakpoint, Stop, HELP, dump, linear, registers};
+
+const CODE_LINES: usize = 16;
+const STACK_LINES: usize = 8;
+const MEMORY_LINES: u32 = 8;
+const OUTPUT_LINES: usize = 6;
+// Lo que corre "g" entre redibujados, un frame
+const GO_CYCLES: u64 = FRAME_CYCLES as u64;
+
+const MONITOR_HELP: &str = "\
+g                go until a breakpoint (Esc stops)
+m ADDR           memory view address (PgUp/PgDn scroll)
+u [ADDR]         code view address, without ADDR follow CS:IP (Ctrl+Up/Down scroll)
+reset            reset the machine and reload the ROMs
+q                quit
+Up/Down          command history";
+
+// Monitor de registros, memoria y codigo en el terminal
+pub struct Monitor {
+    pub sys: System,
+    pub running: bool,
+    // Corriendo con "g" hasta que salte algo
+    going: bool,
+
+    memory: u32,
+    // None sigue a CS:IP
+    code: Option<u32>,
+    code_back: Vec<u32>,
+
+    input: String,
+    history: Vec<String>,
+    history_pos: usize,
+    output: Vec<String>,
+}
+
+impl Monitor {
+    pub fn new(sys: System) -> Self {
+        Monitor {
+            sys,
+            running: true,
+            going: false,
+
+            memory: 0,
+            code: None,
+            code_back: Vec::new(),
+
+            input: String::new(),
+            history: Vec::new(),
+            history_pos: 0,
+            output: Vec::new(),
+        }
+    }
+
+    fn print(&mut self, text: &str) {
+        self.output.extend(text.lines().map(String::from));
+        let extra = self.output.len().saturating_sub(OUTPUT_LINES);
+        self.output.drain(..extra);
+    }
+
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    pub fn command(&mut self, line: &str) {
+        let line = line.trim();
+        if !line.is_empty() && self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+        self.history_pos = self.history.len();
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let address = |arg: Option<&&str>| arg.and_then(|arg| {
+            match arg.split_once(':') {
+                Some((seg, off)) => Some(linear(u16::from_str_radix(seg, 16).ok()?, u16::from_str_radix(off, 16).ok()?)),
+                None => u32::from_str_radix(arg, 16).ok().map(|address| address & 0xFFFFF),
+            }
+        });
+
+        match args.first().copied() {
+            Some("q") | Some("quit") => self.running = false,
+            Some("g") => self.going = true,
+            Some("m") => match address(args.get(1)) {
+                Some(memory) => self.memory = memory,
+                None => self.print(&format!("could not parse '{}'", line)),
+            },
+            Some("u") => {
+                self.code = address(args.get(1));
+                self.code_back.clear();
+            },
+            Some("reset") => {
+                self.sys.rst();
+                match self.sys.load_roms() {
+                    Ok(()) => self.print("reset"),
+                    Err(err) => self.print(&err.to_string()),
+                }
+            },
+            Some("help") | Some("?") => {
+                self.print(MONITOR_HELP);
+                self.print(HELP);
+            },
+            _ => {
+                let result = self.sys.debug_command(line);
+                match result {
+                    Ok(text) => self.print(&text),
+                    Err(err) => self.print(&err.to_string()),
+                }
+            },
+        }
+    }
+
+    // Corre un frame de "g". Devuelve false cuando se para
+    pub fn go(&mut self) -> bool {
+        let stop = self.sys.debug_run(GO_CYCLES);
+        if stop != Stop::Timeout {
+            self.going = false;
+            let text = self.sys.describe_stop(stop);
+            self.print(&text);
+        }
+        self.going
+    }
+
+    pub fn key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc if self.going => {
+                self.going = false;
+                let text = self.sys.describe_stop(Stop::Step);
+                self.print(&text);
+            },
+            KeyCode::Char('c') if ctrl => self.running = false,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut self.input);
+                self.command(&line);
+            },
+            KeyCode::Up if ctrl => {
+                let code = self.code_start();
+                self.code = Some(self.code_back.pop().unwrap_or(code.wrapping_sub(1) & 0xFFFFF));
+            },
+            KeyCode::Down if ctrl => {
+                let code = self.code_start();
+                let len = self.dissasemble(code).bytes.len();
+                self.code_back.push(code);
+                self.code = Some((code + len as u32) & 0xFFFFF);
+            },
+            KeyCode::Up => {
+                self.history_pos = self.history_pos.saturating_sub(1);
+                self.input = self.history.get(self.history_pos).cloned().unwrap_or_default();
+            },
+            KeyCode::Down => {
+                self.history_pos = (self.history_pos + 1).min(self.history.len());
+                self.input = self.history.get(self.history_pos).cloned().unwrap_or_default();
+            },
+            KeyCode::PageUp => self.memory = self.memory.wrapping_sub(MEMORY_LINES * 16) & 0xFFFFF,
+            KeyCode::PageDown => self.memory = (self.memory + MEMORY_LINES * 16) & 0xFFFFF,
+            _ => {},
+        }
+    }
+
+    fn code_start(&self) -> u32 {
+        self.code.unwrap_or(linear(self.sys.cpu.cs, self.sys.cpu.ip))
+    }
+
+    // Si la direccion cae dentro de CS se usa CS, asi los destinos de los saltos salen con su offset.
+    // Si no, el segmento que la contenga con IP mas bajo
+    fn dissasemble(&self, address: u32) -> Dissasembly {
+        let cs = self.sys.cpu.cs;
+        let offset = address.wrapping_sub(linear(cs, 0)) & 0xFFFFF;
+        if offset < 0x10000 {
+            dissasemble_bus(&self.sys.bus, cs, offset as u16)
+        } else {
+            dissasemble_bus(&self.sys.bus, (address >> 4) as u16, (address & 0x0F) as u16)
+        }
+    }
+
+    fn code_lines(&self) -> Vec<String> {
+        let ip = linear(self.sys.cpu.cs, self.sys.cpu.ip);
+        let mut address = self.code_start();
+        let mut lines = Vec::new();
+
+        for _ in 0..CODE_LINES {
+            let instr = self.dissasemble(address);
+            let bytes: Vec<String> = instr.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let breakpoint = self.sys.debugger.breakpoints().iter().any(|entry| entry.enabled && match entry.breakpoint {
+                Breakpoint::Exec(cs, ip) => linear(cs, ip) == address,
+                Breakpoint::Linear(linear) => linear == address,
+                _ => false,
+            });
+
+            lines.push(format!(
+                "{}{} {:05X}  {:<12} {}",
+                if address == ip {'>'} else {' '}, if breakpoint {'*'} else {' '},
+                address, bytes.join(""), instr.text
+            ));
+            address = (address + instr.bytes.len() as u32) & 0xFFFFF;
+        }
+        lines
+    }
+
+    fn side_lines(&self) -> Vec<String> {
+        let cpu = &self.sys.cpu;
+        let bus = &self.sys.bus;
+
+        let mut lines = vec![String::from("Stack")];
+        for i in 0..STACK_LINES as u16 {
+            let offset = cpu.sp.wrapping_add(i * 2);
+            let low = bus.read_dir(linear(cpu.ss, offset) as usize);
+            let high = bus.read_dir(linear(cpu.ss, offset.wrapping_add(1)) as usize);
+            lines.push(format!("{:04X}:{:04X}  {:02X}{:02X}", cpu.ss, offset, high, low));
+        }
+
+        lines.push(String::new());
+        lines.push(format!("PIC  IRR={:02X} ISR={:02X} IMR={:02X}", bus.pic.irr, bus.pic.isr(), bus.pic.imr()));
+        for channel in 0..3 {
+            let (count, reload, mode) = bus.pit.channel(channel);
+            lines.push(format!(
+                "PIT{} mode {} count {:04X} reload {:04X} out {}",
+                channel, mode, count, reload, bus.pit.out(channel) as u8
+            ));
+        }
+        lines
+    }
+
+    // Todas las lineas de la pantalla
+    pub fn screen(&self) -> Vec<String> {
+        let mut lines: Vec<String> = registers(&self.sys.cpu).lines().map(String::from).collect();
+        lines.push(String::new());
+
+        let side = self.side_lines();
+        for (i, code) in self.code_lines().into_iter().enumerate() {
+            lines.push(format!("{:<64}{}", code, side.get(i).map(String::as_str).unwrap_or("")));
+        }
+
+        lines.push(String::new());
+        lines.extend(dump(&self.sys.bus, self.memory, MEMORY_LINES * 16).lines().map(String::from));
+        lines.push(String::new());
+
+        for i in 0..OUTPUT_LINES {
+            lines.push(self.output.get(i).cloned().unwrap_or_default());
+        }
+        lines.push(format!("{} {}", if self.going {"running, Esc stops"} else {">"}, self.input));
+        lines
+    }
+
+    pub fn render(&self, out: &mut impl Write) -> io::Result<()> {
+        let lines = self.screen();
+        for (y, line) in lines.iter().enumerate() {
+            queue!(out, MoveTo(0, y as u16), Print(line), Clear(ClearType::UntilNewLine))?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+
+        let result = self.event_loop();
+
+        execute!(io::stdout(), Show, LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn event_loop(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        while self.running {
+            self.render(&mut stdout)?;
+
+            if self.going {
+                self.go();
+                if !event::poll(Duration::ZERO)? {
+                    continue;
+                }
+            }
+
+            if let Event::Key(key) = event::read()? {
+                self.key(key);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use crate::CPU_CLOCK;
+use crate::hardware::state::StateError;
+use crate::hardware::sys::System;
+
+// Rebobinado: cada interval ciclos se guarda un punto de control con la CPU y los perifericos (sin la RAM
+// ni el contenido de los disquetes).
+// Entre dos puntos el Bus apunta lo que habia en cada byte de RAM antes de escribirlo por primera vez y
+// lo que devuelven los puertos, y el System las teclas que entran. Para volver a una instruccion se
+// deshacen las escrituras hasta el punto anterior, se carga ese punto y se ejecuta de nuevo hasta ella
+// con las mismas lecturas y las mismas teclas. Lo que va despues se pierde.
+// Los disquetes no vuelven atras: al rebobinar el FDC vuelve a como estaba, pero lo escrito en ellos se queda.
+
+#[derive(Clone, Default)]
+pub struct Journal {
+    pub enabled: bool,
+    // Direccion y valor anterior. Solo la primera escritura de cada byte desde el ultimo punto
+    pub writes: Vec<(u32, u8)>,
+    // Bytes ya apuntados desde el ultimo punto, un bit por direccion
+    dirty: Vec<u64>,
+    // Puerto y valor de cada lectura
+    pub reads: Vec<(u16, u16)>,
+    // Al ejecutar de nuevo, la siguiente lectura que se repite
+    replay: Option<usize>,
+}
+
+impl Journal {
+    #[inline]
+    pub fn write(&mut self, address: u32, old: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.dirty.is_empty() {
+            self.dirty = vec![0; 0x100000 / 64];
+        }
+        let (word, bit) = (address as usize / 64, address % 64);
+        if self.dirty[word] & 1 << bit == 0 {
+            self.dirty[word] |= 1 << bit;
+            self.writes.push((address, old));
+        }
+    }
+
+    #[inline]
+    pub fn port_in(&mut self, port: u16, val: u16) -> u16 {
+        if !self.enabled {
+            return val;
+        }
+        if let Some(next) = self.replay {
+            match self.reads.get(next) {
+                Some(&(read_port, read_val)) if read_port == port => {
+                    self.replay = Some(next + 1);
+                    return read_val;
+                },
+                // Si la ejecucion se ha separado de la original, el resto ya no vale
+                _ => {
+                    self.reads.truncate(next);
+                    self.replay = None;
+                },
+            }
+        }
+        self.reads.push((port, val));
+        val
+    }
+
+    // Empieza otro tramo entre puntos de control
+    fn mark(&mut self) {
+        self.dirty.fill(0);
+    }
+}
+
+#[derive(Debug)]
+pub enum RewindError {
+    Disabled,
+    // La instruccion pedida, y la primera y la ultima que se pueden alcanzar
+    OutOfRange(u64, u64, u64),
+    State(StateError),
+}
+
+impl Display for RewindError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewindError::Disabled => write!(f, "rewind is not enabled"),
+            RewindError::OutOfRange(instruction, first, last) => {
+                write!(f, "instruction {} is not in the history ({}-{})", instruction, first, last)
+            },
+            RewindError::State(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RewindError {}
+
+impl From<StateError> for RewindError {
+    fn from(err: StateError) -> Self {
+        RewindError::State(err)
+    }
+}
+
+struct Checkpoint {
+    instruction: u64,
+    cycles: u64,
+    state: Vec<u8>,
+    // Donde empieza su tramo en el diario del Bus
+    writes: usize,
+    reads: usize,
+}
+
+pub struct Rewind {
+    // Ciclos entre puntos de control
+    pub interval: u64,
+    // Ciclos hacia atras que se guardan
+    pub history: u64,
+    // Instrucciones ejecutadas desde que empieza la historia
+    pub instructions: u64,
+
+    checkpoints: VecDeque<Checkpoint>,
+    // Teclas con el numero de instruccion antes de la que han entrado
+    keys: VecDeque<(u64, u8)>,
+}
+
+impl Rewind {
+    pub fn new(interval: u64, history: u64) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            history,
+            instructions: 0,
+
+            checkpoints: VecDeque::new(),
+            keys: VecDeque::new(),
+        }
+    }
+
+    // Un punto cada decima de segundo
+    pub fn seconds(seconds: u64) -> Self {
+        Rewind::new(CPU_CLOCK / 10, seconds * CPU_CLOCK)
+    }
+
+    pub fn due(&self, cycles: u64) -> bool {
+        self.checkpoints.back().is_none_or(|last| cycles.saturating_sub(last.cycles) >= self.interval)
+    }
+
+    pub fn key_input(&mut self, key_code: u8) {
+        self.keys.push_back((self.instructions, key_code));
+    }
+
+    // La primera instruccion a la que se puede volver
+    pub fn oldest(&self) -> u64 {
+        self.checkpoints.front().map_or(self.instructions, |first| first.instruction)
+    }
+
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    fn clear(&mut self) {
+        self.instructions = 0;
+        self.checkpoints.clear();
+        self.keys.clear();
+    }
+}
+
+impl System {
+    // Guarda un punto de control y se olvida de lo que ya no cabe en la historia
+    pub fn checkpoint(&mut self) {
+        let state = self.save_devices();
+        let journal = &mut self.bus.journal;
+        journal.enabled = true;
+        journal.mark();
+
+        let Some(rewind) = &mut self.rewind else {
+            return;
+        };
+        rewind.checkpoints.push_back(Checkpoint {
+            instruction: rewind.instructions,
+            cycles: self.total_cycles,
+            state,
+            writes: journal.writes.len(),
+            // Al repetir, las lecturas que siguen ya estan en el diario
+            reads: journal.replay.unwrap_or(journal.reads.len()),
+        });
+
+        // Siempre queda al menos un punto mas viejo que la historia pedida
+        let limit = self.total_cycles.saturating_sub(rewind.history);
+        let mut dropped = 0;
+        while rewind.checkpoints.len() > 1 && rewind.checkpoints[1].cycles <= limit {
+            rewind.checkpoints.pop_front();
+            dropped += 1;
+        }
+        if dropped > 0 {
+            let first = &rewind.checkpoints[0];
+            let (writes, reads, instruction) = (first.writes, first.reads, first.instruction);
+            journal.writes.drain(..writes);
+            journal.reads.drain(..reads);
+            if let Some(next) = &mut journal.replay {
+                *next -= reads;
+            }
+            for checkpoint in rewind.checkpoints.iter_mut() {
+                checkpoint.writes -= writes;
+                checkpoint.reads -= reads;
+            }
+            while rewind.keys.front().is_some_and(|(at, _)| *at < instruction) {
+                rewind.keys.pop_front();
+            }
+        }
+    }
+
+    // Empieza la historia de nuevo, por ejemplo despues de cargar un estado
+    pub fn reset_rewind(&mut self) {
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+            self.bus.journal = Default::default();
+        }
+    }
+
+    // Deja la maquina como estaba antes de ejecutar la instruccion numero instruction
+    pub fn rewind_to(&mut self, instruction: u64) -> Result<(), RewindError> {
+        let rewind = self.rewind.as_mut().ok_or(RewindError::Disabled)?;
+        let (first, last) = (rewind.oldest(), rewind.instructions);
+        if !(first..=last).contains(&instruction) || rewind.checkpoints.is_empty() {
+            return Err(RewindError::OutOfRange(instruction, first, last));
+        }
+
+        let index = rewind.checkpoints.iter().rposition(|checkpoint| checkpoint.instruction <= instruction).unwrap();
+        rewind.checkpoints.truncate(index + 1);
+        let checkpoint = &rewind.checkpoints[index];
+        rewind.instructions = checkpoint.instruction;
+        let (state, writes, reads) = (checkpoint.state.clone(), checkpoint.writes, checkpoint.reads);
+
+        // La RAM vuelve hacia atras deshaciendo las escrituras
+        let journal = &mut self.bus.journal;
+        for &(address, old) in journal.writes[writes..].iter().rev() {
+            self.bus.memory[address as usize] = old;
+        }
+        journal.writes.truncate(writes);
+        journal.mark();
+        journal.replay = Some(reads);
+        self.restore_devices(&state)?;
+
+        // Y se repite hasta la instruccion pedida, sin volver a grabar la traza. Las teclas de cada
+        // instruccion entran antes que ella, y las del punto de control ya estan en su estado
+        let start = self.rewind.as_ref().map_or(instruction, |rewind| rewind.instructions);
+        let tracer = self.tracer.take();
+        while let Some(rewind) = &self.rewind {
+            let at = rewind.instructions;
+            if at > start {
+                let keys: Vec<u8> = rewind.keys.iter().filter(|(key_at, _)| *key_at == at).map(|(_, key_code)| *key_code).collect();
+                for key_code in keys {
+                    self.bus.ppi.key_input(key_code);
+                }
+            }
+            if at >= instruction || self.cpu.halted {
+                break;
+            }
+
+            let mut cycles = 0;
+            self.step(&mut cycles);
+        }
+        self.tracer = tracer;
+
+        // Lo que venia despues ya no ha pasado
+        let journal = &mut self.bus.journal;
+        if let Some(next) = journal.replay.take() {
+            journal.reads.truncate(next);
+        }
+        if let Some(rewind) = &mut self.rewind {
+            let at = rewind.instructions;
+            rewind.keys.retain(|(key_at, _)| *key_at <= at);
+        }
+        let cycles = self.total_cycles;
+        if let Some(recording) = &mut self.recording {
+            recording.events.retain(|event| event.cycle <= cycles);
+        }
+        if let Some(replay) = &mut self.replay {
+            replay.seek(cycles);
+        }
+        Ok(())
+    }
+
+    pub fn step_back(&mut self) -> Result<(), RewindError> {
+        let instructions = self.rewind.as_ref().ok_or(RewindError::Disabled)?.instructions;
+        self.rewind_to(instructions.saturating_sub(1))
+    }
+
+    // Vuelve al punto de control de hace al menos cycles ciclos, o al mas viejo.
+    // Devuelve cuantos ciclos ha vuelto atras
+    pub fn rewind_cycles(&mut self, cycles: u64) -> Result<u64, RewindError> {
+        let rewind = self.rewind.as_ref().ok_or(RewindError::Disabled)?;
+        let target = self.total_cycles.saturating_sub(cycles);
+        let checkpoint = rewind.checkpoints.iter().rev().find(|checkpoint| checkpoint.cycles <= target)
+            .or(rewind.checkpoints.front())
+            .ok_or(RewindError::OutOfRange(rewind.instructions, rewind.instructions, rewind.instructions))?;
+
+        let (instruction, now) = (checkpoint.instruction, self.total_cycles);
+        self.rewind_to(instruction)?;
+        Ok(now - self.total_cycles)
+    }
+}
@@ -0,0 +1,349 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::hardware::bus::Bus;
+use crate::hardware::cpu_8088::CPU;
+use crate::hardware::cpu_8088::dissasemble::dissasemble;
+use crate::hardware::cpu_8088::dissasemble::dissasemble_bus;
+use crate::hardware::state::{StateWriter, StateReader, StateError};
+
+pub const TRACE_MAGIC: &[u8; 8] = b"IBM5150T";
+pub const TRACE_VERSION: u16 = 1;
+
+// Orden de los registros en cada registro de la traza
+pub const REGISTERS: [&str; 14] = ["AX", "BX", "CX", "DX", "SP", "BP", "SI", "DI", "CS", "DS", "ES", "SS", "IP", "FLAGS"];
+const CS: usize = 8;
+const IP: usize = 12;
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    BadMagic,
+    Version(u16),
+    Truncated,
+    Invalid(&'static str),
+    // Linea de una referencia de texto que no se entiende
+    Parse(usize, String),
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceError::Io(err) => write!(f, "{}", err),
+            TraceError::BadMagic => write!(f, "not a trace file"),
+            TraceError::Version(v) => write!(f, "unsupported trace version {} (expected {})", v, TRACE_VERSION),
+            TraceError::Truncated => write!(f, "trace is truncated"),
+            TraceError::Invalid(what) => write!(f, "invalid value for {} in trace", what),
+            TraceError::Parse(line, text) => write!(f, "line {}: could not parse '{}'", line, text),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(err: io::Error) -> Self {
+        TraceError::Io(err)
+    }
+}
+
+impl From<StateError> for TraceError {
+    fn from(err: StateError) -> Self {
+        match err {
+            StateError::Io(err) => TraceError::Io(err),
+            StateError::Invalid(what) => TraceError::Invalid(what),
+            _ => TraceError::Truncated,
+        }
+    }
+}
+
+// Un acceso de la CPU al bus: memoria con la direccion lineal, E/S con el puerto
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BusAccess {
+    MemRead(u32, u8),
+    MemWrite(u32, u8),
+    IoRead(u16, u16),
+    IoWrite(u16, u16),
+}
+
+impl Display for BusAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BusAccess::MemRead(address, val) => write!(f, "R {:05X}={:02X}", address, val),
+            BusAccess::MemWrite(address, val) => write!(f, "W {:05X}={:02X}", address, val),
+            BusAccess::IoRead(port, val) => write!(f, "I {:04X}={:02X}", port, val),
+            BusAccess::IoWrite(port, val) => write!(f, "O {:04X}={:02X}", port, val),
+        }
+    }
+}
+
+// Los accesos que apunta el Bus. Solo mientras se graba una instruccion
+#[derive(Clone, Default)]
+pub struct AccessLog {
+    pub enabled: bool,
+    accesses: RefCell<Vec<BusAccess>>,
+}
+
+impl AccessLog {
+    #[inline]
+    pub fn push(&self, access: BusAccess) {
+        if self.enabled {
+            self.accesses.borrow_mut().push(access);
+        }
+    }
+
+    pub fn take(&self) -> Vec<BusAccess> {
+        std::mem::take(&mut self.accesses.borrow_mut())
+    }
+}
+
+// Una instruccion ejecutada: los registros son los de antes de ejecutarla
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceRecord {
+    pub cycle: u64,
+    pub regs: [u16; 14],
+    // Vacio en las repeticiones de un REP, que no vuelven a leer la instruccion
+    pub bytes: Vec<u8>,
+    pub accesses: Vec<BusAccess>,
+}
+
+impl TraceRecord {
+    pub fn capture(cpu: &CPU, bus: &Bus, cycle: u64) -> Self {
+        TraceRecord {
+            cycle,
+            regs: [
+                cpu.ax.get_x(), cpu.bx.get_x(), cpu.cx.get_x(), cpu.dx.get_x(),
+                cpu.sp, cpu.bp, cpu.si, cpu.di,
+                cpu.cs, cpu.ds, cpu.es, cpu.ss, cpu.ip, cpu.flags.get_flags(),
+            ],
+            bytes: if cpu.to_decode {dissasemble_bus(bus, cpu.cs, cpu.ip).bytes} else {Vec::new()},
+            accesses: Vec::new(),
+        }
+    }
+
+    pub fn cs(&self) -> u16 {
+        self.regs[CS]
+    }
+
+    pub fn ip(&self) -> u16 {
+        self.regs[IP]
+    }
+}
+
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text = if self.bytes.is_empty() {String::from("(rep)")} else {dissasemble(&self.bytes, self.ip()).text};
+
+        write!(f, "{:>10} {:04X}:{:04X}  {:<12} {:<28}", self.cycle, self.cs(), self.ip(), bytes.join(""), text)?;
+        for (name, val) in REGISTERS.iter().zip(self.regs) {
+            if !matches!(*name, "CS" | "IP") {
+                write!(f, " {}={:04X}", name, val)?;
+            }
+        }
+        for access in &self.accesses {
+            write!(f, " [{}]", access)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_varint(w: &mut StateWriter, mut val: u64) {
+    while val >= 0x80 {
+        w.write_u8(val as u8 | 0x80);
+        val >>= 7;
+    }
+    w.write_u8(val as u8);
+}
+
+fn read_varint(r: &mut StateReader) -> Result<u64, TraceError> {
+    let mut val = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = r.read_u8()?;
+        val |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err(TraceError::Invalid("cycle"))
+}
+
+// Graba la traza en binario. Cada registro lleva los ciclos desde el anterior, una mascara con
+// los registros que han cambiado y solo esos, los bytes de la instruccion y los accesos al bus
+pub struct Tracer {
+    // Rangos inclusivos de direcciones lineales de CS:IP. Vacio es todo
+    pub ranges: Vec<(u32, u32)>,
+    // Ventana de ciclos, con los ciclos al empezar la instruccion
+    pub cycles: Range<u64>,
+
+    out: Box<dyn Write + Send>,
+    regs: [u16; 14],
+    cycle: u64,
+    pub records: u64,
+    // El primer error de escritura para la traza. Se devuelve en finish
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        let mut tracer = Tracer {
+            ranges: Vec::new(),
+            cycles: 0..u64::MAX,
+
+            out: Box::new(out),
+            regs: [0; 14],
+            cycle: 0,
+            records: 0,
+            error: None,
+        };
+
+        let mut w = StateWriter::new();
+        for b in TRACE_MAGIC {
+            w.write_u8(*b);
+        }
+        w.write_u16(TRACE_VERSION);
+        tracer.write(&w.into_inner());
+        tracer
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn wants(&self, cycle: u64, address: u32) -> bool {
+        self.error.is_none()
+            && self.cycles.contains(&cycle)
+            && (self.ranges.is_empty() || self.ranges.iter().any(|&(start, end)| (start..=end).contains(&address)))
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        if self.error.is_none() {
+            if let Err(err) = self.out.write_all(data) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) {
+        let mut w = StateWriter::new();
+        write_varint(&mut w, record.cycle.wrapping_sub(self.cycle));
+
+        let mask = (0..14).filter(|&i| record.regs[i] != self.regs[i]).fold(0u16, |mask, i| mask | 1 << i);
+        w.write_u16(mask);
+        for i in (0..14).filter(|&i| mask & 1 << i != 0) {
+            w.write_u16(record.regs[i]);
+        }
+
+        w.write_u8(record.bytes.len() as u8);
+        for byte in &record.bytes {
+            w.write_u8(*byte);
+        }
+
+        w.write_u8(record.accesses.len().min(0xFF) as u8);
+        for access in record.accesses.iter().take(0xFF) {
+            let (kind, address, val) = match *access {
+                BusAccess::MemRead(address, val) => (0, address, val as u16),
+                BusAccess::MemWrite(address, val) => (1, address, val as u16),
+                BusAccess::IoRead(port, val) => (2, port as u32, val),
+                BusAccess::IoWrite(port, val) => (3, port as u32, val),
+            };
+            w.write_u8(kind);
+            w.write_u32(address);
+            w.write_u16(val);
+        }
+
+        self.write(&w.into_inner());
+        self.regs = record.regs;
+        self.cycle = record.cycle;
+        self.records += 1;
+    }
+
+    // Vacia lo que quede y devuelve cuantas instrucciones se han grabado
+    pub fn finish(mut self) -> io::Result<u64> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.out.flush()?;
+        Ok(self.records)
+    }
+}
+
+pub struct TraceReader<'a> {
+    r: StateReader<'a>,
+    regs: [u16; 14],
+    cycle: u64,
+    // Despues de un error no se sigue leyendo
+    done: bool,
+}
+
+impl<'a> TraceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, TraceError> {
+        if data.len() < TRACE_MAGIC.len() || &data[..TRACE_MAGIC.len()] != TRACE_MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+
+        let mut r = StateReader::new(&data[TRACE_MAGIC.len()..]);
+        let version = r.read_u16()?;
+        if version != TRACE_VERSION {
+            return Err(TraceError::Version(version));
+        }
+
+        Ok(TraceReader { r, regs: [0; 14], cycle: 0, done: false })
+    }
+
+    fn next_record(&mut self) -> Result<TraceRecord, TraceError> {
+        self.cycle = self.cycle.wrapping_add(read_varint(&mut self.r)?);
+
+        let mask = self.r.read_u16()?;
+        for i in (0..14).filter(|&i| mask & 1 << i != 0) {
+            self.regs[i] = self.r.read_u16()?;
+        }
+
+        let len = self.r.read_u8()?;
+        let bytes = (0..len).map(|_| self.r.read_u8()).collect::<Result<Vec<u8>, StateError>>()?;
+
+        let count = self.r.read_u8()?;
+        let mut accesses = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let kind = self.r.read_u8()?;
+            let address = self.r.read_u32()?;
+            let val = self.r.read_u16()?;
+            accesses.push(match kind {
+                0 => BusAccess::MemRead(address, val as u8),
+                1 => BusAccess::MemWrite(address, val as u8),
+                2 => BusAccess::IoRead(address as u16, val),
+                3 => BusAccess::IoWrite(address as u16, val),
+                _ => return Err(TraceError::Invalid("access")),
+            });
+        }
+
+        Ok(TraceRecord { cycle: self.cycle, regs: self.regs, bytes, accesses })
+    }
+}
+
+impl Iterator for TraceReader<'_> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.r.is_empty() {
+            return None;
+        }
+        let record = self.next_record();
+        self.done = record.is_err();
+        Some(record)
+    }
+}
+
+// Una linea de texto por instruccion. Devuelve cuantas ha escrito
+pub fn export_text<W: Write>(data: &[u8], out: &mut W) -> Result<u64, TraceError> {
+    let mut count = 0;
+    for record in TraceReader::new(data)? {
+        writeln!(out, "{}", record?)?;
+        count += 1;
+    }
+    Ok(count)
+}
@@ -0,0 +1,263 @@
+use std::fmt::Display;
+
+use crate::hardware::cpu_8088::dissasemble::dissasemble;
+use crate::hardware::sys::System;
+use crate::util::trace::{BusAccess, TraceError, TraceReader, TraceRecord, REGISTERS};
+
+// Compara la maquina paso a paso con una traza de referencia, binaria o de texto.
+//
+// Formato de texto: una linea por cada paso de System::step (cada vuelta de un REP es un paso)
+//     [ciclos] CS:IP [bytes y desensamblado] AX=hhhh BX=hhhh ... FLAGS=hhhh [W aaaaa=vv] [O pppp=vv]
+// - CS:IP es el primer campo con la forma hhhh:hhhh
+// - Los registros son los de antes de ejecutar la instruccion. Solo se comparan los que aparecen.
+//   Valen AX BX CX DX SP BP SI DI CS DS ES SS IP FLAGS, y FL como FLAGS
+// - Los accesos al bus van entre corchetes: R y W de memoria con la direccion lineal, I y O de puertos
+//   Los accesos solo se comparan en las lineas que traen corchetes, porque muchos emuladores no los sacan
+// - Las lineas vacias y las que empiezan por # o ; no cuentan
+// Es lo mismo que saca export_text, asi que una traza exportada sirve de referencia
+
+const FLAGS: usize = 13;
+// OF DF IF TF SF ZF AF PF CF. Los bits reservados cambian de un emulador a otro
+pub const FLAGS_MASK: u16 = 0x0FD5;
+const FLAG_NAMES: [(u16, &str); 9] = [
+    (0x0800, "OF"), (0x0400, "DF"), (0x0200, "IF"), (0x0100, "TF"), (0x0080, "SF"),
+    (0x0040, "ZF"), (0x0010, "AF"), (0x0004, "PF"), (0x0001, "CF"),
+];
+
+// Un paso de la referencia
+#[derive(Clone, PartialEq, Debug)]
+pub struct Expected {
+    // Linea del fichero de texto, o numero de registro en una traza binaria
+    pub line: usize,
+    pub cs: u16,
+    pub ip: u16,
+    pub regs: [Option<u16>; 14],
+    // None si la referencia no dice nada del bus en este paso
+    pub accesses: Option<Vec<BusAccess>>,
+}
+
+impl Expected {
+    pub fn from_record(line: usize, record: &TraceRecord) -> Self {
+        Expected {
+            line,
+            cs: record.cs(),
+            ip: record.ip(),
+            regs: record.regs.map(Some),
+            accesses: Some(record.accesses.clone()),
+        }
+    }
+
+    pub fn parse(line: usize, text: &str) -> Result<Option<Self>, TraceError> {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') || text.starts_with(';') {
+            return Ok(None);
+        }
+        let error = || TraceError::Parse(line, text.to_string());
+        let hex = |text: &str| u32::from_str_radix(text, 16).map_err(|_| error());
+
+        let mut address = None;
+        let mut regs = [None; 14];
+        for field in text.split_whitespace() {
+            if address.is_none() {
+                if let Some((cs, ip)) = field.split_once(':') {
+                    if cs.len() == 4 && ip.len() == 4 {
+                        if let (Ok(cs), Ok(ip)) = (u16::from_str_radix(cs, 16), u16::from_str_radix(ip, 16)) {
+                            address = Some((cs, ip));
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            if let Some((name, val)) = field.split_once('=') {
+                let name = if name == "FL" {"FLAGS"} else {name};
+                if let Some(reg) = REGISTERS.iter().position(|other| *other == name) {
+                    regs[reg] = Some(hex(val)? as u16);
+                }
+            }
+        }
+        let (cs, ip) = address.ok_or_else(error)?;
+        for (reg, val) in [(8, cs), (12, ip)] {
+            regs[reg].get_or_insert(val);
+        }
+
+        // [W 00400=12]
+        let mut accesses: Option<Vec<BusAccess>> = None;
+        for part in text.split('[').skip(1) {
+            let Some((kind, rest)) = part.split_once(' ') else {
+                continue;
+            };
+            if !matches!(kind, "R" | "W" | "I" | "O") {
+                continue;
+            }
+            let (address, val) = rest.split_once(']').and_then(|(access, _)| access.split_once('=')).ok_or_else(error)?;
+            let (address, val) = (hex(address)?, hex(val)?);
+            accesses.get_or_insert_with(Vec::new).push(match kind {
+                "R" => BusAccess::MemRead(address, val as u8),
+                "W" => BusAccess::MemWrite(address, val as u8),
+                "I" => BusAccess::IoRead(address as u16, val as u16),
+                _ => BusAccess::IoWrite(address as u16, val as u16),
+            });
+        }
+
+        Ok(Some(Expected { line, cs, ip, regs, accesses }))
+    }
+}
+
+// Lee una referencia de texto
+pub fn parse_reference(text: &str) -> Result<Vec<Expected>, TraceError> {
+    let mut expected = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if let Some(step) = Expected::parse(i + 1, line)? {
+            expected.push(step);
+        }
+    }
+    Ok(expected)
+}
+
+// Una traza binaria o de texto, segun empiece o no por la firma
+pub fn load_reference(data: &[u8]) -> Result<Vec<Expected>, TraceError> {
+    match TraceReader::new(data) {
+        Ok(reader) => reader.enumerate().map(|(i, record)| Ok(Expected::from_record(i + 1, &record?))).collect(),
+        Err(TraceError::BadMagic) => parse_reference(&String::from_utf8_lossy(data)),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Mismatch {
+    Address(u16, u16),
+    Register(&'static str, u16, u16),
+    Flags(u16, u16),
+    Accesses(Vec<BusAccess>, Vec<BusAccess>),
+    // La CPU esta parada en un HLT y la referencia sigue
+    Halted,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |accesses: &[BusAccess]| {
+            let list: Vec<String> = accesses.iter().map(|access| format!("[{}]", access)).collect();
+            if list.is_empty() {String::from("nothing")} else {list.join(" ")}
+        };
+
+        match self {
+            Mismatch::Address(cs, ip) => write!(f, "CS:IP expected {:04X}:{:04X}", cs, ip),
+            Mismatch::Register(name, expected, actual) => write!(f, "{} expected {:04X}, got {:04X}", name, expected, actual),
+            Mismatch::Flags(expected, actual) => {
+                let names: Vec<String> = FLAG_NAMES.iter()
+                    .filter(|(bit, _)| (expected ^ actual) & bit != 0)
+                    .map(|(bit, name)| format!("{}={}", name, (actual & bit != 0) as u8))
+                    .collect();
+                write!(f, "FLAGS expected {:04X}, got {:04X} ({})", expected, actual, names.join(" "))
+            },
+            Mismatch::Accesses(expected, actual) => write!(f, "bus expected {}, got {}", list(expected), list(actual)),
+            Mismatch::Halted => write!(f, "the CPU is halted"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Divergence {
+    // Pasos que coinciden antes de la divergencia
+    pub step: u64,
+    pub line: usize,
+    pub mismatch: Mismatch,
+    // La instruccion en la que se ha visto. Con registros es la que iba a ejecutarse
+    pub record: TraceRecord,
+    // La anterior, que es la que suele tener la culpa de los registros
+    pub previous: Option<TraceRecord>,
+}
+
+fn describe(record: &TraceRecord) -> String {
+    let bytes: Vec<String> = record.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let text = if record.bytes.is_empty() {String::from("(rep)")} else {dissasemble(&record.bytes, record.ip()).text};
+    format!("{:04X}:{:04X}  {:<12} {}", record.cs(), record.ip(), bytes.join(""), text)
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "divergence after {} steps (reference line {}): {}", self.step, self.line, self.mismatch)?;
+        if let Some(previous) = &self.previous {
+            writeln!(f, "previous  {}", describe(previous))?;
+        }
+        write!(f, "current   {}", describe(&self.record))
+    }
+}
+
+pub struct Comparator {
+    // Bits de FLAGS que se comparan
+    pub flags_mask: u16,
+    // Comparar los accesos al bus ademas de los registros
+    pub accesses: bool,
+}
+
+impl Comparator {
+    pub fn new() -> Self {
+        Comparator {
+            flags_mask: FLAGS_MASK,
+            accesses: true,
+        }
+    }
+
+    fn compare_regs(&self, expected: &Expected, actual: &TraceRecord) -> Option<Mismatch> {
+        if (expected.cs, expected.ip) != (actual.cs(), actual.ip()) {
+            return Some(Mismatch::Address(expected.cs, expected.ip));
+        }
+
+        for (reg, name) in REGISTERS.iter().enumerate() {
+            let Some(val) = expected.regs[reg] else {
+                continue;
+            };
+            if reg == FLAGS {
+                if (val ^ actual.regs[reg]) & self.flags_mask != 0 {
+                    return Some(Mismatch::Flags(val, actual.regs[reg]));
+                }
+            } else if val != actual.regs[reg] {
+                return Some(Mismatch::Register(name, val, actual.regs[reg]));
+            }
+        }
+        None
+    }
+
+    // Ejecuta un paso por cada uno de la referencia hasta la primera diferencia.
+    // Devuelve cuantos pasos coinciden, o la divergencia
+    pub fn run(&self, sys: &mut System, reference: &[Expected]) -> Result<u64, Box<Divergence>> {
+        // Los accesos al bus los recoge el comparador, no la traza
+        let tracer = sys.tracer.take();
+        let result = self.lockstep(sys, reference);
+        sys.tracer = tracer;
+        result
+    }
+
+    fn lockstep(&self, sys: &mut System, reference: &[Expected]) -> Result<u64, Box<Divergence>> {
+        let mut previous: Option<TraceRecord> = None;
+
+        for (step, expected) in reference.iter().enumerate() {
+            let mut record = TraceRecord::capture(&sys.cpu, &sys.bus, sys.total_cycles);
+            let mismatch = if sys.cpu.halted {Some(Mismatch::Halted)} else {self.compare_regs(expected, &record)};
+            if let Some(mismatch) = mismatch {
+                return Err(Box::new(Divergence { step: step as u64, line: expected.line, mismatch, record, previous }));
+            }
+
+            sys.bus.trace.enabled = true;
+            let mut cycles = 0;
+            sys.step(&mut cycles);
+            sys.bus.trace.enabled = false;
+            record.accesses = sys.bus.trace.take();
+
+            if let Some(accesses) = expected.accesses.as_ref().filter(|accesses| self.accesses && **accesses != record.accesses) {
+                let mismatch = Mismatch::Accesses(accesses.clone(), record.accesses.clone());
+                return Err(Box::new(Divergence { step: step as u64, line: expected.line, mismatch, record, previous }));
+            }
+            previous = Some(record);
+        }
+        Ok(reference.len() as u64)
+    }
+}
+
+impl Default for Comparator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
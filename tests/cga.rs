@@ -0,0 +1,48 @@
+use ibm_5150::{DisplayType, Headless, MachineConfig};
+use ibm_5150::hardware::display::ibm_cga::{IbmCGA, IMG_WIDTH};
+use ibm_5150::hardware::peripheral::Peripheral;
+
+fn pixel(cga: &IbmCGA, x: usize, y: usize) -> [u8; 3] {
+    let pos = (y * IMG_WIDTH + x) * 4;
+    [cga.img_buffer[pos], cga.img_buffer[pos + 1], cga.img_buffer[pos + 2]]
+}
+
+pub fn test_boot_cga() {
+    let mut config = MachineConfig::ibm_5150_1981();
+    config.display = DisplayType::Cga80;
+
+    let mut headless = Headless::with_config(config).unwrap();
+    assert!(headless.run_until_text("Ok", 2000), "{}", headless.screen_text());
+    assert_eq!(headless.screen_rows()[0].len(), 80);
+
+    let vram = headless.sys.bus.memory[0xB8000..0xBC000].to_vec();
+    headless.sys.bus.cga.render(&vram);
+    assert!(headless.sys.bus.cga.img_buffer.chunks(4).any(|p| p[0] > 0));
+}
+
+pub fn test_cga_graphics() {
+    let mut cga = IbmCGA::new();
+    let mut vram = vec![0; 0x4000];
+
+    // 320x200, paleta 1 con intensidad y fondo azul
+    cga.port_out(0x0A, 0x3D8);
+    cga.port_out(0x31, 0x3D9);
+    vram[0] = 0b00_01_10_11;
+    vram[0x2000] = 0b11_00_00_00;
+    cga.render(&vram);
+
+    assert_eq!(pixel(&cga, 0, 0), [0x00, 0x00, 0xAA]);
+    assert_eq!(pixel(&cga, 2, 0), [0x55, 0xFF, 0xFF]);
+    assert_eq!(pixel(&cga, 5, 0), [0xFF, 0x55, 0xFF]);
+    assert_eq!(pixel(&cga, 6, 0), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(pixel(&cga, 0, 1), [0xFF, 0xFF, 0xFF]);
+
+    // 640x200 con primer plano amarillo
+    cga.port_out(0x1A, 0x3D8);
+    cga.port_out(0x0E, 0x3D9);
+    vram[0] = 0b10000000;
+    cga.render(&vram);
+
+    assert_eq!(pixel(&cga, 0, 0), [0xFF, 0xFF, 0x55]);
+    assert_eq!(pixel(&cga, 1, 0), [0x00, 0x00, 0x00]);
+}
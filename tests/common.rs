@@ -0,0 +1,59 @@
+use ibm_5150::System;
+use ibm_5150::hardware::{bus::Bus, cpu_8088::CPU};
+
+// Donde se cargan los programas de prueba
+pub const CODE: u16 = 0x1000;
+pub const DATA: u16 = 0x2000;
+
+// CPU con el programa en CODE:0000, DS y SS en DATA y la pila en DATA:1000
+pub fn load_cpu(code: &[u8], bus: &mut Bus) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.cs = CODE;
+    cpu.ip = 0;
+    cpu.ds = DATA;
+    cpu.ss = DATA;
+    cpu.sp = 0x1000;
+
+    for (i, byte) in code.iter().enumerate() {
+        bus.write_8(CODE, i as u16, *byte);
+    }
+    cpu
+}
+
+// Una instruccion y las interrupciones que pida
+pub fn step_cpu(cpu: &mut CPU, bus: &mut Bus) {
+    cpu.fetch_decode_execute(bus);
+    cpu.handle_interrupts(bus);
+}
+
+// Ejecuta hasta el HLT
+pub fn run_cpu(cpu: &mut CPU, bus: &mut Bus) {
+    for _ in 0..100 {
+        if cpu.halted {
+            break;
+        }
+        step_cpu(cpu, bus);
+    }
+    assert!(cpu.halted);
+}
+
+// Ejecuta el programa en CODE:0000 hasta el HLT
+pub fn run(code: &[u8], bus: &mut Bus) -> CPU {
+    let mut cpu = load_cpu(code, bus);
+    run_cpu(&mut cpu, bus);
+    cpu
+}
+
+// System sin ROMs con el programa en CODE:0000, como load_cpu
+pub fn load_system(code: &[u8]) -> System {
+    let mut sys = System::new();
+    sys.cpu = load_cpu(code, &mut sys.bus);
+    sys
+}
+
+pub fn step_system(sys: &mut System, count: usize) {
+    for _ in 0..count {
+        let mut cycles = 0;
+        sys.step(&mut cycles);
+    }
+}
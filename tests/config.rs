@@ -0,0 +1,34 @@
+use ibm_5150::{DiskError, FloppyDisk, Headless, MachineConfig, RomError, RomImage, System};
+
+pub fn test_boot_1981() {
+    let mut headless = Headless::with_config(MachineConfig::ibm_5150_1981()).unwrap();
+
+    assert!(headless.run_until_text("Version C1.00", 2000));
+}
+
+pub fn test_missing_rom() {
+    let mut config = MachineConfig::ibm_5150_1982();
+    config.option_roms.push(RomImage::new("roms/no_existe.bin", 0xC8000));
+
+    assert!(matches!(Headless::with_config(config), Err(RomError::Io(..))));
+}
+
+pub fn test_rom_out_of_range() {
+    let mut config = MachineConfig::ibm_5150_1982();
+    config.bios.address = 0xFF000;
+
+    assert!(matches!(Headless::with_config(config), Err(RomError::OutOfRange(..))));
+}
+
+pub fn test_config_args() {
+    let args: Vec<String> = ["ibm_5150", "--cga", "--floppy", "a.img", "--floppy", "b.img"].iter().map(|arg| arg.to_string()).collect();
+    let (config, floppies) = MachineConfig::from_args(&args).unwrap();
+    assert_eq!((config.floppy_drives, floppies), (2, vec!["a.img".to_string(), "b.img".to_string()]));
+
+    // El FDC solo tiene 4 unidades
+    let args: Vec<String> = (0..5).flat_map(|i| ["--floppy".to_string(), format!("{}.img", i)]).collect();
+    assert!(matches!(MachineConfig::from_args(&args), Err(DiskError::NoDrive(4))));
+    let mut sys = System::with_config(MachineConfig { floppy_drives: 9, ..Default::default() });
+    let disk = FloppyDisk::from_bytes(vec![0; 368_640]).unwrap();
+    assert!(matches!(sys.insert_floppy(4, disk), Err(DiskError::NoDrive(4))));
+}
@@ -0,0 +1,126 @@
+use ibm_5150::util::debugger::{Access, Breakpoint, Condition, Stop};
+
+use crate::common::*;
+
+// Bucle que escribe CX en [100] y lo saca por el puerto 80
+const LOOP: [u8; 14] = [
+    0xB9, 0x05, 0x00,       // MOV CX, 5
+    0x89, 0x0E, 0x00, 0x01, // MOV [100], CX
+    0x89, 0xC8,             // MOV AX, CX
+    0xE6, 0x80,             // OUT 80, AL
+    0xE2, 0xF6,             // LOOP -10
+    0xF4,
+];
+
+pub fn test_breakpoints() {
+    let mut sys = load_system(&LOOP);
+
+    // Ejecucion con condicion
+    let id = sys.debugger.add(Breakpoint::Exec(CODE, 0x0009), Some(Condition::parse("CX == 3").unwrap()));
+    assert_eq!(sys.debug_run(100_000), Stop::Breakpoint(id));
+    assert_eq!(sys.cpu.ip, 0x0009);
+    assert_eq!(sys.cpu.cx.get_x(), 3);
+
+    // Al seguir no se para en la misma instruccion
+    sys.debugger.remove(id).unwrap();
+    let id = sys.debugger.add(Breakpoint::Linear(0x10009), None);
+    assert_eq!(sys.debug_run(100_000), Stop::Breakpoint(id));
+    assert_eq!(sys.cpu.cx.get_x(), 2);
+    sys.debugger.set_enabled(id, false).unwrap();
+
+    // Escritura en memoria: para despues del MOV
+    let id = sys.debugger.add(Breakpoint::Memory(0x20100, 0x20101, Access::Write), None);
+    assert_eq!(sys.debug_run(100_000), Stop::Breakpoint(id));
+    assert_eq!(sys.cpu.ip, 0x0007);
+    assert_eq!(sys.bus.read_8(DATA, 0x100), 1);
+    sys.debugger.clear();
+
+    // Puerto con condicion sobre AL
+    let mut sys = load_system(&LOOP);
+    let id = sys.debugger.add(Breakpoint::Port(0x80, Access::Write), Some(Condition::parse("al < 3 && ZF || al == 5").unwrap()));
+    assert_eq!(sys.debug_run(100_000), Stop::Breakpoint(id));
+    assert_eq!(sys.cpu.ax.low, 5);
+    assert_eq!(sys.debug_run(100_000), Stop::Halted);
+    assert_eq!(sys.debugger.breakpoints()[0].hits, 1);
+    assert!(sys.bus.watchpoints.take_hits().is_empty());
+
+    // Una lectura no dispara un watchpoint de escritura
+    let mut sys = load_system(&[0xA1, 0x00, 0x01, 0xF4]);
+    sys.debugger.add(Breakpoint::Memory(0x20100, 0x20100, Access::Write), None);
+    assert_eq!(sys.debug_run(1_000), Stop::Halted);
+    assert_eq!(sys.debug_run(1_000), Stop::Halted);
+
+    // Un rango que pasa de FFFFF sigue en 00000
+    let mut sys = load_system(&[0xA2, 0x00, 0x00, 0xF4]);
+    sys.cpu.ds = 0;
+    let id = sys.debugger.add(Breakpoint::Memory(0xFFFF0, 0x00010, Access::Write), None);
+    assert_eq!(sys.debug_run(1_000), Stop::Breakpoint(id));
+}
+
+pub fn test_stepping() {
+    let code = [
+        0xE8, 0x05, 0x00,       // CALL 0008
+        0xCD, 0x60,             // INT 60
+        0x90,                   // NOP
+        0xF4,
+        0x90,
+        0x40,                   // INC AX
+        0xE8, 0x01, 0x00,       // CALL 000D
+        0xC3,                   // RET
+        0x43,                   // INC BX
+        0xC3,                   // RET
+    ];
+    // INT 60 va a 1100:0000 y hace un IRET
+    let load_int = || {
+        let mut sys = load_system(&code);
+        sys.bus.write_16(0, 0x180, 0x0000);
+        sys.bus.write_16(0, 0x182, 0x1100);
+        sys.bus.write_8(0x1100, 0, 0xCF);
+        sys
+    };
+    let mut sys = load_int();
+
+    // Step over del CALL ejecuta la subrutina entera
+    assert_eq!(sys.step_over(1_000), Stop::Step);
+    assert_eq!(sys.cpu.ip, 0x0003);
+    assert_eq!(sys.cpu.ax.get_x(), 1);
+    assert_eq!(sys.cpu.bx.get_x(), 1);
+
+    // Y el del INT la interrupcion
+    let id = sys.debugger.add(Breakpoint::Interrupt(0x60), None);
+    assert_eq!(sys.step_over(1_000), Stop::Breakpoint(id));
+    assert_eq!((sys.cpu.cs, sys.cpu.ip), (0x1100, 0));
+    sys.debugger.remove(id).unwrap();
+    assert_eq!(sys.step_out(1_000), Stop::Step);
+    assert_eq!((sys.cpu.cs, sys.cpu.ip), (CODE, 0x0005));
+
+    // Step out desde dentro de la subrutina anidada vuelve a la de fuera
+    let mut sys = load_int();
+    sys.debug_command("b 1000:000D").unwrap();
+    assert_eq!(sys.debug_command("c").unwrap(), "breakpoint 1 at 1000:000D");
+    assert_eq!(sys.step_out(1_000), Stop::Step);
+    assert_eq!(sys.cpu.ip, 0x000C);
+    assert_eq!(sys.debug_command("s 2").unwrap(), "stopped at 1100:0000");
+    assert_eq!(sys.debug_command("").unwrap(), "stopped at 1000:0005");
+}
+
+pub fn test_debug_commands() {
+    let mut sys = load_system(&LOOP);
+
+    assert_eq!(sys.debug_command("watch w 2000:0100-2000:0101 if CX == 2").unwrap(), "breakpoint 1: watch w 20100-20101");
+    assert_eq!(sys.debug_command("port rw 80").unwrap(), "breakpoint 2: port rw 0080");
+    sys.debug_command("disable 2").unwrap();
+    assert!(sys.debug_command("int zz").is_err());
+    assert!(sys.debug_command("del 7").is_err());
+    assert!(sys.debug_command("n if CX").is_err());
+
+    assert_eq!(sys.debug_command("c").unwrap(), "breakpoint 1 at 1000:0007");
+    assert!(sys.debug_command("r").unwrap().starts_with("AX=0003  BX=0000  CX=0002"));
+    assert!(sys.debug_command("x 2000:0100 2").unwrap().starts_with("20100  02 00"));
+    // Un volcado enorme se queda en 1 MB, y pasado FFFFF sigue en 00000
+    let all = sys.debug_command("x FFFF0 FFFFFFFF").unwrap();
+    assert_eq!(all.lines().count(), 0x10000);
+    assert!(all.lines().nth(1).unwrap().starts_with("00000  "));
+    assert_eq!(sys.debug_command("bl").unwrap(), "  1   watch w 20100-20101 if CX == 2 (1 hits)\n  2 - port rw 0080 (0 hits)");
+    assert_eq!(sys.debug_command("c").unwrap(), "halted at 1000:000E");
+}
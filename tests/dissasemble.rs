@@ -0,0 +1,76 @@
+use ibm_5150::System;
+use ibm_5150::hardware::cpu_8088::dissasemble::*;
+
+pub fn test_dissasemble_text() {
+    let cases: [(&[u8], &str); 24] = [
+        (&[0xB8, 0x34, 0x12], "MOV AX, 1234"),
+        (&[0x26, 0x8B, 0x47, 0xFE], "MOV AX, ES:[BX-02]"),
+        (&[0x88, 0x87, 0x00, 0x01], "MOV [BX+0100], AL"),
+        (&[0x8E, 0xD8], "MOV DS, AX"),
+        (&[0xA1, 0x10, 0x00], "MOV AX, [0010]"),
+        (&[0x83, 0x06, 0x00, 0x01, 0xFF], "ADD WORD PTR [0100], FFFF"),
+        (&[0x80, 0x3E, 0x49, 0x04, 0x07], "CMP BYTE PTR [0449], 07"),
+        (&[0xF6, 0x46, 0x02, 0x80], "TEST BYTE PTR [BP+02], 80"),
+        (&[0xD3, 0xE0], "SHL AX, CL"),
+        (&[0xD0, 0xF1], "SETMO CL, 1"),
+        (&[0xFF, 0x1E, 0x12, 0x00], "CALL DWORD PTR [0012]"),
+        (&[0xFF, 0x27], "JMP WORD PTR [BX]"),
+        (&[0xFF, 0xF8], "PUSH AX"),
+        (&[0xF3, 0xA4], "REP MOVSB"),
+        (&[0xF3, 0xA6], "REPZ CMPSB"),
+        (&[0xF2, 0x2E, 0xAE], "REPNZ CS: SCASB"),
+        (&[0xF0, 0x86, 0x07], "LOCK XCHG [BX], AL"),
+        (&[0xE4, 0x60], "IN AL, 60"),
+        (&[0xEF], "OUT DX, AX"),
+        (&[0xCD, 0x10], "INT 10"),
+        (&[0xD4, 0x0A], "AAM"),
+        (&[0xC9], "RETF"),
+        (&[0xDD, 0x5E, 0x02], "ESC 2B, [BP+02]"),
+        (&[0x0F], "POP CS"),
+    ];
+    for (code, text) in cases {
+        let instr = dissasemble(code, 0x0100);
+        assert_eq!(instr.text, text);
+        assert_eq!(instr.bytes, code);
+        assert_eq!(instr.target, None);
+    }
+
+    // Saltos con el destino ya calculado
+    let jumps: [(&[u8], &str, Target); 6] = [
+        (&[0xEB, 0xFE], "JMP SHORT 0100", Target::Near(0x0100)),
+        (&[0x74, 0x10], "JZ 0112", Target::Near(0x0112)),
+        (&[0x64, 0x10], "JZ 0112", Target::Near(0x0112)),
+        (&[0xE2, 0xFC], "LOOP 00FE", Target::Near(0x00FE)),
+        (&[0xE8, 0x00, 0xFF], "CALL 0003", Target::Near(0x0003)),
+        (&[0xEA, 0x5B, 0xE0, 0x00, 0xF0], "JMP F000:E05B", Target::Far(0xF000, 0xE05B)),
+    ];
+    for (code, text, target) in jumps {
+        let instr = dissasemble(code, 0x0100);
+        assert_eq!(instr.text, text);
+        assert_eq!(instr.target, Some(target));
+    }
+
+    // Si faltan bytes sale un DB
+    let instr = dissasemble(&[0xB8, 0x34], 0);
+    assert_eq!((instr.text.as_str(), instr.bytes.len()), ("DB B8", 1));
+}
+
+pub fn test_dissasemble_listing() {
+    let code = [0xFA, 0xB4, 0xD5, 0x9E, 0x73, 0xFE, 0xE9];
+    assert_eq!(listing(&code, 0xF000, 0xE05B), "\
+F000:E05B  FA              CLI
+F000:E05C  B4D5            MOV AH, D5
+F000:E05E  9E              SAHF
+F000:E05F  73FE            JNB E05F
+F000:E061  E9              DB E9");
+
+    // Desde el bus no se toca nada: ni la CPU ni los ciclos
+    let mut sys = System::new();
+    for (i, byte) in code.iter().enumerate() {
+        sys.bus.write_8(0x1000, 0xFFFE_u16.wrapping_add(i as u16), *byte);
+    }
+    let (ip, cycles) = (sys.cpu.ip, sys.cpu.cycles);
+    let instr = dissasemble_bus(&sys.bus, 0x1000, 0xFFFF);
+    assert_eq!((instr.text.as_str(), instr.bytes.as_slice()), ("MOV AH, D5", &[0xB4, 0xD5][..]));
+    assert_eq!((sys.cpu.ip, sys.cpu.cycles), (ip, cycles));
+}
@@ -0,0 +1,114 @@
+use ibm_5150::hardware::peripheral::Peripheral;
+use ibm_5150::hardware::peripheral::dma_8237::{DMA8237, DmaDevice};
+
+// Dispositivo que entrega y recibe bytes y baja DREQ tras `limit` bytes
+struct TestDevice {
+    data: Vec<u8>,
+    received: Vec<u8>,
+    limit: usize,
+    transferred: usize,
+    tc: bool,
+}
+
+impl TestDevice {
+    fn new(data: &[u8], limit: usize) -> Self {
+        TestDevice { data: data.to_vec(), received: Vec::new(), limit, transferred: 0, tc: false }
+    }
+}
+
+impl DmaDevice for TestDevice {
+    fn dma_read(&mut self) -> u8 {
+        self.transferred += 1;
+        self.data.remove(0)
+    }
+
+    fn dma_write(&mut self, val: u8) {
+        self.transferred += 1;
+        self.received.push(val);
+    }
+
+    fn terminal_count(&mut self) {
+        self.tc = true;
+    }
+
+    fn dreq(&self) -> bool {
+        self.transferred < self.limit
+    }
+}
+
+fn program(dma: &mut DMA8237, mode: u8, addr: u16, count: u16) {
+    let channel = (mode & 0x03) as u16;
+
+    dma.port_out(mode as u16, 0x0B);
+    dma.port_out(0, 0x0C);
+    dma.port_out(addr & 0xFF, channel * 2);
+    dma.port_out(addr >> 8, channel * 2);
+    dma.port_out(count & 0xFF, channel * 2 + 1);
+    dma.port_out(count >> 8, channel * 2 + 1);
+    dma.port_out(channel, 0x0A);
+}
+
+pub fn test_dma_single() {
+    let mut dma = DMA8237::new();
+    let mut memory = vec![0; 0x100000];
+    let mut device = TestDevice::new(&[1, 2, 3, 4], 4);
+
+    // Canal 1, single, escritura en memoria, en 0x21000
+    dma.page_out(0x02, 0x83);
+    program(&mut dma, 0x45, 0x1000, 3);
+    dma.set_dreq(1, true);
+
+    for _ in 0..4 {
+        assert_eq!(dma.service(1, &mut device, &mut memory), 1);
+    }
+    assert_eq!(&memory[0x21000..0x21005], &[1, 2, 3, 4, 0]);
+    assert!(device.tc);
+
+    // Al llegar a TC sin autoinit el canal queda enmascarado
+    dma.set_dreq(1, true);
+    assert_eq!(dma.service(1, &mut device, &mut memory), 0);
+
+    assert_eq!(dma.port_in(0x08) & 0x02, 0x02);
+    assert_eq!(dma.port_in(0x08) & 0x02, 0x00);
+}
+
+pub fn test_dma_block_demand() {
+    let mut dma = DMA8237::new();
+    let mut memory = vec![0; 0x100000];
+    memory[0x500..0x508].copy_from_slice(&[9, 8, 7, 6, 5, 4, 3, 2]);
+
+    // Canal 2, block, lectura de memoria: todo en una peticion
+    let mut device = TestDevice::new(&[], 8);
+    program(&mut dma, 0x8A, 0x500, 7);
+    dma.set_dreq(2, true);
+    assert_eq!(dma.service(2, &mut device, &mut memory), 8);
+    assert_eq!(device.received, vec![9, 8, 7, 6, 5, 4, 3, 2]);
+
+    // Canal 3, demand: se para cuando el dispositivo baja DREQ
+    let mut device = TestDevice::new(&[], 3);
+    program(&mut dma, 0x0B, 0x500, 7);
+    dma.set_dreq(3, true);
+    assert_eq!(dma.service(3, &mut device, &mut memory), 3);
+    assert!(!device.tc);
+}
+
+pub fn test_dma_autoinit() {
+    let mut dma = DMA8237::new();
+    let mut memory = vec![0; 0x100000];
+    let mut device = TestDevice::new(&[], 0);
+
+    // Canal 0 como lo programa la BIOS para el refresco
+    program(&mut dma, 0x58, 0x1234, 1);
+    for _ in 0..2 {
+        dma.set_dreq(0, true);
+        dma.service(0, &mut device, &mut memory);
+    }
+
+    dma.port_out(0, 0x0C);
+    assert_eq!(dma.port_in(0x00), 0x34);
+    assert_eq!(dma.port_in(0x00), 0x12);
+    assert_eq!(dma.port_in(0x01), 0x01);
+
+    dma.set_dreq(0, true);
+    assert_eq!(dma.service(0, &mut device, &mut memory), 1);
+}
@@ -0,0 +1,181 @@
+use ibm_5150::hardware::{bus::Bus, cpu_8088::CPU};
+
+use crate::common::*;
+
+const HANDLERS: u16 = 0x3000;
+
+// Carga el programa y apunta los vectores a HANDLERS:(tipo * 0x10)
+fn load(code: &[u8], bus: &mut Bus) -> CPU {
+    let cpu = load_cpu(code, bus);
+    for int in 0..5 {
+        bus.write_16(0, int * 4, int * 0x10);
+        bus.write_16(0, int * 4 + 2, HANDLERS);
+        bus.write_8(HANDLERS, int * 0x10, 0xF4);
+    }
+    cpu
+}
+
+pub fn test_divide_error() {
+    let mut bus = Bus::new();
+    let code = [
+        0xB8, 0x00, 0x10,       // MOV AX, 1000
+        0xB3, 0x10,             // MOV BL, 10
+        0xF6, 0xF3,             // DIV BL: el cociente no cabe en AL
+        0xF4,
+    ];
+    let mut cpu = load(&code, &mut bus);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (HANDLERS, 0x01));
+    // Vuelve a la instruccion siguiente al DIV
+    assert_eq!(bus.read_16(DATA, cpu.sp), 7);
+    assert_eq!(bus.read_16(DATA, cpu.sp + 2), CODE);
+    assert_eq!(cpu.ax.get_x(), 0x1000);
+
+    // DX:AX / r16
+    let code = [
+        0xBA, 0x01, 0x00,       // MOV DX, 1
+        0xB8, 0x05, 0x00,       // MOV AX, 5
+        0xBB, 0x02, 0x00,       // MOV BX, 2
+        0xF7, 0xF3,             // DIV BX
+        0xF4,
+    ];
+    let mut cpu = load(&code, &mut bus);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!(cpu.cs, CODE);
+    assert_eq!(cpu.ax.get_x(), 0x8002);
+    assert_eq!(cpu.dx.get_x(), 1);
+
+    // IDIV con divisor negativo y el cociente -128, que en el 8088 es error
+    let code = [
+        0xB8, 0xF9, 0xFF,       // MOV AX, -7
+        0xB3, 0xFE,             // MOV BL, -2
+        0xF6, 0xFB,             // IDIV BL
+        0xA3, 0x00, 0x01,       // MOV [100], AX
+        0xB8, 0x00, 0xFF,       // MOV AX, -256
+        0xB3, 0x02,             // MOV BL, 2
+        0xF6, 0xFB,             // IDIV BL
+        0xF4,
+    ];
+    let mut cpu = load(&code, &mut bus);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!(bus.read_16(DATA, 0x100), 0xFF03);
+    assert_eq!((cpu.cs, cpu.ip), (HANDLERS, 0x01));
+    assert_eq!(bus.read_16(DATA, cpu.sp), 17);
+}
+
+pub fn test_single_step() {
+    let mut bus = Bus::new();
+    let code = [
+        0x9C,                   // PUSHF
+        0x58,                   // POP AX
+        0x0D, 0x00, 0x01,       // OR AX, 0100
+        0x50,                   // PUSH AX
+        0x9D,                   // POPF: el primer paso es despues de la siguiente
+        0x90,                   // NOP
+        0x8C, 0xD0,             // MOV AX, SS
+        0x8E, 0xD0,             // MOV SS, AX: no hay paso
+        0x90,                   // NOP
+        0x9C,                   // PUSHF
+        0x58,                   // POP AX
+        0x25, 0xFF, 0xFE,       // AND AX, FEFF
+        0x50,                   // PUSH AX
+        0x9D,                   // POPF: TF estaba activo al empezar
+        0xF4,
+    ];
+    let mut cpu = load(&code, &mut bus);
+    // INT 1 cuenta los pasos
+    for (i, byte) in [0xFE, 0x06, 0x00, 0x01, 0xCF].iter().enumerate() {
+        bus.write_8(HANDLERS, 0x10 + i as u16, *byte);
+    }
+    bus.write_8(DATA, 0x100, 0);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (CODE, code.len() as u16));
+    assert_eq!(bus.read_8(DATA, 0x100), 8);
+    assert!(!cpu.flags.t);
+}
+
+pub fn test_interrupt_priority() {
+    let mut bus = Bus::new();
+
+    // INT n no depende de IF
+    let mut cpu = load(&[0xFA, 0xCC, 0xF4], &mut bus);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (HANDLERS, 0x31));
+
+    // Con INT n y NMI a la vez va primero la interna y luego la NMI
+    let mut cpu = load(&[0xCD, 0x04, 0xF4], &mut bus);
+    cpu.nmi = true;
+    cpu.nmi_enabled = true;
+    step_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (HANDLERS, 0x40));
+    assert!(cpu.nmi);
+    cpu.handle_interrupts(&mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (HANDLERS, 0x20));
+    assert!(!cpu.nmi);
+
+    // MOV SS retrasa la NMI una instruccion
+    let mut cpu = load(&[0x8E, 0xD0, 0x90, 0xF4], &mut bus);
+    cpu.ax.set_x(DATA);
+    cpu.nmi = true;
+    cpu.nmi_enabled = true;
+    step_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (CODE, 2));
+    step_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (HANDLERS, 0x20));
+    assert_eq!(bus.read_16(DATA, cpu.sp), 3);
+}
+
+pub fn test_rep_interrupt() {
+    let mut bus = Bus::new();
+    // NMI con un IRET
+    let mut cpu = load(&[0xF3, 0xA5, 0xF4], &mut bus);
+    bus.write_8(HANDLERS, 0x20, 0xCF);
+    for i in 0..10 {
+        bus.write_16(DATA, 0x100 + i * 2, 0x1111 * i);
+    }
+    cpu.es = DATA;
+    cpu.si = 0x100;
+    cpu.di = 0x200;
+    cpu.cx.set_x(10);
+    cpu.nmi_enabled = true;
+
+    // REP MOVSW interrumpido a la mitad vuelve al REP y acaba la copia
+    for _ in 0..4 {
+        step_cpu(&mut cpu, &mut bus);
+    }
+    cpu.nmi = true;
+    cpu.handle_interrupts(&mut bus);
+    assert_eq!(cpu.cs, HANDLERS);
+    assert_eq!(bus.read_16(DATA, cpu.sp), 0);
+    assert_eq!(cpu.cx.get_x(), 6);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (CODE, 3));
+    assert_eq!(cpu.cx.get_x(), 0);
+    for i in 0..10 {
+        assert_eq!(bus.read_16(DATA, 0x200 + i * 2), 0x1111 * i);
+    }
+
+    // Con ES: REP MOVSB se vuelve al REP y se pierde el ES:
+    let other = 0x2100;
+    let mut cpu = load(&[0x26, 0xF3, 0xA4, 0xF4], &mut bus);
+    bus.write_8(HANDLERS, 0x20, 0xCF);
+    for i in 0..6 {
+        bus.write_8(DATA, i, 0xD0 + i as u8);
+        bus.write_8(other, i, 0xE0 + i as u8);
+    }
+    cpu.es = other;
+    cpu.si = 0;
+    cpu.di = 0x80;
+    cpu.cx.set_x(6);
+    cpu.nmi_enabled = true;
+    for _ in 0..3 {
+        step_cpu(&mut cpu, &mut bus);
+    }
+    cpu.nmi = true;
+    cpu.handle_interrupts(&mut bus);
+    assert_eq!(bus.read_16(DATA, cpu.sp), 1);
+    run_cpu(&mut cpu, &mut bus);
+    assert_eq!((cpu.cs, cpu.ip), (CODE, 4));
+    let copied: Vec<u8> = (0..6).map(|i| bus.read_8(other, 0x80 + i)).collect();
+    assert_eq!(copied, [0xE0, 0xE1, 0xE2, 0xD3, 0xD4, 0xD5]);
+}
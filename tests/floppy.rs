@@ -0,0 +1,149 @@
+use ibm_5150::{DiskError, FloppyDisk, Headless, MachineConfig, StateError, System};
+use ibm_5150::hardware::state::{Snapshot, StateWriter, StateReader};
+use ibm_5150::hardware::peripheral::Peripheral;
+use ibm_5150::hardware::peripheral::fdc_765::FDC765;
+use ibm_5150::hardware::peripheral::pic_8259::PIC8259;
+use ibm_5150::hardware::peripheral::dma_8237::{DMA8237, DmaDevice};
+
+// Lee el sector 2 en 0000:8000 con INT 13h, lo copia al sector 3 y
+// escribe en pantalla el texto que contiene
+const BOOT_SECTOR: [u8; 71] = [
+    0x31, 0xC0, 0x8E, 0xD8, 0x8E, 0xC0, 0xBB, 0x00, 0x80, 0xB8, 0x01, 0x02,
+    0xB9, 0x02, 0x00, 0x31, 0xD2, 0xCD, 0x13, 0x72, 0x22, 0xB8, 0x01, 0x03,
+    0xB9, 0x03, 0x00, 0x31, 0xD2, 0xCD, 0x13, 0x72, 0x16, 0xBE, 0x00, 0x80,
+    0xB8, 0x00, 0xB0, 0x8E, 0xC0, 0x31, 0xFF, 0xB4, 0x07, 0xAC, 0x08, 0xC0,
+    0x74, 0x03, 0xAB, 0xEB, 0xF8, 0xEB, 0xFE, 0xBE, 0x3C, 0x7C, 0xEB, 0xE8,
+    b'D', b'I', b'S', b'K', b' ', b'E', b'R', b'R', b'O', b'R', 0x00,
+];
+
+fn test_disk() -> FloppyDisk {
+    let mut data = vec![0xF6; 368_640];
+    data[..BOOT_SECTOR.len()].copy_from_slice(&BOOT_SECTOR);
+    data[510] = 0x55;
+    data[511] = 0xAA;
+
+    let msg = b"FLOPPY OK\0";
+    data[512..512 + msg.len()].copy_from_slice(msg);
+
+    FloppyDisk::from_bytes(data).unwrap()
+}
+
+pub fn test_boot_floppy() {
+    let mut config = MachineConfig::ibm_5150_1982();
+    config.floppy_drives = 1;
+
+    let mut headless = Headless::with_config(config).unwrap();
+    headless.sys.insert_floppy(0, test_disk()).unwrap();
+
+    assert!(headless.run_until_text("FLOPPY OK", 2000), "{}", headless.screen_text());
+
+    let disk = headless.sys.eject_floppy(0).unwrap();
+    assert_eq!(disk.read_sector(0, 0, 3), disk.read_sector(0, 0, 2));
+}
+
+pub fn test_disk_errors() {
+    assert!(matches!(FloppyDisk::from_bytes(vec![0; 1000]), Err(DiskError::UnknownSize(1000))));
+    assert_eq!(test_disk().geometry.sectors, 9);
+
+    let mut headless = Headless::new();
+    assert!(matches!(headless.sys.insert_floppy(0, test_disk()), Err(DiskError::NoDrive(0))));
+}
+
+// Manda un comando al FDC, espera a que termine y devuelve el resultado
+fn fdc_command(fdc: &mut FDC765, command: &[u8]) -> Vec<u8> {
+    let (mut pic, mut dma) = (PIC8259::new(), DMA8237::new());
+    for byte in command {
+        fdc.port_out(*byte as u16, 0x3F5);
+    }
+    fdc.update(2000, &mut pic, &mut dma);
+
+    let mut result = Vec::new();
+    while fdc.port_in(0x3F4) & 0xC0 == 0xC0 {
+        result.push(fdc.port_in(0x3F5) as u8);
+    }
+    result
+}
+
+fn fdc_with_disk() -> FDC765 {
+    let mut fdc = FDC765::new();
+    fdc.drives[0] = Some(test_disk());
+    fdc.port_out(0x0C, 0x3F2);
+    fdc.update(2000, &mut PIC8259::new(), &mut DMA8237::new());
+    assert_eq!(fdc_command(&mut fdc, &[0x08]), [0xC0, 0]);
+    fdc
+}
+
+pub fn test_fdc_seek_limit() {
+    // El cabezal se queda en el ultimo cilindro, el 39
+    let mut fdc = fdc_with_disk();
+    assert!(fdc_command(&mut fdc, &[0x0F, 0x00, 45]).is_empty());
+    assert_eq!(fdc_command(&mut fdc, &[0x08]), [0x20, 39]);
+
+    // Y un sector del cilindro 40 no existe
+    let result = fdc_command(&mut fdc, &[0xE6, 0x00, 40, 0, 1, 2, 9, 0x2A, 0xFF]);
+    assert_eq!(result[..3], [0x40, 0x04, 0x10]);
+}
+
+pub fn test_fdc_eject() {
+    // Se saca el disquete con el primer sector ya leido
+    let mut fdc = fdc_with_disk();
+    for byte in [0xE6, 0x00, 0, 0, 1, 2, 9, 0x2A, 0xFF] {
+        fdc.port_out(byte, 0x3F5);
+    }
+    fdc.update(2000, &mut PIC8259::new(), &mut DMA8237::new());
+    for _ in 0..512 {
+        fdc.dma_read();
+    }
+    fdc.drives[0] = None;
+
+    let result = fdc_command(&mut fdc, &[]);
+    assert_eq!(result[..3], [0x40, 0x01, 0x00]);
+}
+
+fn fdc_state(fdc: &FDC765) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    fdc.save_state(&mut w);
+    w.into_inner()
+}
+
+pub fn test_fdc_state() {
+    // Un READ DATA a medias se guarda y se carga
+    let mut fdc = fdc_with_disk();
+    for byte in [0xE6, 0x00, 0, 0, 1, 2, 9, 0x2A, 0xFF] {
+        fdc.port_out(byte, 0x3F5);
+    }
+    fdc.update(2000, &mut PIC8259::new(), &mut DMA8237::new());
+    let mut other = FDC765::new();
+    other.load_state(&mut StateReader::new(&fdc_state(&fdc))).unwrap();
+    assert_eq!(other.dma_read(), BOOT_SECTOR[0]);
+
+    // Sin comando ni resultado: DOR, fase, comando, resultado, posicion, cilindros, SENSE y la operacion pendiente
+    let data = fdc_state(&FDC765::new());
+    assert_eq!((data[1], &data[22..27]), (0, &[4, 0, 0, 0, 0][..]));
+    for (offset, val) in [(1, 1), (1, 2), (26, 3)] {
+        let mut data = data.clone();
+        data[offset] = val;
+        assert!(matches!(other.load_state(&mut StateReader::new(&data)), Err(StateError::Invalid("FDC command"))));
+    }
+}
+
+pub fn test_floppy_restore_path() {
+    let dir = std::env::temp_dir().join(format!("ibm_5150_floppy_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (path_a, path_b) = (dir.join("a.img"), dir.join("b.img"));
+    std::fs::write(&path_a, vec![0xAA; 368_640]).unwrap();
+    std::fs::write(&path_b, vec![0xBB; 368_640]).unwrap();
+
+    let mut sys = System::with_config(MachineConfig { floppy_drives: 1, ..Default::default() });
+    sys.insert_floppy(0, FloppyDisk::open(&path_a).unwrap()).unwrap();
+    let state = sys.snapshot();
+
+    // El estado se guardo con A, pero al salir con B puesto B no se toca
+    sys.insert_floppy(0, FloppyDisk::open(&path_b).unwrap()).unwrap();
+    sys.restore(&state).unwrap();
+    assert_eq!(sys.bus.fdc.drives[0].as_ref().unwrap().data[0], 0xAA);
+    sys.flush_floppies().unwrap();
+    let data_b = std::fs::read(&path_b).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(data_b.iter().all(|&b| b == 0xBB));
+}
\ No newline at end of file
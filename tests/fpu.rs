@@ -0,0 +1,136 @@
+use ibm_5150::{Headless, MachineConfig};
+use ibm_5150::hardware::{bus::Bus, cpu_8088::CPU, fpu_8087::FPU8087};
+
+use crate::common::*;
+
+// Ejecuta el programa con o sin 8087
+fn run_fpu(fpu: bool, code: &[u8], bus: &mut Bus) -> CPU {
+    let mut cpu = load_cpu(code, bus);
+    if fpu {
+        cpu.fpu = Some(FPU8087::new());
+    }
+    run_cpu(&mut cpu, bus);
+    cpu
+}
+
+fn read_f64(bus: &Bus, offset: u16) -> f64 {
+    let bytes: Vec<u8> = (0..8).map(|i| bus.read_8(DATA, offset + i)).collect();
+    f64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+pub fn test_fpu_arithmetic() {
+    let mut bus = Bus::new();
+    for (i, byte) in 10f64.to_le_bytes().iter().chain(4f64.to_le_bytes().iter()).enumerate() {
+        bus.write_8(DATA, 0x100 + i as u16, *byte);
+    }
+    bus.write_16(DATA, 0x120, 7);
+
+    let code = [
+        0xDB, 0xE3,             // FINIT
+        0xDD, 0x06, 0x00, 0x01, // FLD qword [100]
+        0xDD, 0x06, 0x08, 0x01, // FLD qword [108]
+        0xDE, 0xF9,             // FDIVP ST(1), ST
+        0xD9, 0xFA,             // FSQRT
+        0xDD, 0x1E, 0x10, 0x01, // FSTP qword [110]
+        0xDF, 0x06, 0x20, 0x01, // FILD word [120]
+        0xDE, 0x0E, 0x20, 0x01, // FIMUL word [120]
+        0xDB, 0x1E, 0x22, 0x01, // FISTP dword [122]
+        0xD9, 0xEB,             // FLDPI
+        0xD8, 0xC0,             // FADD ST, ST(0)
+        0xDF, 0x36, 0x30, 0x01, // FBSTP [130]
+        0x9B,                   // WAIT
+        0xDD, 0x3E, 0x18, 0x01, // FSTSW [118]
+        0xF4,
+    ];
+    run_fpu(true, &code, &mut bus);
+
+    assert_eq!(read_f64(&bus, 0x110), 2.5f64.sqrt());
+    assert_eq!(bus.read_16(DATA, 0x122), 49);
+    assert_eq!(bus.read_16(DATA, 0x124), 0);
+
+    // 2*PI redondeado a 6 en BCD
+    assert_eq!(bus.read_8(DATA, 0x130), 0x06);
+    assert_eq!(bus.read_8(DATA, 0x139), 0x00);
+
+    // Pila vacia (TOP = 0), solo queda la excepcion de precision
+    assert_eq!(bus.read_16(DATA, 0x118), 0x0020);
+}
+
+pub fn test_fpu_exceptions() {
+    let mut bus = Bus::new();
+    // Division por cero sin enmascarar
+    bus.write_16(DATA, 0x100, 0x037B);
+
+    let code = [
+        0xDB, 0xE3,             // FINIT
+        0xD9, 0x2E, 0x00, 0x01, // FLDCW [100]
+        0xD9, 0xD0,             // FNOP
+        0xD9, 0xE8,             // FLD1
+        0xD9, 0xEE,             // FLDZ
+        0xDE, 0xF9,             // FDIVP ST(1), ST
+        0xDD, 0x3E, 0x02, 0x01, // FSTSW [102]
+        0xDB, 0xE2,             // FCLEX
+        0xDD, 0x3E, 0x04, 0x01, // FSTSW [104]
+        0xF4,
+    ];
+    let mut cpu = run_fpu(true, &code, &mut bus);
+
+    // IR y ZE activos, la interrupcion va a la NMI y el resultado no se escribe
+    assert!(cpu.nmi);
+    assert_eq!(bus.read_16(DATA, 0x102) & 0x00FF, 0x0084);
+    assert_eq!(bus.read_16(DATA, 0x104) & 0x00FF, 0x0000);
+    let fpu = cpu.fpu.as_ref().unwrap();
+    assert_eq!(fpu.top(), 6);
+    assert_eq!(fpu.st(1).to_f64(), 1.0);
+
+    // Con la NMI enmascarada queda pendiente hasta que se habilita
+    cpu.interrupts_taken.clear();
+    cpu.nmi_out(0x80);
+    cpu.handle_interrupts(&mut bus);
+    assert_eq!(cpu.interrupts_taken, [2]);
+    assert!(!cpu.nmi);
+
+    // Con FDISI no se pide la interrupcion
+    let mut code = code;
+    code[6..8].copy_from_slice(&[0xDB, 0xE1]);
+    let cpu = run_fpu(true, &code, &mut bus);
+    assert!(!cpu.nmi);
+
+    // Desbordamiento de la pila enmascarado: el noveno FLD1 deja indefinido
+    let mut code = vec![0xDB, 0xE3];
+    for _ in 0..9 {
+        code.extend_from_slice(&[0xD9, 0xE8]);
+    }
+    code.extend_from_slice(&[0xDD, 0x3E, 0x06, 0x01, 0xF4]);
+    let cpu = run_fpu(true, &code, &mut bus);
+    assert!(!cpu.nmi);
+    assert_eq!(bus.read_16(DATA, 0x106) & 0x0001, 0x0001);
+    assert!(cpu.fpu.unwrap().st(0).is_nan());
+}
+
+pub fn test_fpu_detection() {
+    // Deteccion tipica: FNINIT y FNSTSW, sin 8087 la memoria no cambia
+    let code = [
+        0xDB, 0xE3,             // FNINIT
+        0xDD, 0x3E, 0x00, 0x01, // FNSTSW [100]
+        0xF4,
+    ];
+
+    let mut bus = Bus::new();
+    bus.write_16(DATA, 0x100, 0xFFFF);
+    let cpu = run_fpu(false, &code, &mut bus);
+    assert_eq!(bus.read_16(DATA, 0x100), 0xFFFF);
+    assert_eq!(cpu.ip, code.len() as u16);
+
+    let mut bus = Bus::new();
+    bus.write_16(DATA, 0x100, 0xFFFF);
+    run_fpu(true, &code, &mut bus);
+    assert_eq!(bus.read_16(DATA, 0x100), 0x0000);
+
+    // La BIOS ve el coprocesador en SW1 y lo apunta en la palabra de equipo
+    let mut config = MachineConfig::ibm_5150_1981();
+    config.fpu = true;
+    let mut headless = Headless::with_config(config).unwrap();
+    assert!(headless.run_until_text("Version C1.00", 2000));
+    assert_eq!(headless.sys.bus.read_16(0x40, 0x10) & 0x02, 0x02);
+}
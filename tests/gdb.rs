@@ -0,0 +1,147 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use ibm_5150::{GdbStub, System};
+use ibm_5150::hardware::cpu_8088::biu::BIU;
+
+fn send(stream: &mut TcpStream, data: &[u8]) {
+    let checksum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    stream.write_all(&[b"$", data, format!("#{:02x}", checksum).as_bytes()].concat()).unwrap();
+}
+
+// Se salta los + del stub y contesta con otro
+fn reply(stream: &mut TcpStream) -> String {
+    let mut byte = [0];
+    while byte[0] != b'$' {
+        stream.read_exact(&mut byte).unwrap();
+    }
+    let mut data = Vec::new();
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+    stream.read_exact(&mut [0; 2]).unwrap();
+    stream.write_all(b"+").unwrap();
+    String::from_utf8(data).unwrap()
+}
+
+fn command(stream: &mut TcpStream, data: &str) -> String {
+    send(stream, data.as_bytes());
+    reply(stream)
+}
+
+pub fn test_gdb_session() {
+    let mut sys = System::new();
+    let code = [
+        0xB8, 0x34, 0x12,       // MOV AX, 1234
+        0xA3, 0x00, 0x02,       // MOV [0200], AX
+        0x43,                   // INC BX
+        0xEB, 0xFE,             // JMP $
+    ];
+    for (i, byte) in code.iter().enumerate() {
+        sys.bus.write_8(0x1000, i as u16, *byte);
+    }
+    sys.cpu.cs = 0x1000;
+    sys.cpu.ip = 0;
+    sys.cpu.ds = 0;
+    sys.cpu.ss = 0x2000;
+    sys.cpu.sp = 0x0100;
+
+    // AX CX DX BX SP BP SI DI IP FLAGS CS SS DS ES FS GS, 32 bits little endian
+    let registers: String = [0, 0, 0, 0, 0x0100, 0, 0, 0, 0, sys.cpu.flags.get_flags(), 0x1000, 0x2000, 0, 0, 0, 0]
+        .iter()
+        .map(|reg: &u16| format!("{:02x}{:02x}0000", reg & 0xFF, reg >> 8))
+        .collect();
+
+    let mut stub = GdbStub::bind("127.0.0.1:0").unwrap();
+    // Conectado antes de la primera llamada, para que la maquina no llegue a correr sola
+    let mut stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    let client = std::thread::spawn(move || {
+        let mut replies = Vec::new();
+        for packet in [
+            "qSupported:swbreak+", "?", "g", "P0=78560000", "p0", "m10000,3", "M500,2:abcd", "m500,2",
+            // Watchpoint de escritura: para despues del MOV
+            "Z2,200,2", "c", "p8", "m200,2", "z2,200,2",
+            // Breakpoint en el JMP
+            "Z0,10007,1", "c", "p8", "p3", "z0,10007,1",
+            "s", "p8",
+        ] {
+            replies.push(command(&mut stream, packet));
+        }
+
+        // Corriendo sin fin hasta el Ctrl+C
+        send(&mut stream, b"c");
+        std::thread::sleep(Duration::from_millis(50));
+        stream.write_all(&[0x03]).unwrap();
+        replies.push(reply(&mut stream));
+        // Direcciones que pasan de FFFFF vuelven a 00000
+        for packet in ["mffffffff,2", "Mffffffff,2:1234", "m0,1", "Z2,fffff,ffffffff", "z2,fffff,ffffffff"] {
+            replies.push(command(&mut stream, packet));
+        }
+        replies.push(command(&mut stream, "D"));
+        replies
+    });
+
+    while !client.is_finished() {
+        stub.update(&mut sys).unwrap();
+    }
+    let replies = client.join().unwrap();
+
+    assert_eq!(replies[..8], [
+        "PacketSize=1000", "S05",
+        &registers,
+        "OK", "78560000", "b83412", "OK", "abcd",
+    ]);
+    assert_eq!(replies[8..13], ["OK", "T05watch:200;", "06000000", "3412", "OK"]);
+    assert_eq!(replies[13..18], ["OK", "S05", "07000000", "01000000", "OK"]);
+    assert_eq!(replies[18..], ["S05", "07000000", "S02", "0000", "OK", "34", "OK", "OK", "OK"]);
+
+    // Al desconectar se quitan los breakpoints y la maquina sigue sola
+    assert!(!stub.attached());
+    assert!(sys.debugger.breakpoints().is_empty());
+    let ip = sys.cpu.ip;
+    stub.update(&mut sys).unwrap();
+    assert_eq!((sys.cpu.ax.get_x(), sys.cpu.ip), (0x1234, ip));
+}
+
+// Manda los paquetes y deja la conexion abierta, con la maquina parada
+fn gdb_session(sys: &mut System, packets: &'static [&'static str]) -> Vec<String> {
+    let mut stub = GdbStub::bind("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+
+    let client = std::thread::spawn(move || {
+        let replies: Vec<String> = packets.iter().map(|packet| command(&mut stream, packet)).collect();
+        (replies, stream)
+    });
+    while !client.is_finished() {
+        stub.update(sys).unwrap();
+    }
+    client.join().unwrap().0
+}
+
+pub fn test_gdb_timing() {
+    // Leer memoria desde gdb no cambia los ciclos de la siguiente instruccion
+    let mut timed = Vec::new();
+    for packets in [&["?", "s"][..], &["?", "m10000,100", "M20000,2:abcd", "s"][..]] {
+        let mut sys = System::new();
+        sys.cpu.biu = Some(BIU::new());
+        for (i, byte) in [0xA1, 0x00, 0x02, 0x90].iter().enumerate() {
+            sys.bus.write_8(0x1000, i as u16, *byte);
+        }
+        sys.cpu.cs = 0x1000;
+        sys.cpu.ip = 0;
+
+        let replies = gdb_session(&mut sys, packets);
+        assert_eq!(replies.last().unwrap(), "S05");
+        timed.push((sys.cpu.ip, sys.cpu.cycles, sys.cpu.biu.as_ref().unwrap().bus_cycles));
+    }
+    assert_eq!(timed[0].0, 3);
+    assert_eq!(timed[0], timed[1]);
+}
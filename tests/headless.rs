@@ -0,0 +1,19 @@
+use ibm_5150::{Headless, MachineConfig};
+
+fn boot() -> Headless {
+    Headless::with_config(MachineConfig::ibm_5150_1982()).unwrap()
+}
+
+pub fn test_boot_basic() {
+    let mut headless = boot();
+
+    assert!(headless.run_until_text("Bytes free", 2000));
+    assert!(headless.screen_contains("The IBM Personal Computer Basic"));
+
+    // PRINT 44-2
+    for scancode in [0x19, 0x13, 0x17, 0x31, 0x14, 0x39, 0x05, 0x05, 0x0C, 0x03, 0x1C] {
+        headless.press_key(scancode);
+    }
+
+    assert!(headless.run_until_text(" 42", 50));
+}
@@ -0,0 +1,101 @@
+use ibm_5150::{System, Headless, MachineConfig, InputLog, InputError, Rewind, StateError};
+
+use crate::common::*;
+
+const PROGRAM: [u8; 24] = [
+    0xFA,                   // CLI
+    0xB0, 0x48,             // MOV AL, 48
+    0xE6, 0x61,             // OUT 61, AL
+    0xE4, 0x60,             // IN AL, 60
+    0x84, 0xC0,             // TEST AL, AL
+    0x74, 0xFA,             // JZ 0005
+    0x88, 0x07,             // MOV [BX], AL
+    0x43,                   // INC BX
+    0xB0, 0xC8,             // MOV AL, C8
+    0xE6, 0x61,             // OUT 61, AL
+    0xB0, 0x48,             // MOV AL, 48
+    0xE6, 0x61,             // OUT 61, AL
+    0xEB, 0xED,             // JMP 0005
+];
+
+fn machine() -> System {
+    let mut sys = load_system(&PROGRAM);
+    sys.cpu.ds = 0x3000;
+    sys
+}
+
+pub fn test_input_replay() {
+    // Se empieza a grabar con la maquina ya en marcha, asi que va el estado
+    let mut sys = machine();
+    step_system(&mut sys, 7);
+    sys.start_recording();
+    for (steps, key_code) in [(3, 0x1E), (37, 0x9E), (1, 0x30), (259, 0xB0)] {
+        step_system(&mut sys, steps);
+        sys.key_input(key_code);
+    }
+    step_system(&mut sys, 3000);
+    let recording = sys.recording.take().unwrap();
+    assert_eq!(recording.events.len(), 4);
+    assert!(recording.state.is_some());
+    let end = sys.snapshot();
+    let cycles = sys.total_cycles;
+
+    let data = recording.save();
+    let log = InputLog::load(&data).unwrap();
+    assert_eq!(log.events, recording.events);
+    assert_eq!(log.config.bios.path, recording.config.bios.path);
+
+    // En otra maquina sale igual byte a byte, tambien rebobinando por el camino
+    let mut other = System::new();
+    other.start_replay(log).unwrap();
+    other.rewind = Some(Rewind::new(300, u64::MAX));
+    step_system(&mut other, 150);
+    other.rewind_to(20).unwrap();
+    while other.total_cycles < cycles {
+        step_system(&mut other, 1);
+    }
+    assert!(other.replay.as_ref().unwrap().finished());
+    assert_eq!(other.total_cycles, cycles);
+    assert!(other.snapshot() == end);
+
+    // Y el programa ha leido las teclas
+    let read: Vec<u8> = (0..other.cpu.bx.get_x()).map(|i| other.bus.read_8(0x3000, i)).collect();
+    assert_eq!(read, [0x1E, 0x9E, 0x30, 0xB0]);
+
+    assert!(matches!(InputLog::load(b"IBM5150T\x01\x00"), Err(InputError::BadMagic)));
+    assert!(matches!(InputLog::load(&data[..data.len() - 1]), Err(InputError::Truncated)));
+
+    // Un estado de partida que no es un snapshot
+    let mut log = InputLog::load(&data).unwrap();
+    log.state = Some(b"IBM5150I".to_vec());
+    assert!(matches!(System::new().start_replay(log), Err(InputError::State(StateError::BadMagic))));
+
+    // Mas disqueteras de las que tiene el FDC
+    let mut log = InputLog::load(&data).unwrap();
+    log.config.floppy_drives = 5;
+    assert!(matches!(InputLog::load(&log.save()), Err(InputError::Invalid("floppy drives"))));
+}
+
+pub fn test_input_boot() {
+    // Desde el arranque no hay estado: la repeticion hace el reset y carga las ROMs
+    let mut pc = Headless::with_config(MachineConfig::ibm_5150_1982()).unwrap();
+    pc.sys.start_recording();
+    pc.run_frames(20);
+    pc.sys.key_input(0x1C);
+    pc.run_cycles(1234);
+    pc.sys.key_input(0x9C);
+    pc.run_frames(10);
+    let log = pc.sys.recording.take().unwrap();
+    assert!(log.state.is_none());
+    assert_eq!(log.events.len(), 2);
+
+    let mut other = Headless::with_config(MachineConfig::ibm_5150_1982()).unwrap();
+    other.run_frames(3);
+    other.sys.start_replay(log).unwrap();
+    assert_eq!(other.sys.total_cycles, 0);
+    while other.sys.total_cycles < pc.sys.total_cycles {
+        let mut cycles = 0;
+        other.sys.step(&mut cycles);
+    }
+    assert!(other.sys.snapshot() == pc.sys.snapshot());
+}
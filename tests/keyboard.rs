@@ -0,0 +1,113 @@
+use ibm_5150::{Headless, MachineConfig};
+use ibm_5150::hardware::peripheral::Peripheral;
+use ibm_5150::hardware::peripheral::pic_8259::PIC8259;
+use ibm_5150::hardware::peripheral::ppi_8255::{ascii_key, PPI8255};
+
+// Ciclos de CPU en 1 ms
+const MS: u32 = 4_773;
+
+struct Kbd {
+    ppi: PPI8255,
+    pic: PIC8259,
+}
+
+impl Kbd {
+    fn new() -> Self {
+        let mut kbd = Kbd { ppi: PPI8255::new(), pic: PIC8259::new() };
+        // Reloj del teclado activo
+        kbd.ppi.port_out(0x40, 0x61);
+        kbd
+    }
+
+    fn run(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.ppi.update(&mut self.pic, MS);
+        }
+    }
+
+    // Lee el scancode si hay IRQ1 y lo reconoce con PB7 como hace la BIOS
+    fn read(&mut self) -> Option<u8> {
+        if self.pic.irr & 0x02 == 0 {
+            return None;
+        }
+
+        let key_code = self.ppi.port_in(0x60) as u8;
+        self.ppi.port_out(0xC0, 0x61);
+        self.ppi.update(&mut self.pic, 1);
+        self.ppi.port_out(0x40, 0x61);
+        Some(key_code)
+    }
+
+    fn read_all(&mut self, ms: u32) -> Vec<u8> {
+        let mut codes = Vec::new();
+        for _ in 0..ms {
+            self.run(1);
+            codes.extend(self.read());
+        }
+        codes
+    }
+}
+
+pub fn test_ascii_keys() {
+    assert_eq!(ascii_key('a'), Some((0x1E, false)));
+    assert_eq!(ascii_key('A'), Some((0x1E, true)));
+    assert_eq!(ascii_key('0'), Some((0x0B, false)));
+    assert_eq!(ascii_key(')'), Some((0x0B, true)));
+    assert_eq!(ascii_key('"'), Some((0x28, true)));
+    assert_eq!(ascii_key('\n'), Some((0x1C, false)));
+    assert_eq!(ascii_key('ñ'), None);
+}
+
+pub fn test_key_buffer() {
+    let mut kbd = Kbd::new();
+
+    for key_code in [0x1E, 0x9E, 0x30, 0xB0] {
+        kbd.ppi.key_input(key_code);
+    }
+
+    // El primer scancode se queda en el puerto A hasta que se reconoce
+    kbd.run(1);
+    assert_eq!(kbd.ppi.port_in(0x60), 0x1E);
+    kbd.pic.irr = 0;
+    kbd.run(10);
+    assert_eq!(kbd.pic.irr & 0x02, 0);
+    assert_eq!(kbd.ppi.port_in(0x60), 0x1E);
+    assert_eq!(kbd.ppi.pending_keys(), 3);
+
+    kbd.pic.irr = 0x02;
+    assert_eq!(kbd.read_all(10), [0x1E, 0x9E, 0x30, 0xB0]);
+    assert_eq!(kbd.ppi.pending_keys(), 0);
+}
+
+pub fn test_typematic() {
+    let mut kbd = Kbd::new();
+    kbd.ppi.set_typematic(100, 20);
+
+    // 100 ms de retardo y luego una repeticion cada 50 ms
+    kbd.ppi.key_input(0x1E);
+    assert_eq!(kbd.read_all(299), [0x1E; 5]);
+
+    kbd.ppi.key_input(0x9E);
+    assert_eq!(kbd.read_all(300), [0x9E]);
+
+    // Sin repeticion
+    kbd.ppi.set_typematic(100, 0);
+    kbd.ppi.key_input(0x1E);
+    assert_eq!(kbd.read_all(300), [0x1E]);
+
+    // Un retardo enorme no desborda: la tecla no llega a repetirse
+    kbd.ppi.set_typematic(u32::MAX, 20);
+    kbd.ppi.key_input(0x30);
+    assert_eq!(kbd.read_all(300), [0x30]);
+}
+
+pub fn test_type_text() {
+    let mut headless = Headless::with_config(MachineConfig::ibm_5150_1981()).unwrap();
+    assert!(headless.run_until_text("Bytes free", 2000));
+
+    assert_eq!(headless.type_text("print \"6*7=\";6*7\n"), Ok(()));
+    assert!(headless.run_until_text("6*7= 42", 50));
+
+    // No se teclea nada si hay caracteres sin tecla
+    assert_eq!(headless.type_text("PRINT \"ñ\""), Err('ñ'));
+}
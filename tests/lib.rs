@@ -1,4 +1,28 @@
+mod common;
 mod mul;
+mod headless;
+mod config;
+mod state;
+mod floppy;
+mod dma;
+mod cga;
+mod timer;
+mod speaker;
+mod keyboard;
+mod pic;
+mod fpu;
+mod timing;
+mod opcodes;
+mod exceptions;
+mod debugger;
+mod monitor;
+mod dissasemble;
+mod gdb;
+mod trace;
+mod trace_diff;
+mod single_step;
+mod rewind;
+mod input;
 
 #[cfg(test)]
 mod test {
@@ -6,6 +30,29 @@ mod test {
     use std::time::{Instant, Duration};
     
     use crate::mul::*;
+    use crate::headless::*;
+    use crate::config::*;
+    use crate::state::*;
+    use crate::floppy::*;
+    use crate::dma::*;
+    use crate::cga::*;
+    use crate::timer::*;
+    use crate::speaker::*;
+    use crate::keyboard::*;
+    use crate::pic::*;
+    use crate::fpu::*;
+    use crate::timing::*;
+    use crate::opcodes::*;
+    use crate::exceptions::*;
+    use crate::debugger::*;
+    use crate::monitor::*;
+    use crate::dissasemble::*;
+    use crate::gdb::*;
+    use crate::trace::*;
+    use crate::trace_diff::*;
+    use crate::single_step::*;
+    use crate::rewind::*;
+    use crate::input::*;
     
     // fn write_instr(sys: &mut IbmPc, op: u8) {
     //     sys.sys.bus.memory[0xFFFF0] = op;
@@ -54,4 +101,162 @@ mod test {
         test_imul2();
         test_imul3();
     }
+
+    #[test]
+    fn test_headless() {
+        test_boot_basic();
+    }
+
+    #[test]
+    fn test_config() {
+        test_boot_1981();
+        test_missing_rom();
+        test_rom_out_of_range();
+        test_config_args();
+    }
+
+    #[test]
+    fn test_state() {
+        test_snapshot_roundtrip();
+        test_snapshot_mid_rep();
+        test_snapshot_errors();
+    }
+
+    #[test]
+    fn test_floppy() {
+        test_boot_floppy();
+        test_fdc_seek_limit();
+        test_fdc_eject();
+        test_fdc_state();
+        test_floppy_restore_path();
+        test_disk_errors();
+    }
+
+    #[test]
+    fn test_dma() {
+        test_dma_single();
+        test_dma_block_demand();
+        test_dma_autoinit();
+    }
+
+    #[test]
+    fn test_cga() {
+        test_boot_cga();
+        test_cga_graphics();
+    }
+
+    #[test]
+    fn test_timer() {
+        test_pit_mode0_bcd();
+        test_pit_gate_modes();
+        test_pit_mode4_readback();
+    }
+
+    #[test]
+    fn test_speaker() {
+        test_post_beep();
+        test_wav_output();
+    }
+
+    #[test]
+    fn test_keyboard() {
+        test_ascii_keys();
+        test_key_buffer();
+        test_typematic();
+        test_type_text();
+    }
+
+    #[test]
+    fn test_pic() {
+        test_pic_priority_eoi();
+        test_pic_rotation_modes();
+        test_pic_state();
+    }
+
+    #[test]
+    fn test_fpu() {
+        test_fpu_arithmetic();
+        test_fpu_exceptions();
+        test_fpu_detection();
+    }
+
+    #[test]
+    fn test_timing() {
+        test_prefetch_queue();
+        test_accurate_boot();
+    }
+
+    #[test]
+    fn test_opcodes() {
+        test_undocumented_opcodes();
+        test_opcode_fixes();
+    }
+
+    #[test]
+    fn test_exceptions() {
+        test_divide_error();
+        test_single_step();
+        test_interrupt_priority();
+        test_rep_interrupt();
+    }
+
+    #[test]
+    fn test_debugger() {
+        test_breakpoints();
+        test_stepping();
+        test_debug_commands();
+    }
+
+    #[test]
+    fn test_monitor() {
+        test_monitor_views();
+    }
+
+    #[test]
+    fn test_dissasembler() {
+        test_dissasemble_text();
+        test_dissasemble_listing();
+    }
+
+    #[test]
+    fn test_gdb() {
+        test_gdb_session();
+        test_gdb_timing();
+    }
+
+    #[test]
+    fn test_trace() {
+        test_trace_records();
+        test_trace_filters();
+    }
+
+    #[test]
+    fn test_trace_diff() {
+        test_trace_diff_match();
+        test_trace_diff_divergence();
+    }
+
+    #[test]
+    fn test_single_step_json() {
+        test_single_step_harness();
+    }
+
+    #[test]
+    #[ignore]
+    fn test_single_step_json_all() {
+        test_single_step_corpus();
+    }
+
+    #[test]
+    fn test_rewind() {
+        test_rewind_seek();
+        test_rewind_history();
+        test_rewind_floppy();
+    }
+
+    #[test]
+    fn test_input() {
+        test_input_replay();
+        test_input_boot();
+    }
 }
\ No newline at end of file
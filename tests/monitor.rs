@@ -0,0 +1,60 @@
+use ibm_5150::System;
+use ibm_5150::util::monitor::Monitor;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+fn key(monitor: &mut Monitor, code: KeyCode) {
+    monitor.key(KeyEvent::new(code, KeyModifiers::NONE));
+}
+
+pub fn test_monitor_views() {
+    let mut sys = System::new();
+    let code = [
+        0xB8, 0x34, 0x12,       // MOV AX, 1234
+        0x50,                   // PUSH AX
+        0x90,                   // NOP
+        0xF4,
+    ];
+    for (i, byte) in code.iter().enumerate() {
+        sys.bus.write_8(0x1000, i as u16, *byte);
+    }
+    sys.cpu.cs = 0x1000;
+    sys.cpu.ip = 0;
+    sys.cpu.ss = 0x2000;
+    sys.cpu.sp = 0x0100;
+
+    let mut monitor = Monitor::new(sys);
+    for c in "b 1000:0004".chars() {
+        key(&mut monitor, KeyCode::Char(c));
+    }
+    key(&mut monitor, KeyCode::Enter);
+    monitor.command("g");
+    while monitor.go() {}
+    assert_eq!(monitor.output().last().unwrap(), "breakpoint 1 at 1000:0004");
+
+    let screen = monitor.screen();
+    assert!(screen[0].starts_with("AX=1234"));
+    assert!(screen.iter().any(|line| line.starts_with(">* 10004  90")));
+    assert!(screen.iter().any(|line| line.ends_with("2000:00FE  1234")));
+    assert!(screen.iter().any(|line| line.contains("PIC  IRR=00")));
+
+    // Historial con las flechas
+    key(&mut monitor, KeyCode::Up);
+    assert_eq!(monitor.screen().last().unwrap(), "> g");
+    key(&mut monitor, KeyCode::Up);
+    assert_eq!(monitor.screen().last().unwrap(), "> b 1000:0004");
+    key(&mut monitor, KeyCode::Down);
+    key(&mut monitor, KeyCode::Down);
+    assert_eq!(monitor.screen().last().unwrap(), "> ");
+
+    // Vista de memoria
+    monitor.command("m 2000:00F0");
+    key(&mut monitor, KeyCode::PageDown);
+    assert!(monitor.screen().iter().any(|line| line.starts_with("20170")));
+
+    let mut out = Vec::new();
+    monitor.render(&mut out).unwrap();
+    assert!(String::from_utf8_lossy(&out).contains("breakpoint 1 at 1000:0004"));
+
+    monitor.command("q");
+    assert!(!monitor.running);
+}
@@ -0,0 +1,66 @@
+use ibm_5150::hardware::bus::Bus;
+
+use crate::common::*;
+
+pub fn test_undocumented_opcodes() {
+    let mut bus = Bus::new();
+    bus.write_16(DATA, 0x100, 0x1234);
+
+    let code = [
+        0xF9,                   // STC
+        0xD6,                   // SALC
+        0xA2, 0x00, 0x02,       // MOV [200], AL
+        0xF8,                   // CLC
+        0xD6,                   // SALC
+        0xA2, 0x01, 0x02,       // MOV [201], AL
+        0x73, 0x01,             // JNC +1 (con el alias 63 abajo)
+        0xF4,
+        0x63, 0x01,             // JNC +1
+        0xF4,
+        0xF0, 0xB3, 0x55,       // LOCK MOV BL, 55
+        0xFF, 0x36, 0x00, 0x01, // PUSH [100]
+        0xFF, 0x3E, 0x00, 0x01, // PUSH [100] con /7
+        0x5E,                   // POP SI
+        0xD0, 0x36, 0x02, 0x01, // SETMO byte [102], 1
+        0x8D, 0x40, 0x10,       // LEA AX, [BX+SI+10]
+        0xC0,                   // RET con alias C0 (vuelve a 1234)
+    ];
+    // El RET salta a 1234, alli hay un HLT
+    bus.write_8(CODE, 0x1234, 0xF4);
+    let cpu = run(&code, &mut bus);
+    assert_eq!(cpu.ip, 0x1235);
+
+    assert_eq!(bus.read_8(DATA, 0x200), 0xFF);
+    assert_eq!(bus.read_8(DATA, 0x201), 0x00);
+    assert_eq!(cpu.bx.low, 0x55);
+    assert_eq!(cpu.si, 0x1234);
+    assert_eq!(bus.read_8(DATA, 0x102), 0xFF);
+    assert_eq!(cpu.ax.get_x(), 0x0055 + 0x1234 + 0x10);
+}
+
+pub fn test_opcode_fixes() {
+    let mut bus = Bus::new();
+    let code = [
+        0xB8, 0x0F, 0x00,       // MOV AX, 000F
+        0xA8, 0x01,             // TEST AL, 1 (no cambia AL)
+        0xA2, 0x00, 0x01,       // MOV [100], AL
+        0xB8, 0x05, 0x03,       // MOV AX, 0305
+        0xD5, 0x0A,             // AAD
+        0xA2, 0x01, 0x01,       // MOV [101], AL
+        0xFE, 0x06, 0x02, 0x01, // INC byte [102]
+        0xF4,
+    ];
+    bus.write_8(DATA, 0x102, 0xFF);
+    let cpu = run(&code, &mut bus);
+    assert_eq!(bus.read_8(DATA, 0x100), 0x0F);
+    assert_eq!(bus.read_8(DATA, 0x101), 35);
+    assert_eq!(bus.read_8(DATA, 0x103), 0x00);
+    assert_eq!(bus.read_8(DATA, 0x102), 0x00);
+    assert!(cpu.flags.z);
+
+    // AAM 0 pide la interrupcion de division en vez de dividir
+    let mut cpu = load_cpu(&[0xD4, 0x00, 0xF4], &mut bus);
+    cpu.fetch_decode_execute(&mut bus);
+    assert!(cpu.sw_int);
+    assert_eq!(cpu.sw_int_type, 0);
+}
@@ -0,0 +1,117 @@
+use ibm_5150::hardware::peripheral::Peripheral;
+use ibm_5150::hardware::peripheral::pic_8259::{PIC8259, IRQs};
+use ibm_5150::hardware::state::{Snapshot, StateWriter, StateReader, StateError};
+
+// Inicializacion de la BIOS: flanco, un solo 8259, vectores en 0x08, ICW4 para 8086
+fn bios_pic(icw4: u16) -> PIC8259 {
+    let mut pic = PIC8259::new();
+    pic.port_out(0x13, 0x20);
+    pic.port_out(0x08, 0x21);
+    pic.port_out(icw4, 0x21);
+    pic.port_out(0x00, 0x21);
+    pic
+}
+
+fn read_isr(pic: &mut PIC8259) -> u8 {
+    pic.port_out(0x0B, 0x20);
+    let isr = pic.port_in(0x20) as u8;
+    pic.port_out(0x0A, 0x20);
+    isr
+}
+
+pub fn test_pic_priority_eoi() {
+    let mut pic = bios_pic(0x09);
+
+    pic.irq(IRQs::Irq1);
+    assert!(pic.has_int());
+    assert_eq!(pic.get_next(), 0x09);
+
+    // IRQ3 tiene menos prioridad que IRQ1 en servicio, IRQ0 tiene mas
+    pic.irq(IRQs::Irq3);
+    assert!(!pic.has_int());
+    pic.irq(IRQs::Irq0);
+    assert_eq!(pic.get_next(), 0x08);
+    assert_eq!(read_isr(&mut pic), 0x03);
+    assert_eq!(pic.port_in(0x20), 0x08);
+
+    // EOI no especifico: se quita la de mas prioridad (IRQ0)
+    pic.port_out(0x20, 0x20);
+    assert_eq!(read_isr(&mut pic), 0x02);
+    assert!(!pic.has_int());
+
+    // EOI especifico de IRQ1
+    pic.port_out(0x61, 0x20);
+    assert_eq!(read_isr(&mut pic), 0x00);
+    assert_eq!(pic.get_next(), 0x0B);
+    pic.port_out(0x63, 0x20);
+
+    // Las IRQ enmascaradas no se atienden. Sin IRQ el vector es el de la 7
+    pic.port_out(0xFE, 0x21);
+    pic.irq(IRQs::Irq2);
+    assert!(!pic.has_int());
+    assert_eq!(pic.get_next(), 0x0F);
+    assert_eq!(read_isr(&mut pic), 0x00);
+    assert_eq!(pic.port_in(0x21), 0xFE);
+}
+
+pub fn test_pic_rotation_modes() {
+    let mut pic = bios_pic(0x09);
+
+    // Prioridad fijada: IRQ4 la mas baja, IRQ5 la mas alta
+    pic.port_out(0xC4, 0x20);
+    pic.irq(IRQs::Irq0);
+    pic.irq(IRQs::Irq6);
+    assert_eq!(pic.get_next(), 0x0E);
+
+    // Rotar con EOI no especifico: IRQ6 pasa a ser la mas baja
+    pic.port_out(0xA0, 0x20);
+    pic.irq(IRQs::Irq6);
+    assert_eq!(pic.get_next(), 0x08);
+    pic.port_out(0x20, 0x20);
+    assert_eq!(pic.get_next(), 0x0E);
+    pic.port_out(0x66, 0x20);
+
+    // Special mask mode: IRQ de menos prioridad con otra en servicio
+    pic.port_out(0xE7, 0x20);
+    pic.irq(IRQs::Irq0);
+    assert_eq!(pic.get_next(), 0x08);
+    pic.irq(IRQs::Irq1);
+    assert!(!pic.has_int());
+    pic.port_out(0x68, 0x20);
+    assert_eq!(pic.get_next(), 0x09);
+    pic.port_out(0x48, 0x20);
+    pic.port_out(0x20, 0x20);
+    pic.port_out(0x20, 0x20);
+    assert_eq!(read_isr(&mut pic), 0x00);
+
+    // Poll
+    pic.irq(IRQs::Irq5);
+    pic.port_out(0x0C, 0x20);
+    assert_eq!(pic.port_in(0x20), 0x85);
+    assert_eq!(read_isr(&mut pic), 0x20);
+    pic.port_out(0x0C, 0x20);
+    assert_eq!(pic.port_in(0x20), 0x00);
+
+    // Auto-EOI: no queda nada en servicio
+    let mut pic = bios_pic(0x0B);
+    pic.irq(IRQs::Irq1);
+    pic.irq(IRQs::Irq2);
+    assert_eq!(pic.get_next(), 0x09);
+    assert_eq!(read_isr(&mut pic), 0x00);
+    assert_eq!(pic.get_next(), 0x0A);
+}
+
+pub fn test_pic_state() {
+    let pic = bios_pic(0x09);
+    let mut w = StateWriter::new();
+    pic.save_state(&mut w);
+    let mut data = w.into_inner();
+
+    let mut other = PIC8259::new();
+    other.load_state(&mut StateReader::new(&data)).unwrap();
+
+    // Un paso de inicializacion que no existe, despues de ISR, IMR, IRR y las ICW con su longitud
+    assert_eq!(data[11], 0);
+    data[11] = 5;
+    assert!(matches!(other.load_state(&mut StateReader::new(&data)), Err(StateError::Invalid(_))));
+}
\ No newline at end of file
@@ -0,0 +1,103 @@
+use ibm_5150::{System, Rewind, RewindError, FloppyDisk};
+
+use crate::common::*;
+
+const PROGRAM: [u8; 9] = [
+    0xE4, 0x40,             // IN AL, 40
+    0x88, 0x07,             // MOV [BX], AL
+    0x43,                   // INC BX
+    0x00, 0x04,             // ADD [SI], AL
+    0xEB, 0xF7,             // JMP 0000
+];
+
+fn machine(rewind: Rewind) -> System {
+    let mut sys = load_system(&PROGRAM);
+    sys.cpu.ds = 0x3000;
+    sys.cpu.si = 0x8000;
+    sys.rewind = Some(rewind);
+    sys
+}
+
+pub fn test_rewind_seek() {
+    // Un punto cada 200 ciclos, sin limite de historia
+    let mut sys = machine(Rewind::new(200, u64::MAX));
+
+    // El estado completo antes de cada instruccion
+    let mut states = Vec::new();
+    for i in 0..600 {
+        if i == 250 {
+            sys.key_input(0x1E);
+        }
+        states.push(sys.snapshot());
+        step_system(&mut sys, 1);
+    }
+    assert_eq!(sys.rewind.as_ref().unwrap().instructions, 600);
+    assert!(sys.rewind.as_ref().unwrap().checkpoints() > 10);
+
+    for target in [599, 420, 251, 250, 249, 100, 37, 0] {
+        sys.rewind_to(target).unwrap();
+        assert_eq!(sys.rewind.as_ref().unwrap().instructions, target);
+        assert!(sys.snapshot() == states[target as usize], "instruction {}", target);
+    }
+
+    // Lo que venia despues se ha perdido
+    match sys.rewind_to(1) {
+        Err(RewindError::OutOfRange(1, 0, 0)) => (),
+        other => panic!("{:?}", other),
+    }
+
+    // Y volviendo a ejecutar sale lo mismo, con la tecla en su sitio
+    for (i, state) in states.iter().enumerate() {
+        if i == 250 {
+            sys.key_input(0x1E);
+        }
+        assert!(sys.snapshot() == *state, "instruction {}", i);
+        step_system(&mut sys, 1);
+    }
+}
+
+pub fn test_rewind_history() {
+    let mut sys = machine(Rewind::new(100, 1000));
+    step_system(&mut sys, 2000);
+    let rewind = sys.rewind.as_ref().unwrap();
+    assert!(rewind.oldest() > 0);
+    assert!(rewind.checkpoints() <= 12);
+    assert!(matches!(sys.rewind_to(0), Err(RewindError::OutOfRange(0, _, 2000))));
+
+    // Paso atras desde el depurador
+    let before = sys.snapshot();
+    let ip = sys.cpu.ip;
+    step_system(&mut sys, 3);
+    assert_eq!(sys.debug_command("bs 3").unwrap(), format!("back at 1000:{:04X}", ip));
+    assert!(sys.snapshot() == before);
+
+    // Volver unos ciclos atras va a un punto de control
+    let cycles = sys.total_cycles;
+    let back = sys.rewind_cycles(500).unwrap();
+    assert!(back >= 500);
+    assert_eq!(sys.total_cycles, cycles - back);
+
+    // Sin rebobinado no se puede, y el reset empieza la historia
+    sys.rst();
+    assert_eq!(sys.rewind.as_ref().unwrap().instructions, 0);
+    assert!(sys.bus.journal.writes.is_empty());
+    sys.rewind = None;
+    assert!(matches!(sys.step_back(), Err(RewindError::Disabled)));
+    assert_eq!(sys.debug_command("bs").unwrap_err().to_string(), "rewind is not enabled");
+}
+
+pub fn test_rewind_floppy() {
+    // Los puntos de control no llevan la imagen del disquete
+    let mut sys = machine(Rewind::new(200, u64::MAX));
+    let size = sys.save_devices().len();
+    sys.bus.fdc.drives[0] = Some(FloppyDisk::from_bytes(vec![0xF6; 368_640]).unwrap());
+    assert_eq!(sys.save_devices().len(), size);
+
+    // Y lo escrito en el disquete se queda al rebobinar
+    step_system(&mut sys, 300);
+    sys.bus.fdc.drives[0].as_mut().unwrap().data[0] = 0x12;
+    step_system(&mut sys, 300);
+    sys.rewind_to(100).unwrap();
+    assert_eq!(sys.rewind.as_ref().unwrap().instructions, 100);
+    assert_eq!(sys.bus.fdc.drives[0].as_ref().unwrap().data[0], 0x12);
+}
@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::{json, Value};
+
+use ibm_5150::hardware::{bus::Bus, cpu_8088::CPU};
+use ibm_5150::util::trace::BusAccess;
+
+// Pruebas de un solo paso de la comunidad (SingleStepTests/8088).
+// Cada fichero es un opcode (00.json, o 80.4.json para un grupo, tambien comprimidos .json.gz) con una
+// lista de pruebas: el estado inicial (registros y RAM), el final (solo los registros que cambian y la
+// RAM) y los ciclos de bus, que no se comparan porque la CPU no va ciclo a ciclo. El 8088.json o
+// metadata.json de la carpeta trae las mascaras de los flags indefinidos de cada opcode.
+// SINGLE_STEP_TESTS=carpeta cargo test --release --test lib test_single_step_json_all -- --ignored --nocapture
+
+const REGS: [&str; 14] = ["ax", "bx", "cx", "dx", "sp", "bp", "si", "di", "cs", "ds", "es", "ss", "ip", "flags"];
+const FLAGS: usize = 13;
+// Los bits reservados de FLAGS no los guarda la CPU
+const FLAGS_MASK: u16 = 0x0FD5;
+
+fn read_json(path: &Path) -> Value {
+    let mut data = Vec::new();
+    let file = std::fs::File::open(path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        GzDecoder::new(file).read_to_end(&mut data)
+    } else {
+        std::io::BufReader::new(file).read_to_end(&mut data)
+    }.unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    serde_json::from_slice(&data).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+// "00" o "80.4" a partir de 00.json.gz
+fn opcode_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let name = name.strip_suffix(".json.gz").or_else(|| name.strip_suffix(".json"))?;
+    let (opcode, _) = name.split_once('.').unwrap_or((name, ""));
+    (opcode.len() == 2 && u8::from_str_radix(opcode, 16).is_ok()).then(|| name.to_uppercase())
+}
+
+// Mascara de los flags definidos de cada opcode, y de cada /reg en los grupos
+fn flags_masks(dir: &Path) -> HashMap<String, u16> {
+    let mut masks = HashMap::new();
+    let Some(path) = ["8088.json", "metadata.json"].iter().map(|name| dir.join(name)).find(|path| path.exists()) else {
+        return masks;
+    };
+    let metadata = read_json(&path);
+    let Some(opcodes) = metadata["opcodes"].as_object() else {
+        return masks;
+    };
+
+    for (opcode, info) in opcodes {
+        let opcode = opcode.to_uppercase();
+        if let Some(mask) = info["flags-mask"].as_u64() {
+            masks.insert(opcode.clone(), mask as u16);
+        }
+        for (reg, info) in info["reg"].as_object().into_iter().flatten() {
+            if let Some(mask) = info["flags-mask"].as_u64() {
+                masks.insert(format!("{}.{}", opcode, reg), mask as u16);
+            }
+        }
+    }
+    masks
+}
+
+fn get_regs(cpu: &CPU) -> [u16; 14] {
+    [
+        cpu.ax.get_x(), cpu.bx.get_x(), cpu.cx.get_x(), cpu.dx.get_x(),
+        cpu.sp, cpu.bp, cpu.si, cpu.di,
+        cpu.cs, cpu.ds, cpu.es, cpu.ss, cpu.ip, cpu.flags.get_flags(),
+    ]
+}
+
+fn set_regs(cpu: &mut CPU, regs: &[u16; 14]) {
+    cpu.ax.set_x(regs[0]);
+    cpu.bx.set_x(regs[1]);
+    cpu.cx.set_x(regs[2]);
+    cpu.dx.set_x(regs[3]);
+    cpu.sp = regs[4];
+    cpu.bp = regs[5];
+    cpu.si = regs[6];
+    cpu.di = regs[7];
+    cpu.cs = regs[8];
+    cpu.ds = regs[9];
+    cpu.es = regs[10];
+    cpu.ss = regs[11];
+    cpu.ip = regs[12];
+    cpu.flags.set_flags(regs[13]);
+}
+
+// Los registros que trae el estado, sobre los de base
+fn read_regs(state: &Value, base: [u16; 14]) -> [u16; 14] {
+    let mut regs = base;
+    for (reg, name) in REGS.iter().enumerate() {
+        if let Some(val) = state["regs"][name].as_u64() {
+            regs[reg] = val as u16;
+        }
+    }
+    regs
+}
+
+fn read_ram(state: &Value) -> Vec<(usize, u8)> {
+    state["ram"].as_array().into_iter().flatten()
+        .filter_map(|entry| Some((entry[0].as_u64()? as usize % 0x100000, entry[1].as_u64()? as u8)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    // Lo que no se puede probar en un PC: escribir en la ROM o empezar con la cola llena
+    Skip,
+}
+
+// Ejecuta una prueba sobre bus, que queda como estaba
+pub fn run_single_step(bus: &mut Bus, test: &Value, flags_mask: u16) -> Outcome {
+    let initial = read_regs(&test["initial"], [0; 14]);
+    let expected = read_regs(&test["final"], initial);
+    let ram = read_ram(&test["initial"]);
+    let expected_ram = read_ram(&test["final"]);
+
+    if test["initial"]["queue"].as_array().is_some_and(|queue| !queue.is_empty()) {
+        return Outcome::Skip;
+    }
+    let before: HashMap<usize, u8> = ram.iter().copied().collect();
+    if expected_ram.iter().any(|(address, val)| *address >= 0xC0000 && before.get(address) != Some(val)) {
+        return Outcome::Skip;
+    }
+
+    let mut cpu = CPU::new();
+    set_regs(&mut cpu, &initial);
+    for (address, val) in &ram {
+        bus.memory[*address] = *val;
+    }
+
+    bus.trace.take();
+    bus.trace.enabled = true;
+    cpu.fetch_decode_execute(bus);
+    // Un REP entero, como en las pruebas
+    for _ in 0..0x20000 {
+        if cpu.to_decode {
+            break;
+        }
+        cpu.fetch_decode_execute(bus);
+    }
+    // Las excepciones (division, INT n) terminan en la rutina
+    if cpu.sw_int {
+        cpu.trap = false;
+        cpu.handle_interrupts(bus);
+    }
+    bus.trace.enabled = false;
+    let written: Vec<usize> = bus.trace.take().iter().filter_map(|access| match access {
+        BusAccess::MemWrite(address, _) => Some(*address as usize),
+        _ => None,
+    }).collect();
+
+    let mut errors = Vec::new();
+    let actual = get_regs(&cpu);
+    for (reg, name) in REGS.iter().enumerate() {
+        let mask = if reg == FLAGS {FLAGS_MASK & flags_mask} else {0xFFFF};
+        if (actual[reg] ^ expected[reg]) & mask != 0 {
+            errors.push(format!("{} expected {:04X}, got {:04X}", name, expected[reg], actual[reg]));
+        }
+    }
+    let checked: HashMap<usize, u8> = expected_ram.iter().copied().collect();
+    for (address, val) in &expected_ram {
+        if bus.memory[*address] != *val {
+            errors.push(format!("[{:05X}] expected {:02X}, got {:02X}", address, val, bus.memory[*address]));
+        }
+    }
+    for address in &written {
+        if !checked.contains_key(address) {
+            errors.push(format!("[{:05X}] written with {:02X}", address, bus.memory[*address]));
+        }
+    }
+
+    // La memoria vuelve a cero para la siguiente
+    for address in ram.iter().map(|(address, _)| *address).chain(written) {
+        if address < 0xC0000 || before.contains_key(&address) {
+            bus.memory[address] = 0;
+        }
+    }
+
+    if errors.is_empty() {
+        Outcome::Pass
+    } else {
+        errors.dedup();
+        Outcome::Fail(errors.join(", "))
+    }
+}
+
+pub struct OpcodeReport {
+    pub opcode: String,
+    pub flags_mask: u16,
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    // El nombre de la primera que falla y lo que ha ido mal
+    pub first_failure: Option<(String, String)>,
+}
+
+pub fn run_single_step_file(bus: &mut Bus, opcode: &str, tests: &Value, flags_mask: u16) -> OpcodeReport {
+    let mut report = OpcodeReport {
+        opcode: opcode.to_string(),
+        flags_mask,
+        passed: 0,
+        failed: 0,
+        skipped: 0,
+        first_failure: None,
+    };
+
+    for test in tests.as_array().into_iter().flatten() {
+        match run_single_step(bus, test, flags_mask) {
+            Outcome::Pass => report.passed += 1,
+            Outcome::Skip => report.skipped += 1,
+            Outcome::Fail(error) => {
+                report.failed += 1;
+                if report.first_failure.is_none() {
+                    let name = test["name"].as_str().unwrap_or("").to_string();
+                    report.first_failure = Some((name, error));
+                }
+            },
+        }
+    }
+    report
+}
+
+// Todos los opcodes de la carpeta, en orden
+pub fn run_single_step_dir(dir: &Path) -> Vec<OpcodeReport> {
+    let masks = flags_masks(dir);
+    let mut files: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).unwrap_or_else(|err| panic!("{}: {}", dir.display(), err)) {
+        let path = entry.unwrap().path();
+        if let Some(opcode) = opcode_name(&path) {
+            files.insert(opcode, path);
+        }
+    }
+
+    let mut bus = Bus::new();
+    files.iter().map(|(opcode, path)| {
+        // Un grupo sin mascara propia usa la del opcode
+        let group = opcode.split('.').next().unwrap();
+        let mask = masks.get(opcode).or_else(|| masks.get(group)).copied().unwrap_or(0xFFFF);
+        run_single_step_file(&mut bus, opcode, &read_json(path), mask)
+    }).collect()
+}
+
+pub fn single_step_report(reports: &[OpcodeReport]) -> String {
+    let mut text = String::new();
+    for report in reports {
+        let status = if report.failed == 0 {"pass"} else {"FAIL"};
+        text += &format!("{:<6} {}  {:>6} passed {:>6} failed {:>6} skipped  flags-mask {:04X}\n",
+            report.opcode, status, report.passed, report.failed, report.skipped, report.flags_mask);
+        if let Some((name, error)) = &report.first_failure {
+            text += &format!("       {}: {}\n", name, error);
+        }
+    }
+    let passed = reports.iter().filter(|report| report.failed == 0).count();
+    text += &format!("{} of {} opcodes pass\n", passed, reports.len());
+    text
+}
+
+pub fn test_single_step_harness() {
+    // Una carpeta por proceso, por si se lanzan varias pruebas a la vez
+    let dir = std::env::temp_dir().join(format!("ibm_5150_single_step_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // INC AX: el flag de acarreo no cambia
+    let inc = json!([
+        {
+            "name": "inc ax",
+            "bytes": [0x40],
+            "initial": {"regs": {"ax": 0x000F, "bx": 0, "cx": 0, "dx": 0, "cs": 0x1000, "ss": 0, "ds": 0, "es": 0,
+                                 "sp": 0x100, "bp": 0, "si": 0, "di": 0, "ip": 0x10, "flags": 0xF003},
+                        "ram": [[0x10010, 0x40]], "queue": []},
+            "final": {"regs": {"ax": 0x0010, "ip": 0x11, "flags": 0xF013}, "ram": [[0x10010, 0x40]], "queue": []}
+        },
+        {
+            "name": "inc ax (mal)",
+            "bytes": [0x40],
+            "initial": {"regs": {"ax": 0x0001, "bx": 0, "cx": 0, "dx": 0, "cs": 0x1000, "ss": 0, "ds": 0, "es": 0,
+                                 "sp": 0x100, "bp": 0, "si": 0, "di": 0, "ip": 0x10, "flags": 0xF002},
+                        "ram": [[0x10010, 0x40]], "queue": []},
+            "final": {"regs": {"ax": 0x0003, "ip": 0x11}, "ram": [[0x10010, 0x40]], "queue": []}
+        }
+    ]);
+    std::fs::write(dir.join("40.json"), inc.to_string()).unwrap();
+
+    // MOV [BX], AL comprimido. La segunda escribe en la ROM
+    let mov = json!([
+        {
+            "name": "mov [bx], al",
+            "bytes": [0x88, 0x07],
+            "initial": {"regs": {"ax": 0x1234, "bx": 0x0200, "cx": 0, "dx": 0, "cs": 0x1000, "ss": 0, "ds": 0x0050, "es": 0,
+                                 "sp": 0x100, "bp": 0, "si": 0, "di": 0, "ip": 0, "flags": 0xF002},
+                        "ram": [[0x10000, 0x88], [0x10001, 0x07], [0x00700, 0xAA]], "queue": []},
+            "final": {"regs": {"ip": 2}, "ram": [[0x10000, 0x88], [0x10001, 0x07], [0x00700, 0x34]], "queue": []}
+        },
+        {
+            "name": "mov [bx], al (rom)",
+            "bytes": [0x88, 0x07],
+            "initial": {"regs": {"ax": 0x1234, "bx": 0x0000, "cx": 0, "dx": 0, "cs": 0x1000, "ss": 0, "ds": 0xF000, "es": 0,
+                                 "sp": 0x100, "bp": 0, "si": 0, "di": 0, "ip": 0, "flags": 0xF002},
+                        "ram": [[0x10000, 0x88], [0x10001, 0x07], [0xF0000, 0xAA]], "queue": []},
+            "final": {"regs": {"ip": 2}, "ram": [[0x10000, 0x88], [0x10001, 0x07], [0xF0000, 0x34]], "queue": []}
+        }
+    ]);
+    let mut gz = GzEncoder::new(std::fs::File::create(dir.join("88.json.gz")).unwrap(), flate2::Compression::default());
+    gz.write_all(mov.to_string().as_bytes()).unwrap();
+    gz.finish().unwrap();
+
+    // Una mascara cualquiera, para ver que se lee
+    let metadata = json!({"opcodes": {"40": {"status": "normal", "flags-mask": 0xFFFE}}});
+    std::fs::write(dir.join("8088.json"), metadata.to_string()).unwrap();
+    std::fs::write(dir.join("README.md"), "").unwrap();
+
+    let reports = run_single_step_dir(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(reports.len(), 2);
+    assert_eq!((reports[0].opcode.as_str(), reports[0].flags_mask), ("40", 0xFFFE));
+    assert_eq!((reports[0].passed, reports[0].failed, reports[0].skipped), (1, 1, 0));
+    let (name, error) = reports[0].first_failure.clone().unwrap();
+    assert_eq!(name, "inc ax (mal)");
+    assert_eq!(error, "ax expected 0003, got 0002");
+
+    assert_eq!((reports[1].opcode.as_str(), reports[1].flags_mask), ("88", 0xFFFF));
+    assert_eq!((reports[1].passed, reports[1].failed, reports[1].skipped), (1, 0, 1));
+
+    let report = single_step_report(&reports);
+    assert!(report.contains("40     FAIL       1 passed      1 failed      0 skipped  flags-mask FFFE"), "{}", report);
+    assert!(report.contains("       inc ax (mal): ax expected 0003, got 0002"), "{}", report);
+    assert!(report.ends_with("1 of 2 opcodes pass\n"), "{}", report);
+
+    // Una escritura que la prueba no espera
+    let mut bus = Bus::new();
+    let mut test = mov[0].clone();
+    test["final"]["ram"] = json!([[0x10000, 0x88], [0x10001, 0x07]]);
+    test["initial"]["ram"] = json!([[0x10000, 0x88], [0x10001, 0x07]]);
+    assert_eq!(run_single_step(&mut bus, &test, 0xFFFF), Outcome::Fail(String::from("[00700] written with 34")));
+    assert!(bus.memory.iter().all(|byte| *byte == 0));
+}
+
+// Toda la coleccion, si esta en SINGLE_STEP_TESTS
+pub fn test_single_step_corpus() {
+    let Ok(dir) = std::env::var("SINGLE_STEP_TESTS") else {
+        println!("SINGLE_STEP_TESTS is not set");
+        return;
+    };
+    let reports = run_single_step_dir(Path::new(&dir));
+    let report = single_step_report(&reports);
+    println!("{}", report);
+    assert!(reports.iter().all(|report| report.failed == 0), "some opcodes fail");
+}
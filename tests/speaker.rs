@@ -0,0 +1,45 @@
+use ibm_5150::{Headless, MachineConfig};
+use ibm_5150::hardware::peripheral::speaker::SAMPLE_RATE;
+
+pub fn test_post_beep() {
+    let mut headless = Headless::with_config(MachineConfig::ibm_5150_1981()).unwrap();
+    headless.start_audio();
+    assert!(headless.run_until_text("Ok", 2000));
+
+    let samples = headless.take_samples();
+    let first = samples.iter().position(|&s| s > 0).expect("no beep");
+    let last = samples.iter().rposition(|&s| s > 0).unwrap();
+    let seconds = (last - first) as f64 / SAMPLE_RATE as f64;
+
+    let mut rises = 0;
+    for pair in samples.windows(2) {
+        if pair[0] < 4000 && pair[1] >= 4000 {
+            rises += 1;
+        }
+    }
+
+    // La BIOS pita con el canal 2 a 1331 (~896 Hz)
+    let freq = rises as f64 / seconds;
+    assert!((880.0..910.0).contains(&freq), "{} Hz", freq);
+    assert!((0.1..0.5).contains(&seconds), "{} s", seconds);
+}
+
+pub fn test_wav_output() {
+    let mut headless = Headless::with_config(MachineConfig::ibm_5150_1981()).unwrap();
+    headless.start_audio();
+    headless.run_frames(10);
+
+    let path = std::env::temp_dir().join(format!("ibm_5150_speaker_test_{}.wav", std::process::id()));
+    headless.save_wav(&path).unwrap();
+    let wav = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]), SAMPLE_RATE);
+
+    // 10 frames a 50 fps son 0.2 s de audio
+    let data_len = u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]) as usize;
+    assert_eq!(data_len, wav.len() - 44);
+    assert!((data_len / 2).abs_diff(SAMPLE_RATE as usize / 5) < 100);
+}
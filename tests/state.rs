@@ -0,0 +1,62 @@
+use ibm_5150::{Headless, MachineConfig, StateError};
+
+fn boot() -> Headless {
+    Headless::with_config(MachineConfig::ibm_5150_1981()).unwrap()
+}
+
+pub fn test_snapshot_roundtrip() {
+    let mut original = boot();
+    assert!(original.run_until_text("Ok", 2000));
+
+    let snapshot = original.sys.snapshot();
+
+    let mut restored = boot();
+    restored.sys.restore(&snapshot).unwrap();
+    assert_eq!(restored.sys.snapshot(), snapshot);
+    assert_eq!(restored.screen_text(), original.screen_text());
+
+    // PRINT 44-2 en las dos maquinas
+    for scancode in [0x19, 0x13, 0x17, 0x31, 0x14, 0x39, 0x05, 0x05, 0x0C, 0x03, 0x1C] {
+        original.press_key(scancode);
+        restored.press_key(scancode);
+    }
+    original.run_frames(10);
+    restored.run_frames(10);
+
+    assert!(restored.screen_contains(" 42"));
+    assert!(original.sys.snapshot() == restored.sys.snapshot());
+}
+
+pub fn test_snapshot_mid_rep() {
+    let mut original = boot();
+    let mut cycles = 0;
+
+    while original.sys.cpu.to_decode {
+        original.sys.step(&mut cycles);
+    }
+
+    let snapshot = original.sys.snapshot();
+    let mut restored = boot();
+    restored.sys.restore(&snapshot).unwrap();
+
+    for _ in 0..10_000 {
+        original.sys.step(&mut cycles);
+        restored.sys.step(&mut cycles);
+    }
+
+    assert!(original.sys.snapshot() == restored.sys.snapshot());
+}
+
+pub fn test_snapshot_errors() {
+    let mut headless = boot();
+    let snapshot = headless.sys.snapshot();
+
+    assert!(matches!(headless.sys.restore(b"no es un estado"), Err(StateError::BadMagic)));
+    assert!(matches!(headless.sys.restore(&snapshot[..snapshot.len() - 1]), Err(StateError::Truncated)));
+
+    let mut wrong_version = snapshot.clone();
+    wrong_version[8] = 0xFF;
+    assert!(matches!(headless.sys.restore(&wrong_version), Err(StateError::Version(_))));
+
+    assert!(headless.sys.snapshot() == snapshot);
+}
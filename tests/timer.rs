@@ -0,0 +1,118 @@
+use ibm_5150::hardware::peripheral::Peripheral;
+use ibm_5150::hardware::peripheral::pic_8259::PIC8259;
+use ibm_5150::hardware::peripheral::ppi_8255::PPI8255;
+use ibm_5150::hardware::peripheral::speaker::Speaker;
+use ibm_5150::hardware::peripheral::timer_8253::TIM8253;
+
+struct Pit {
+    pit: TIM8253,
+    pic: PIC8259,
+    ppi: PPI8255,
+    speaker: Speaker,
+}
+
+impl Pit {
+    fn new() -> Self {
+        Pit { pit: TIM8253::new(), pic: PIC8259::new(), ppi: PPI8255::new(), speaker: Speaker::new() }
+    }
+
+    // Un ciclo de CLK del PIT son 4 de CPU
+    fn clock(&mut self, clocks: u32) {
+        for _ in 0..clocks {
+            self.pit.cycles += 4;
+            self.pit.update(&mut self.pic, &mut self.ppi, &mut self.speaker);
+        }
+    }
+
+    fn gate2(&mut self, level: bool) {
+        self.ppi.port_b = level as u8;
+        self.clock(1);
+    }
+
+    // Salida del canal 2 en cada ciclo
+    fn trace(&mut self, clocks: u32) -> Vec<bool> {
+        (0..clocks).map(|_| {
+            self.clock(1);
+            self.pit.out(2)
+        }).collect()
+    }
+}
+
+pub fn test_pit_mode0_bcd() {
+    let mut t = Pit::new();
+
+    // Canal 0, LSB+MSB, modo 0, BCD, cuenta 12
+    t.pit.port_out(0x31, 0x43);
+    t.pit.port_out(0x12, 0x40);
+    t.pit.port_out(0x00, 0x40);
+    assert!(!t.pit.out(0));
+
+    t.clock(4);
+    t.pit.port_out(0x00, 0x43);
+    assert_eq!(t.pit.port_in(0x40), 0x09);
+    assert_eq!(t.pit.port_in(0x40), 0x00);
+
+    t.clock(8);
+    assert!(!t.pit.out(0));
+    t.clock(1);
+    assert!(t.pit.out(0));
+    assert_eq!(t.pic.irr & 0x01, 0x01);
+}
+
+pub fn test_pit_gate_modes() {
+    let mut t = Pit::new();
+
+    // Modo 1 en el canal 2: no empieza hasta el flanco de GATE
+    t.gate2(false);
+    t.pit.port_out(0xB2, 0x43);
+    t.pit.port_out(3, 0x42);
+    t.pit.port_out(0, 0x42);
+    assert_eq!(t.trace(3), vec![true, true, true]);
+    t.gate2(true);
+    assert_eq!(t.trace(4), vec![false, false, true, true]);
+
+    // Modo 5: un pulso bajo de un ciclo tras el disparo
+    t.gate2(false);
+    t.pit.port_out(0xBA, 0x43);
+    t.pit.port_out(2, 0x42);
+    t.pit.port_out(0, 0x42);
+    t.gate2(true);
+    assert_eq!(t.trace(4), vec![true, false, true, true]);
+
+    // Modo 3 con la puerta abierta: cuadrada de 3 ciclos altos y 2 bajos
+    t.pit.port_out(0xB6, 0x43);
+    t.pit.port_out(5, 0x42);
+    t.pit.port_out(0, 0x42);
+    t.clock(1);
+    assert_eq!(t.trace(10), vec![true, true, false, false, true, true, true, false, false, true]);
+    assert_eq!(t.ppi.read_pc() & 0x20, 0x20);
+
+    // Al cerrar la puerta la salida se queda alta
+    t.gate2(false);
+    assert_eq!(t.trace(5), vec![true; 5]);
+}
+
+pub fn test_pit_mode4_readback() {
+    let mut t = Pit::new();
+
+    // Canal 1, solo LSB, modo 4
+    t.pit.port_out(0x58, 0x43);
+    t.pit.port_out(3, 0x41);
+
+    // Read-back del estado: OUT alto y NULL COUNT hasta que se carga
+    t.pit.port_out(0xE4, 0x43);
+    assert_eq!(t.pit.port_in(0x41), 0xD8);
+
+    t.clock(1);
+    let mut outs = Vec::new();
+    for _ in 0..5 {
+        t.clock(1);
+        outs.push(t.pit.out(1));
+    }
+    assert_eq!(outs, vec![true, true, false, true, true]);
+
+    // Read-back de estado y cuenta: primero el estado
+    t.pit.port_out(0xC4, 0x43);
+    assert_eq!(t.pit.port_in(0x41), 0x98);
+    assert_eq!(t.pit.port_in(0x41), 0xFE);
+}
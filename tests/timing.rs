@@ -0,0 +1,68 @@
+use ibm_5150::{Headless, MachineConfig};
+use ibm_5150::hardware::{bus::Bus, cpu_8088::{CPU, biu::BIU}};
+
+fn cycles(accurate: bool, code: &[u8]) -> (Vec<u32>, CPU) {
+    let mut bus = Bus::new();
+    let mut cpu = CPU::new();
+    if accurate {
+        cpu.biu = Some(BIU::new());
+    }
+    cpu.cs = 0x1000;
+    cpu.ip = 0;
+
+    for (i, byte) in code.iter().enumerate() {
+        bus.write_8(0x1000, i as u16, *byte);
+    }
+    bus.write_8(0x1000, code.len() as u16, 0xF4);
+    // Las escrituras del programa no son del 8088
+    bus.take_activity();
+
+    let mut cycles = Vec::new();
+    loop {
+        let (c, _) = cpu.fetch_decode_execute(&mut bus);
+        if cpu.halted {
+            break;
+        }
+        cycles.push(c);
+    }
+    (cycles, cpu)
+}
+
+pub fn test_prefetch_queue() {
+    let mut code = vec![0x90; 6];
+    code.extend_from_slice(&[0xF6, 0xE1]);      // MUL CL
+    code.extend_from_slice(&[0x90; 4]);
+    code.extend_from_slice(&[0xEB, 0x01, 0x90]); // JMP +1
+    code.push(0x90);
+
+    // Sin el modelo cada instruccion cuesta lo de las tablas
+    let (plain, cpu) = cycles(false, &code);
+    assert!(cpu.biu.is_none());
+    assert_eq!(plain[..6], [3; 6]);
+
+    let (accurate, cpu) = cycles(true, &code);
+    // Con la cola vacia los NOP van al ritmo del bus: 4 ciclos por byte
+    assert_eq!(accurate[..6], [7, 4, 4, 4, 4, 4]);
+    // El MUL espera a sus 2 bytes y mientras multiplica se llena la cola
+    assert_eq!(accurate[6], 5 + plain[6]);
+    assert_eq!(accurate[7..11], [3; 4]);
+    // El salto vacia la cola y el NOP de destino tiene que esperar
+    assert_eq!(accurate[11], plain[11]);
+    assert_eq!(accurate[12], 7);
+
+    let biu = cpu.biu.unwrap();
+    // Contando el HLT del final
+    assert_eq!(biu.stall_cycles, 4 + 5 + 5 + 4 + 1);
+    assert!(biu.bus_cycles >= code.len() as u64 - 1);
+}
+
+pub fn test_accurate_boot() {
+    // La POST comprueba el PIT, el DMA y el refresco con bucles de espera
+    let mut config = MachineConfig::ibm_5150_1981();
+    config.accurate_timing = true;
+    let mut headless = Headless::with_config(config).unwrap();
+
+    assert!(headless.run_until_text("Version C1.00", 2500));
+    let biu = headless.sys.cpu.biu.as_ref().unwrap();
+    assert!(biu.stall_cycles > 0);
+}
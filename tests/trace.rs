@@ -0,0 +1,106 @@
+use ibm_5150::System;
+use ibm_5150::util::trace::*;
+
+// Ejecuta el codigo en 1000:0000 grabando la traza y la devuelve
+fn trace(code: &[u8], setup: impl Fn(&mut Tracer), name: &str) -> Vec<TraceRecord> {
+    let mut sys = System::new();
+    for (i, byte) in code.iter().enumerate() {
+        sys.bus.write_8(0x1000, i as u16, *byte);
+    }
+    sys.cpu.cs = 0x1000;
+    sys.cpu.ip = 0;
+    sys.cpu.ds = 0;
+    sys.cpu.es = 0x3000;
+    sys.cpu.ss = 0x2000;
+    sys.cpu.sp = 0x0100;
+
+    let path = std::env::temp_dir().join(format!("{}_{}.trace", name, std::process::id()));
+    let mut tracer = Tracer::create(&path).unwrap();
+    setup(&mut tracer);
+    sys.tracer = Some(tracer);
+
+    let mut cycles = 0;
+    while (sys.cpu.ip as usize) < code.len() {
+        sys.step(&mut cycles);
+    }
+    let count = sys.tracer.take().unwrap().finish().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let records: Vec<TraceRecord> = TraceReader::new(&data).unwrap().collect::<Result<_, _>>().unwrap();
+    assert_eq!(records.len() as u64, count);
+    records
+}
+
+const CODE: [u8; 15] = [
+    0xB8, 0x34, 0x12,       // MOV AX, 1234
+    0xA3, 0x00, 0x02,       // MOV [0200], AX
+    0xE6, 0x81,             // OUT 81, AL
+    0xB9, 0x02, 0x00,       // MOV CX, 0002
+    0xF3, 0xAA,             // REP STOSB
+    0x90,                   // NOP
+    0x90,
+];
+
+pub fn test_trace_records() {
+    let records = trace(&CODE, |_| {}, "ibm_5150_trace_records");
+
+    // MOV, MOV, OUT, MOV, REP STOSB tres veces (la ultima ve CX a 0) y los dos NOP
+    assert_eq!(records.len(), 9);
+    assert!(records.windows(2).all(|w| w[0].cycle <= w[1].cycle));
+
+    // Los registros son los de antes de ejecutar
+    assert_eq!((records[0].regs[0], records[1].regs[0]), (0x0000, 0x1234));
+    assert_eq!((records[1].cs(), records[1].ip()), (0x1000, 0x0003));
+    assert_eq!(records[1].bytes, [0xA3, 0x00, 0x02]);
+    assert_eq!(records[1].accesses, [BusAccess::MemWrite(0x00200, 0x34), BusAccess::MemWrite(0x00201, 0x12)]);
+    assert_eq!(records[2].accesses, [BusAccess::IoWrite(0x81, 0x34)]);
+
+    // Las repeticiones no vuelven a leer la instruccion
+    assert_eq!(records[4].bytes, [0xF3, 0xAA]);
+    assert_eq!(records[4].accesses, [BusAccess::MemWrite(0x30000, 0x34)]);
+    assert!(records[5].bytes.is_empty());
+    assert_eq!(records[5].regs[2], 0x0001);
+
+    let line = records[1].to_string();
+    assert!(line.ends_with("SS=2000 FLAGS=0200 [W 00200=34] [W 00201=12]"));
+    assert!(line.trim_start().starts_with(&format!("{} 1000:0003  A30002       MOV [0200], AX ", records[1].cycle)));
+}
+
+pub fn test_trace_filters() {
+    // Solo las instrucciones en 10003-10007
+    let records = trace(&CODE, |tracer| tracer.ranges.push((0x10003, 0x10007)), "ibm_5150_trace_range");
+    let ips: Vec<u16> = records.iter().map(|record| record.ip()).collect();
+    assert_eq!(ips, [0x0003, 0x0006]);
+
+    // Ventana de ciclos: desde el MOV CX hasta antes de la ultima vuelta del REP
+    let all = trace(&CODE, |_| {}, "ibm_5150_trace_all");
+    let window = all[3].cycle..all[6].cycle;
+    let records = trace(&CODE, |tracer| tracer.cycles = window.clone(), "ibm_5150_trace_window");
+    assert_eq!(records, all[3..6]);
+
+    // Texto, una linea por instruccion
+    let path = std::env::temp_dir().join(format!("ibm_5150_trace_text_{}.trace", std::process::id()));
+    let mut tracer = Tracer::create(&path).unwrap();
+    for record in &all {
+        tracer.record(record);
+    }
+    tracer.finish().unwrap();
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut text = Vec::new();
+    assert_eq!(export_text(&data, &mut text).unwrap(), 9);
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(text.lines().nth(4).unwrap(), all[4].to_string());
+
+    assert!(matches!(TraceReader::new(b"IBM5150S\x0B\x00"), Err(TraceError::BadMagic)));
+    assert!(matches!(TraceReader::new(&data[..data.len() - 1]).unwrap().last(), Some(Err(TraceError::Truncated))));
+
+    // Un acceso de tipo 9 en el primero de dos registros: despues del error no sigue
+    let record = [0x00, 0x00, 0x00, 0x00, 0x01, 0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+    let data = [&data[..10], &record, &record].concat();
+    let mut reader = TraceReader::new(&data).unwrap();
+    assert!(matches!(reader.next(), Some(Err(TraceError::Invalid("access")))));
+    assert!(reader.next().is_none());
+}
@@ -0,0 +1,133 @@
+use ibm_5150::System;
+use ibm_5150::util::trace::*;
+use ibm_5150::util::trace_diff::*;
+
+use crate::common::*;
+
+const PROGRAM: [u8; 13] = [
+    0xB8, 0x34, 0x12,       // MOV AX, 1234
+    0xA3, 0x00, 0x02,       // MOV [0200], AX
+    0xE6, 0x81,             // OUT 81, AL
+    0x40,                   // INC AX
+    0x01, 0xC0,             // ADD AX, AX
+    0x90,                   // NOP
+    0x90,
+];
+
+// Con DS a 0 las direcciones lineales son los desplazamientos
+fn machine() -> System {
+    let mut sys = load_system(&PROGRAM);
+    sys.cpu.ds = 0;
+    sys
+}
+
+// La traza en texto de ejecutar PROGRAM
+fn reference() -> String {
+    let mut sys = machine();
+    let path = std::env::temp_dir().join(format!("ibm_5150_trace_diff_{}.trace", std::process::id()));
+    sys.tracer = Some(Tracer::create(&path).unwrap());
+    let mut cycles = 0;
+    while (sys.cpu.ip as usize) < PROGRAM.len() {
+        sys.step(&mut cycles);
+    }
+    sys.tracer.take().unwrap().finish().unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // La binaria tambien vale como referencia
+    let expected = load_reference(&data).unwrap();
+    assert_eq!(Comparator::new().run(&mut machine(), &expected).unwrap(), 7);
+
+    let mut text = Vec::new();
+    export_text(&data, &mut text).unwrap();
+    String::from_utf8(text).unwrap()
+}
+
+pub fn test_trace_diff_match() {
+    let text = reference();
+    let expected = load_reference(text.as_bytes()).unwrap();
+    assert_eq!(expected.len(), 7);
+    assert_eq!((expected[1].cs, expected[1].ip), (0x1000, 0x0003));
+    assert_eq!(expected[1].regs[0], Some(0x1234));
+    assert_eq!(expected[2].accesses, Some(vec![BusAccess::IoWrite(0x81, 0x34)]));
+    assert_eq!(Comparator::new().run(&mut machine(), &expected).unwrap(), 7);
+
+    // Formato minimo escrito a mano, con comentarios y FL
+    let text = "# referencia\n\
+                1000:0000 AX=0000\n\
+                \n\
+                ; solo CS:IP\n\
+                1000:0003 AX=1234 [W 00200=34] [W 00201=12]\n\
+                1000:0006 FL=F202 [O 0081=34]\n";
+    let expected = parse_reference(text).unwrap();
+    assert_eq!(expected.len(), 3);
+    assert_eq!(expected[1].line, 5);
+    assert_eq!(expected[2].regs[13], Some(0xF202));
+    assert_eq!(Comparator::new().run(&mut machine(), &expected).unwrap(), 3);
+
+    // Sin corchetes, como en los emuladores que no sacan el bus, los accesos no se comparan
+    let expected = parse_reference("1000:0000 AX=0000\n1000:0003 AX=1234\n1000:0006 AX=1234\n").unwrap();
+    assert_eq!(expected[1].accesses, None);
+    assert_eq!(Comparator::new().run(&mut machine(), &expected).unwrap(), 3);
+
+    match parse_reference("1000:0000 AX=12G4") {
+        Err(TraceError::Parse(1, _)) => (),
+        other => panic!("{:?}", other.map(|expected| expected.len())),
+    }
+    assert!(parse_reference("AX=1234").is_err());
+}
+
+pub fn test_trace_diff_divergence() {
+    let lines: Vec<String> = reference().lines().map(String::from).collect();
+
+    // Un registro distinto despues del INC
+    let mut text = lines.clone();
+    text[4] = text[4].replace("AX=1235", "AX=1236");
+    let divergence = Comparator::new().run(&mut machine(), &parse_reference(&text.join("\n")).unwrap()).unwrap_err();
+    assert_eq!(divergence.step, 4);
+    assert_eq!(divergence.line, 5);
+    assert_eq!(divergence.mismatch, Mismatch::Register("AX", 0x1236, 0x1235));
+    assert_eq!(divergence.previous.as_ref().unwrap().bytes, [0x40]);
+    let report = divergence.to_string();
+    assert!(report.starts_with("divergence after 4 steps (reference line 5): AX expected 1236, got 1235"), "{}", report);
+    assert!(report.contains("previous  1000:0008  40           INC AX"), "{}", report);
+    assert!(report.contains("current   1000:0009  01C0         ADD AX, AX"), "{}", report);
+
+    // Un flag: la suma no deja el acarreo
+    let mut text = lines.clone();
+    let after_add = parse_reference(&text[5]).unwrap()[0].regs[13].unwrap();
+    text[5] = text[5].replace(&format!("FLAGS={:04X}", after_add), &format!("FLAGS={:04X}", after_add | 0x0001));
+    let divergence = Comparator::new().run(&mut machine(), &parse_reference(&text.join("\n")).unwrap()).unwrap_err();
+    assert_eq!(divergence.step, 5);
+    assert_eq!(divergence.mismatch, Mismatch::Flags(after_add | 0x0001, after_add));
+    assert!(divergence.to_string().contains("(CF=0)"));
+
+    // Los bits reservados no cuentan
+    let mut text = lines.clone();
+    text[5] = text[5].replace(&format!("FLAGS={:04X}", after_add), &format!("FLAGS={:04X}", after_add ^ 0x0008));
+    assert_eq!(Comparator::new().run(&mut machine(), &parse_reference(&text.join("\n")).unwrap()).unwrap(), 7);
+
+    // Una escritura en memoria distinta
+    let mut text = lines.clone();
+    text[1] = text[1].replace("[W 00201=12]", "[W 00201=13]");
+    let divergence = Comparator::new().run(&mut machine(), &parse_reference(&text.join("\n")).unwrap()).unwrap_err();
+    assert_eq!(divergence.step, 1);
+    assert_eq!(divergence.mismatch, Mismatch::Accesses(
+        vec![BusAccess::MemWrite(0x00200, 0x34), BusAccess::MemWrite(0x00201, 0x13)],
+        vec![BusAccess::MemWrite(0x00200, 0x34), BusAccess::MemWrite(0x00201, 0x12)],
+    ));
+    assert!(divergence.to_string().contains("current   1000:0003  A30002       MOV [0200], AX"), "{}", divergence);
+
+    // Sin comparar el bus pasa
+    let mut comparator = Comparator::new();
+    comparator.accesses = false;
+    assert_eq!(comparator.run(&mut machine(), &parse_reference(&text.join("\n")).unwrap()).unwrap(), 7);
+
+    // Un salto a otro sitio
+    let mut text = lines.clone();
+    text[3] = text[3].replace("1000:0008", "1000:0009");
+    let divergence = Comparator::new().run(&mut machine(), &parse_reference(&text.join("\n")).unwrap()).unwrap_err();
+    assert_eq!((divergence.step, divergence.mismatch.clone()), (3, Mismatch::Address(0x1000, 0x0009)));
+    assert_eq!(divergence.previous.unwrap().accesses, [BusAccess::IoWrite(0x81, 0x34)]);
+}